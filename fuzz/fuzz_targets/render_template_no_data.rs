@@ -1,8 +1,23 @@
 #![no_main]
+use std::time::Duration;
+
+use handlebars::RenderLimits;
 use libfuzzer_sys::fuzz_target;
 
 fuzz_target!(|data: &str| {
-    let tpl = handlebars::Handlebars::new();
+    let mut tpl = handlebars::Handlebars::new();
+    tpl.set_render_limits(RenderLimits {
+        max_partial_depth: Some(32),
+        max_output_bytes: Some(1 << 20),
+        max_iterations: Some(10_000),
+        max_helper_calls: Some(10_000),
+        timeout: Some(Duration::from_secs(1)),
+    });
 
     let _ = tpl.render_template(&data, &Vec::<u32>::new());
+
+    // also render the input as a partial, which may include itself
+    if tpl.register_partial("fuzz", data).is_ok() {
+        let _ = tpl.render_template("{{> fuzz}}", &Vec::<u32>::new());
+    }
 });
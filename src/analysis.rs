@@ -0,0 +1,459 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::value::Value as Json;
+
+use crate::json::path::Path;
+use crate::partial::{PARTIAL_BLOCK, PARTIAL_FALLBACK};
+use crate::registry::Registry;
+use crate::template::{DecoratorTemplate, HelperTemplate, Parameter, Subexpression, Template};
+use crate::visitor::{self, Visitor};
+
+/// Partials, helpers, decorators and data paths referenced by a template
+///
+/// They are collected from the template source, without rendering it. Names
+/// computed at render time, like dynamic partials `{{> (lookup . "name")}}`,
+/// can't be known and are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dependencies {
+    /// partials included with `{{> name}}`
+    pub partials: BTreeSet<String>,
+    /// partials included with `{{#> name}}...{{/name}}`, the block is
+    /// rendered instead when the partial is missing
+    pub partial_blocks: BTreeSet<String>,
+    /// partials defined by the template with `{{#*inline "name"}}`
+    pub inline_partials: BTreeSet<String>,
+    /// helpers called with params, as block or in subexpressions
+    pub helpers: BTreeSet<String>,
+    pub decorators: BTreeSet<String>,
+    /// data paths as written, relative to the block they are used in
+    ///
+    /// A name-only expression like `{{title}}` is listed here, though it
+    /// calls the helper `title` instead when there is one.
+    pub paths: BTreeSet<String>,
+}
+
+impl Dependencies {
+    /// Whether nothing is referenced
+    pub fn is_empty(&self) -> bool {
+        self.partials.is_empty()
+            && self.partial_blocks.is_empty()
+            && self.inline_partials.is_empty()
+            && self.helpers.is_empty()
+            && self.decorators.is_empty()
+            && self.paths.is_empty()
+    }
+
+    /// Partials that may be loaded from the registry
+    fn external_partials(&self) -> impl Iterator<Item = &String> {
+        self.partials
+            .iter()
+            .chain(self.partial_blocks.iter())
+            .filter(move |p| !self.inline_partials.contains(*p))
+    }
+}
+
+impl<'ast> Visitor<'ast> for Dependencies {
+    fn visit_expression(&mut self, h: &'ast HelperTemplate) {
+        if !h.is_name_only() {
+            self.helpers.extend(h.name.as_name().map(str::to_owned));
+        }
+        visitor::walk_helper_template(self, h);
+    }
+
+    fn visit_html_expression(&mut self, h: &'ast HelperTemplate) {
+        self.visit_expression(h);
+    }
+
+    fn visit_helper_block(&mut self, h: &'ast HelperTemplate) {
+        self.helpers.extend(h.name.as_name().map(str::to_owned));
+        // the layout of `extend` is rendered like a partial
+        if let (Some("extend"), Some(Parameter::Literal(Json::String(layout)))) =
+            (h.name.as_name(), h.params.first())
+        {
+            self.partials.insert(layout.clone());
+        }
+        visitor::walk_helper_template(self, h);
+    }
+
+    fn visit_decorator(&mut self, d: &'ast DecoratorTemplate) {
+        if let Some(name) = d.name.as_name() {
+            if let (true, Some(Parameter::Literal(Json::String(partial)))) =
+                (name == "inline", d.params.first())
+            {
+                self.inline_partials.insert(partial.clone());
+            }
+            self.decorators.insert(name.to_owned());
+        }
+        visitor::walk_decorator_template(self, d);
+    }
+
+    fn visit_decorator_block(&mut self, d: &'ast DecoratorTemplate) {
+        self.visit_decorator(d);
+    }
+
+    fn visit_partial(&mut self, d: &'ast DecoratorTemplate) {
+        if let Some(name) = partial_name(&d.name) {
+            if name != PARTIAL_BLOCK {
+                if d.template.is_some() {
+                    self.partial_blocks.insert(name);
+                } else {
+                    self.partials.insert(name);
+                }
+            }
+        } else {
+            self.visit_parameter(&d.name);
+        }
+        if let Some(Parameter::Literal(Json::String(fallback))) = d.hash.get(PARTIAL_FALLBACK) {
+            self.partials.insert(fallback.clone());
+        }
+
+        // the name is not a data path, so the rest is walked here
+        for p in &d.params {
+            self.visit_parameter(p);
+        }
+        let mut keys: Vec<&String> = d.hash.keys().collect();
+        keys.sort();
+        for k in keys {
+            self.visit_hash_entry(k, &d.hash[k]);
+        }
+        if let Some(ref t) = d.template {
+            self.visit_template(t);
+        }
+    }
+
+    fn visit_partial_block(&mut self, d: &'ast DecoratorTemplate) {
+        self.visit_partial(d);
+    }
+
+    fn visit_path(&mut self, path: &'ast Path) {
+        if let Path::Relative((_, raw)) = path {
+            self.paths.insert(raw.clone());
+        }
+    }
+
+    fn visit_subexpression(&mut self, s: &'ast Subexpression) {
+        self.helpers.insert(s.name().to_owned());
+        if let Some(params) = s.params() {
+            for p in params {
+                self.visit_parameter(p);
+            }
+        }
+        if let Some(hash) = s.hash() {
+            let mut keys: Vec<&String> = hash.keys().collect();
+            keys.sort();
+            for k in keys {
+                self.visit_hash_entry(k, &hash[k]);
+            }
+        }
+    }
+}
+
+/// The name of a partial, if known before rendering
+fn partial_name(name: &Parameter) -> Option<String> {
+    match name {
+        Parameter::Name(n) => Some(n.clone()),
+        Parameter::Path(p) => Some(p.raw().to_owned()),
+        Parameter::Literal(Json::String(s)) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+impl Template {
+    /// Collect the partials, helpers, decorators and data paths the template
+    /// references
+    ///
+    /// ```
+    /// use handlebars::Template;
+    ///
+    /// let t = Template::compile("{{#each items}}{{> item}}{{upper name}}{{/each}}").unwrap();
+    /// let deps = t.dependencies();
+    /// assert!(deps.partials.contains("item"));
+    /// assert!(deps.helpers.contains("each"));
+    /// assert!(deps.paths.contains("name"));
+    /// ```
+    pub fn dependencies(&self) -> Dependencies {
+        let mut deps = Dependencies::default();
+        deps.visit_template(self);
+        deps
+    }
+}
+
+/// Dependencies of all templates of a registry
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DependencyGraph {
+    /// dependencies of each registered template
+    pub templates: BTreeMap<String, Dependencies>,
+    /// partials, helpers and decorators referenced by each template, that
+    /// are not registered
+    ///
+    /// Partial blocks are never missing as they have a fallback. Partials
+    /// defined with `inline` by any template are assumed to be available.
+    pub missing: BTreeMap<String, Dependencies>,
+    /// templates including each other as partials, the first template of
+    /// each cycle is repeated at its end
+    ///
+    /// They are not errors, recursion guarded by a condition is valid, like
+    /// a tree template including itself for each child.
+    pub cycles: Vec<Vec<String>>,
+}
+
+quick_error! {
+/// A problem found by `Registry::validate`
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum ValidationError {
+        MissingPartial(template: String, name: String) {
+            display("Partial {:?} used in template {:?} is not registered", name, template)
+        }
+        MissingHelper(template: String, name: String) {
+            display("Helper {:?} used in template {:?} is not registered", name, template)
+        }
+        MissingDecorator(template: String, name: String) {
+            display("Decorator {:?} used in template {:?} is not registered", name, template)
+        }
+    }
+}
+
+impl DependencyGraph {
+    /// All missing items, as errors
+    pub fn errors(&self) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        for (template, missing) in &self.missing {
+            for name in &missing.partials {
+                errors.push(ValidationError::MissingPartial(
+                    template.clone(),
+                    name.clone(),
+                ));
+            }
+            for name in &missing.helpers {
+                errors.push(ValidationError::MissingHelper(
+                    template.clone(),
+                    name.clone(),
+                ));
+            }
+            for name in &missing.decorators {
+                errors.push(ValidationError::MissingDecorator(
+                    template.clone(),
+                    name.clone(),
+                ));
+            }
+        }
+        errors
+    }
+}
+
+pub(crate) fn dependency_graph(registry: &Registry<'_>) -> DependencyGraph {
+    let templates: BTreeMap<String, Dependencies> = registry
+        .get_templates()
+        .iter()
+        .map(|(name, t)| (name.clone(), t.dependencies()))
+        .collect();
+
+    let inline_partials: BTreeSet<&String> = templates
+        .values()
+        .flat_map(|deps| deps.inline_partials.iter())
+        .collect();
+
+    let mut missing = BTreeMap::new();
+    for (name, deps) in &templates {
+        let m = Dependencies {
+            partials: deps
+                .partials
+                .iter()
+                .filter(|p| {
+                    !inline_partials.contains(p)
+                        && !matches!(
+                            registry.get_or_load_partial(p, Some(name)),
+                            Some((_, Ok(_)))
+                        )
+                })
+                .cloned()
+                .collect(),
+            helpers: deps
+                .helpers
+                .iter()
+                .filter(|h| !registry.has_helper(h))
+                .cloned()
+                .collect(),
+            decorators: deps
+                .decorators
+                .iter()
+                .filter(|d| registry.get_decorator(d).is_none())
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+        if !m.is_empty() {
+            missing.insert(name.clone(), m);
+        }
+    }
+
+    // partials included by each template, by their registered names
+    let includes: BTreeMap<&String, Vec<&String>> = templates
+        .iter()
+        .map(|(name, deps)| {
+            let partials = deps
+                .external_partials()
+                .filter_map(|p| {
+                    registry.find_partial_candidate(p, Some(name), |c| {
+                        templates.get_key_value(c).map(|(k, _)| k)
+                    })
+                })
+                .collect();
+            (name, partials)
+        })
+        .collect();
+
+    let cycles = find_cycles(&includes);
+    DependencyGraph {
+        templates,
+        missing,
+        cycles,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    Visiting,
+    Done,
+}
+
+/// Find partial cycles with a depth first search, each back edge closes one
+fn find_cycles(includes: &BTreeMap<&String, Vec<&String>>) -> Vec<Vec<String>> {
+    fn visit<'a>(
+        name: &'a String,
+        includes: &BTreeMap<&'a String, Vec<&'a String>>,
+        marks: &mut BTreeMap<&'a String, Mark>,
+        stack: &mut Vec<&'a String>,
+        cycles: &mut Vec<Vec<String>>,
+    ) {
+        marks.insert(name, Mark::Visiting);
+        stack.push(name);
+        for &partial in &includes[name] {
+            match marks.get(partial) {
+                Some(Mark::Visiting) => {
+                    let start = stack.iter().position(|n| *n == partial).unwrap();
+                    let mut cycle: Vec<String> =
+                        stack[start..].iter().map(|n| (*n).clone()).collect();
+                    cycle.push(partial.clone());
+                    cycles.push(cycle);
+                }
+                Some(Mark::Done) => {}
+                None => visit(partial, includes, marks, stack, cycles),
+            }
+        }
+        stack.pop();
+        marks.insert(name, Mark::Done);
+    }
+
+    let mut marks = BTreeMap::new();
+    let mut cycles = Vec::new();
+    for &name in includes.keys() {
+        if !marks.contains_key(name) {
+            visit(name, includes, &mut marks, &mut Vec::new(), &mut cycles);
+        }
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod test {
+    use super::ValidationError;
+    use crate::context::Context;
+    use crate::helpers::HelperResult;
+    use crate::output::Output;
+    use crate::registry::Registry;
+    use crate::render::{Helper, RenderContext};
+    use crate::template::Template;
+
+    #[test]
+    fn test_template_dependencies() {
+        let t = Template::compile(
+            "{{#*inline \"row\"}}{{name}}{{/inline}}\
+             {{#each (sort items by=key) as |item|}}{{> row item}}{{/each}}\
+             {{#> layout title=page.title}}{{> @partial-block}}{{/layout}}\
+             {{> (lookup . \"dynamic\") fallback=\"card\"}}{{{format @index date fmt=\"short\"}}}{{this}}\
+             {{#extend \"base\"}}{{/extend}}",
+        )
+        .unwrap();
+        let deps = t.dependencies();
+
+        let set = |items: &[&str]| items.iter().map(|s| (*s).to_owned()).collect();
+        assert_eq!(deps.partials, set(&["base", "card", "row"]));
+        assert_eq!(deps.partial_blocks, set(&["layout"]));
+        assert_eq!(deps.inline_partials, set(&["row"]));
+        assert_eq!(
+            deps.helpers,
+            set(&["each", "extend", "format", "lookup", "sort"])
+        );
+        assert_eq!(deps.decorators, set(&["inline"]));
+        assert_eq!(
+            deps.paths,
+            set(&[
+                ".",
+                "date",
+                "item",
+                "items",
+                "key",
+                "name",
+                "page.title",
+                "this"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut hbs = Registry::new();
+        hbs.register_template_string("page", "{{#> layout}}{{> nav}}{{/layout}}{{> footer}}")
+            .unwrap();
+        hbs.register_template_string(
+            "layout",
+            "{{#*inline \"nav\"}}{{/inline}}{{> @partial-block}}",
+        )
+        .unwrap();
+        hbs.register_template_string("a", "{{#if x}}{{> b}}{{/if}}{{*log}}")
+            .unwrap();
+        hbs.register_template_string("b", "{{> c}}{{shout x}}")
+            .unwrap();
+        hbs.register_template_string("c", "{{> a}}").unwrap();
+
+        let graph = hbs.dependency_graph();
+        assert_eq!(graph.templates.len(), 5);
+        assert_eq!(graph.cycles, vec![vec!["a", "b", "c", "a"]]);
+        assert_eq!(
+            hbs.validate().unwrap_err(),
+            vec![
+                ValidationError::MissingDecorator("a".to_owned(), "log".to_owned()),
+                ValidationError::MissingHelper("b".to_owned(), "shout".to_owned()),
+                ValidationError::MissingPartial("page".to_owned(), "footer".to_owned()),
+            ]
+        );
+
+        hbs.register_template_string("c", "{{x}}").unwrap();
+        hbs.register_template_string("footer", "").unwrap();
+        hbs.register_template_string("a", "{{> b}}").unwrap();
+        hbs.register_helper(
+            "shout",
+            Box::new(
+                |_: &Helper<'_, '_>,
+                 _: &Registry<'_>,
+                 _: &Context,
+                 _: &mut RenderContext<'_, '_>,
+                 _: &mut dyn Output|
+                 -> HelperResult { Ok(()) },
+            ),
+        );
+        assert!(hbs.validate().is_ok());
+
+        // guarded recursion is a cycle, but not an error
+        hbs.register_template_string("node", "{{#each children}}{{> node}}{{/each}}")
+            .unwrap();
+        assert_eq!(hbs.dependency_graph().cycles, vec![vec!["node", "node"]]);
+        assert!(hbs.validate().is_ok());
+        assert_eq!(
+            hbs.render("node", &json!({"children": [{"children": [{}]}]}))
+                .unwrap(),
+            ""
+        );
+    }
+}
@@ -66,6 +66,9 @@ pub struct BlockContext<'reg> {
     block_params: BlockParams<'reg>,
     /// local variables in current context
     local_variables: LocalVars,
+    /// values shadowing the ones of the base path, like the hash of a
+    /// partial
+    overlay: BTreeMap<String, Json>,
 }
 
 impl<'reg> BlockContext<'reg> {
@@ -123,4 +126,14 @@ impl<'reg> BlockContext<'reg> {
     pub fn set_block_params(&mut self, block_params: BlockParams<'reg>) {
         self.block_params = block_params;
     }
+
+    /// Get a value shadowing the one at `name` of the base path
+    pub(crate) fn get_overlay(&self, name: &str) -> Option<&Json> {
+        self.overlay.get(name)
+    }
+
+    /// Set the values shadowing the ones of the base path
+    pub(crate) fn set_overlay(&mut self, overlay: BTreeMap<String, Json>) {
+        self.overlay = overlay;
+    }
 }
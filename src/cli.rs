@@ -5,7 +5,7 @@ use std::str::FromStr;
 
 use serde_json::value::Value as Json;
 
-use handlebars::Handlebars;
+use handlebars::{Diagnostic, Handlebars};
 
 fn usage() -> ! {
     eprintln!("Usage: handlebars-cli template.hbs '{{\"json\": \"data\"}}'");
@@ -35,16 +35,27 @@ fn main() {
 
     let mut handlebars = Handlebars::new();
 
-    handlebars
-        .register_template_file(&filename, &filename)
-        .ok()
-        .unwrap();
+    let source = match fs::read_to_string(&filename) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", filename, e);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = handlebars.register_template_string(&filename, &source) {
+        eprint!("{}", Diagnostic::from(&e).with_source(&source));
+        process::exit(2);
+    }
     match handlebars.render(&filename, &data) {
         Ok(data) => {
             println!("{}", data);
         }
         Err(e) => {
-            println!("Error rendering {}: {}", filename, e);
+            let mut diagnostic = handlebars.diagnostic(&e);
+            if e.template_name.as_ref() == Some(&filename) {
+                diagnostic = diagnostic.with_source(&source);
+            }
+            eprint!("{}", diagnostic);
             process::exit(2);
         }
     }
@@ -1,22 +1,244 @@
-use std::collections::{HashMap, VecDeque};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::iter::Peekable;
+use std::sync::{Arc, Mutex};
 
 use serde::Serialize;
 use serde_json::value::{to_value, Map, Value as Json};
 
 use crate::block::{BlockContext, BlockParamHolder};
-use crate::error::RenderError;
+use crate::error::{RenderError, RenderErrorReason};
 use crate::grammar::Rule;
 use crate::json::path::*;
-use crate::json::value::ScopedJson;
+use crate::json::value::{JsonTruthy, ScopedJson};
 use crate::util::extend;
 
 pub type Object = HashMap<String, Json>;
 
 /// The context wrap data you render on your templates.
 ///
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Context {
     data: Json,
+    lazy: Option<Arc<dyn ContextValue + Send + Sync>>,
+    computed: BTreeMap<Vec<String>, Arc<dyn ComputedValue + Send + Sync>>,
+    iters: BTreeMap<Vec<String>, IterSlot>,
+}
+
+/// An iterator registered in the context, see `Context::register_iter`
+pub(crate) type ContextIter = Box<dyn Iterator<Item = Result<Json, RenderError>> + Send>;
+
+/// A registered iterator, `None` once consumed
+type IterSlot = Arc<Mutex<Option<Peekable<ContextIter>>>>;
+
+/// Computed values evaluated in a render, by path
+pub(crate) type ComputedCache = RefCell<HashMap<Vec<String>, Json>>;
+
+impl fmt::Debug for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Context")
+            .field("data", &self.data)
+            .field("lazy", &self.lazy.is_some())
+            .field("computed", &self.computed.keys().collect::<Vec<_>>())
+            .field("iters", &self.iters.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Data resolved while rendering, instead of serialized up front
+///
+/// Templates navigate the value with `get`, one path segment at a time, and
+/// only the values they reach are turned into JSON with `to_json`. Use it
+/// with `Context::lazy` for data that is expensive to serialize as a whole,
+/// like a large report or objects fetched on demand.
+///
+/// `if`, `unless`, `each` and `with` read their param with `is_truthy`,
+/// `len` and `keys` instead, and `each` and `with` keep navigating from it
+/// with `get`. So a collection only used by them is never turned into JSON,
+/// its children are fetched one by one:
+///
+/// ```
+/// use handlebars::{Context, ContextValue, Handlebars, JsonValue};
+///
+/// struct Report;
+/// struct Rows;
+/// struct Row(usize);
+///
+/// impl ContextValue for Report {
+///     fn get(&self, key: &str) -> Option<Box<dyn ContextValue + '_>> {
+///         match key {
+///             "title" => Some(Box::new(JsonValue::from("Sales"))),
+///             "rows" => Some(Box::new(Rows)),
+///             _ => None,
+///         }
+///     }
+///
+///     fn to_json(&self) -> JsonValue {
+///         serde_json::json!({ "title": "Sales", "rows": Rows.to_json() })
+///     }
+/// }
+///
+/// impl ContextValue for Rows {
+///     fn get(&self, key: &str) -> Option<Box<dyn ContextValue + '_>> {
+///         let idx: usize = key.parse().ok()?;
+///         if idx < 3 {
+///             Some(Box::new(Row(idx)))
+///         } else {
+///             None
+///         }
+///     }
+///
+///     fn to_json(&self) -> JsonValue {
+///         panic!("rows are fetched one by one");
+///     }
+///
+///     fn is_truthy(&self, _include_zero: bool) -> bool {
+///         true
+///     }
+///
+///     fn len(&self) -> Option<usize> {
+///         Some(3)
+///     }
+/// }
+///
+/// impl ContextValue for Row {
+///     fn get(&self, key: &str) -> Option<Box<dyn ContextValue + '_>> {
+///         match key {
+///             "amount" => Some(Box::new(JsonValue::from(self.0 * 10))),
+///             _ => None,
+///         }
+///     }
+///
+///     fn to_json(&self) -> JsonValue {
+///         serde_json::json!({ "amount": self.0 * 10 })
+///     }
+/// }
+///
+/// let hbs = Handlebars::new();
+/// let ctx = Context::lazy(Report);
+/// let rendered = hbs
+///     .render_template_with_context(
+///         "{{title}}:{{#if rows}}{{#each rows}} {{amount}}{{/each}}{{/if}}",
+///         &ctx,
+///     )
+///     .unwrap();
+/// assert_eq!(rendered, "Sales: 0 10 20");
+/// ```
+#[allow(clippy::len_without_is_empty)]
+pub trait ContextValue {
+    /// The child at `key`, a field of an object or an index of an array, or
+    /// `None` when it doesn't exist
+    fn get(&self, key: &str) -> Option<Box<dyn ContextValue + '_>>;
+
+    /// The JSON value, used when the template renders the value or passes it
+    /// to a helper
+    fn to_json(&self) -> Json;
+
+    /// Whether the value is truthy, for `if`, `unless` and `with`
+    ///
+    /// Defaults to the truthiness of `to_json`.
+    fn is_truthy(&self, include_zero: bool) -> bool {
+        JsonTruthy::is_truthy(&self.to_json(), include_zero)
+    }
+
+    /// The number of items, when the value is an array iterated by `each`
+    ///
+    /// Items are then read with `get`, from `"0"` to the length. Defaults to
+    /// the length of `to_json`.
+    fn len(&self) -> Option<usize> {
+        match self.to_json() {
+            Json::Array(l) => Some(l.len()),
+            _ => None,
+        }
+    }
+
+    /// The keys, when the value is an object iterated by `each`
+    ///
+    /// Values are then read with `get`. Defaults to the keys of `to_json`.
+    fn keys(&self) -> Option<Vec<String>> {
+        match self.to_json() {
+            Json::Object(m) => Some(m.keys().cloned().collect()),
+            _ => None,
+        }
+    }
+}
+
+impl ContextValue for Json {
+    fn get(&self, key: &str) -> Option<Box<dyn ContextValue + '_>> {
+        let child = match self {
+            Json::Array(l) => key.parse::<usize>().ok().and_then(|idx| l.get(idx)),
+            Json::Object(m) => m.get(key),
+            _ => None,
+        };
+        child.map(|c| Box::new(c) as Box<dyn ContextValue>)
+    }
+
+    fn to_json(&self) -> Json {
+        self.clone()
+    }
+
+    fn is_truthy(&self, include_zero: bool) -> bool {
+        JsonTruthy::is_truthy(self, include_zero)
+    }
+
+    fn len(&self) -> Option<usize> {
+        self.as_array().map(Vec::len)
+    }
+
+    fn keys(&self) -> Option<Vec<String>> {
+        self.as_object().map(|m| m.keys().cloned().collect())
+    }
+}
+
+impl<T: ContextValue + ?Sized> ContextValue for &T {
+    fn get(&self, key: &str) -> Option<Box<dyn ContextValue + '_>> {
+        (**self).get(key)
+    }
+
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+
+    fn is_truthy(&self, include_zero: bool) -> bool {
+        (**self).is_truthy(include_zero)
+    }
+
+    fn len(&self) -> Option<usize> {
+        (**self).len()
+    }
+
+    fn keys(&self) -> Option<Vec<String>> {
+        (**self).keys()
+    }
+}
+
+/// A value computed when a template references it
+///
+/// Register it at a path of the context with `Context::register_computed`.
+/// It's computed at most once per render, the first time a template
+/// references the path or a path inside it, and never when the template
+/// doesn't. Closures taking the parent value, the object the computed value
+/// is a field of, implement this trait.
+pub trait ComputedValue {
+    /// Compute the value from its parent value
+    fn compute(&self, parent: &Json) -> Result<Json, RenderError>;
+}
+
+impl<F: Fn(&Json) -> Result<Json, RenderError>> ComputedValue for F {
+    fn compute(&self, parent: &Json) -> Result<Json, RenderError> {
+        (*self)(parent)
+    }
+}
+
+/// Navigate a lazy value, turning only the value at the end into JSON
+fn get_lazy_data(value: &dyn ContextValue, paths: &[String]) -> Option<Json> {
+    match paths.split_first() {
+        Some((p, rest)) => value
+            .get(p)
+            .and_then(|child| get_lazy_data(child.as_ref(), rest)),
+        None => Some(value.to_json()),
+    }
 }
 
 #[derive(Debug)]
@@ -77,6 +299,13 @@ fn parse_json_visitor<'a, 'reg>(
             ResolvedPath::AbsolutePath(path_stack)
         }
         None => {
+            if let Some((value, rest)) =
+                get_in_overlay(block_contexts, relative_path, path_context_depth, from_root)
+            {
+                merge_json_path(&mut path_stack, rest);
+                return ResolvedPath::LocalValue(path_stack, value);
+            }
+
             if path_context_depth > 0 {
                 let blk = block_contexts
                     .get(path_context_depth as usize)
@@ -138,6 +367,31 @@ fn get_in_block_params<'a, 'reg>(
     None
 }
 
+/// The value overlaid on the block `relative_path` starts from, with the
+/// rest of the path
+fn get_in_overlay<'a, 'b>(
+    block_contexts: &'a VecDeque<BlockContext<'_>>,
+    relative_path: &'b [PathSeg],
+    path_context_depth: i64,
+    from_root: bool,
+) -> Option<(&'a Json, &'b [PathSeg])> {
+    if from_root {
+        return None;
+    }
+    let blk = block_contexts
+        .get(path_context_depth as usize)
+        .or_else(|| block_contexts.front())?;
+    let idx = relative_path
+        .iter()
+        .position(|seg| matches!(seg, PathSeg::Named(_)))?;
+    match relative_path[idx] {
+        PathSeg::Named(ref name) => blk
+            .get_overlay(name)
+            .map(|value| (value, &relative_path[idx + 1..])),
+        PathSeg::Ruled(_) => None,
+    }
+}
+
 pub(crate) fn merge_json(base: &Json, addition: &HashMap<&str, &Json>) -> Json {
     let mut base_map = match base {
         Json::Object(ref m) => m.clone(),
@@ -151,17 +405,254 @@ pub(crate) fn merge_json(base: &Json, addition: &HashMap<&str, &Json>) -> Json {
     Json::Object(base_map)
 }
 
+/// The error for iterating a registered iterator twice
+pub(crate) fn iter_consumed(path: &[String]) -> RenderError {
+    RenderErrorReason::IteratorConsumed(path.join(".")).into()
+}
+
+/// Parse a path registered in the context, from its root
+fn parse_context_path(path: &str) -> Result<Vec<String>, RenderError> {
+    let invalid = || RenderErrorReason::InvalidJsonPath(path.to_owned());
+    let segs = match Path::parse(path)? {
+        Path::Relative((segs, _)) => segs,
+        Path::Local(_) => return Err(invalid().into()),
+    };
+    let mut key = Vec::with_capacity(segs.len());
+    for (idx, seg) in segs.into_iter().enumerate() {
+        match seg {
+            PathSeg::Named(name) => key.push(name),
+            PathSeg::Ruled(Rule::path_root) if idx == 0 => {}
+            _ => return Err(invalid().into()),
+        }
+    }
+    if key.is_empty() {
+        return Err(invalid().into());
+    }
+    Ok(key)
+}
+
 impl Context {
     /// Create a context with null data
     pub fn null() -> Context {
-        Context { data: Json::Null }
+        Context {
+            data: Json::Null,
+            lazy: None,
+            computed: BTreeMap::new(),
+            iters: BTreeMap::new(),
+        }
     }
 
     /// Create a context with given data
     pub fn wraps<T: Serialize>(e: T) -> Result<Context, RenderError> {
-        to_value(e)
-            .map_err(RenderError::from)
-            .map(|d| Context { data: d })
+        to_value(e).map_err(RenderError::from).map(|d| Context {
+            data: d,
+            lazy: None,
+            computed: BTreeMap::new(),
+            iters: BTreeMap::new(),
+        })
+    }
+
+    /// Create a context with data resolved while rendering
+    ///
+    /// See `ContextValue`. The JSON data of the context, returned by `data`,
+    /// is `null`.
+    pub fn lazy<T: ContextValue + Send + Sync + 'static>(value: T) -> Context {
+        Context {
+            data: Json::Null,
+            lazy: Some(Arc::new(value)),
+            computed: BTreeMap::new(),
+            iters: BTreeMap::new(),
+        }
+    }
+
+    /// Register a value computed when a template references `path`
+    ///
+    /// The path is a path expression like `user.avatar_url` or `@root.now`,
+    /// always taken from the root of the context. A computed value replaces
+    /// the data at its path, if any.
+    ///
+    /// ```
+    /// use handlebars::{Context, Handlebars, JsonValue};
+    /// use serde_json::json;
+    ///
+    /// let mut ctx = Context::wraps(json!({"user": {"name": "Ann"}})).unwrap();
+    /// ctx.register_computed("user.greeting", |user: &JsonValue| {
+    ///     Ok(JsonValue::from(format!("Hello, {}", user["name"].as_str().unwrap_or(""))))
+    /// })
+    /// .unwrap();
+    ///
+    /// let hbs = Handlebars::new();
+    /// let rendered = hbs
+    ///     .render_template_with_context("{{#with user}}{{greeting}}!{{/with}}", &ctx)
+    ///     .unwrap();
+    /// assert_eq!(rendered, "Hello, Ann!");
+    /// ```
+    pub fn register_computed<C>(&mut self, path: &str, value: C) -> Result<(), RenderError>
+    where
+        C: ComputedValue + Send + Sync + 'static,
+    {
+        let key = parse_context_path(path)?;
+        self.computed.insert(key, Arc::new(value));
+        Ok(())
+    }
+
+    /// Register an iterator at `path`, consumed by `each` as it renders
+    ///
+    /// Items are serialized one at a time, so combined with
+    /// `render_with_context_to_write`, large exports are rendered without
+    /// building the whole array. `@index`, `@first` and `@last` work as with
+    /// arrays, the iterator is peeked one item ahead for `@last`.
+    ///
+    /// The path is taken from the root of the context, like with
+    /// `register_computed`. The iterator can only be consumed once: iterating
+    /// it again, in the same render or the next one, is an error. `if` and
+    /// `unless` peek its first item to tell if it's empty. Other references
+    /// to the path render nothing.
+    ///
+    /// The async render API may render a template several times to resolve
+    /// async helpers. The items are then kept until the end of the render,
+    /// and replayed to the next passes.
+    ///
+    /// ```
+    /// use handlebars::{Context, Handlebars};
+    /// use serde_json::json;
+    ///
+    /// let mut ctx = Context::wraps(json!({"title": "squares"})).unwrap();
+    /// ctx.register_iter("rows", (1..4).map(|i| json!({"n": i, "square": i * i})))
+    ///     .unwrap();
+    ///
+    /// let hbs = Handlebars::new();
+    /// let mut out = Vec::new();
+    /// hbs.render_template_with_context_to_write(
+    ///     "{{#each rows}}{{n}},{{square}}{{#unless @last}};{{/unless}}{{/each}}",
+    ///     &ctx,
+    ///     &mut out,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), "1,1;2,4;3,9");
+    /// ```
+    pub fn register_iter<I>(&mut self, path: &str, iter: I) -> Result<(), RenderError>
+    where
+        I: IntoIterator,
+        I::Item: Serialize,
+        I::IntoIter: Send + 'static,
+    {
+        let key = parse_context_path(path)?;
+        let iter = iter
+            .into_iter()
+            .map(|item| to_value(item).map_err(RenderError::from));
+        self.iters.insert(
+            key,
+            Arc::new(Mutex::new(Some((Box::new(iter) as ContextIter).peekable()))),
+        );
+        Ok(())
+    }
+
+    /// Take the iterator registered at `path`, if any
+    pub(crate) fn take_iter(
+        &self,
+        path: &[String],
+    ) -> Option<Result<Peekable<ContextIter>, RenderError>> {
+        let iter = self.iters.get(path)?.lock().unwrap().take();
+        Some(iter.ok_or_else(|| iter_consumed(path)))
+    }
+
+    /// Whether the iterator registered at `path` has no items, peeking its
+    /// first one
+    pub(crate) fn is_iter_empty(&self, path: &[String]) -> Option<Result<bool, RenderError>> {
+        let mut iter = self.iters.get(path)?.lock().unwrap();
+        Some(match *iter {
+            Some(ref mut iter) => Ok(iter.peek().is_none()),
+            None => Err(iter_consumed(path)),
+        })
+    }
+
+    /// Whether `paths` is resolved by the lazy value, and not by a computed
+    /// value or an iterator
+    fn is_lazy_path(&self, paths: &[String]) -> bool {
+        self.lazy.is_some()
+            && !self.iters.contains_key(paths)
+            && !(1..=paths.len()).any(|len| self.computed.contains_key(&paths[..len]))
+    }
+
+    /// The full path of `relative_path`, when it's resolved by the lazy value
+    pub(crate) fn lazy_path(
+        &self,
+        relative_path: &[PathSeg],
+        block_contexts: &VecDeque<BlockContext<'_>>,
+    ) -> Option<Vec<String>> {
+        match parse_json_visitor(relative_path, block_contexts, true) {
+            ResolvedPath::AbsolutePath(paths) if self.is_lazy_path(&paths) => Some(paths),
+            _ => None,
+        }
+    }
+
+    /// Call `f` with the lazy value at `paths`, if `paths` is resolved by the
+    /// lazy value and exists
+    pub(crate) fn with_lazy<R, F>(&self, paths: &[String], f: F) -> Option<R>
+    where
+        F: FnOnce(&dyn ContextValue) -> R,
+    {
+        fn visit<R, F>(value: &dyn ContextValue, paths: &[String], f: F) -> Option<R>
+        where
+            F: FnOnce(&dyn ContextValue) -> R,
+        {
+            match paths.split_first() {
+                Some((p, rest)) => visit(value.get(p)?.as_ref(), rest, f),
+                None => Some(f(value)),
+            }
+        }
+
+        if !self.is_lazy_path(paths) {
+            return None;
+        }
+        visit(self.lazy.as_ref()?.as_ref(), paths, f)
+    }
+
+    /// The data at `paths` when a computed value is registered at it or at
+    /// one of its parents
+    fn navigate_computed(
+        &self,
+        paths: &[String],
+        cache: &ComputedCache,
+    ) -> Result<Option<Option<Json>>, RenderError> {
+        if self.computed.is_empty() {
+            return Ok(None);
+        }
+        let found = (1..=paths.len())
+            .rev()
+            .find_map(|len| self.computed.get(&paths[..len]).map(|c| (len, c)));
+        let (len, computed) = match found {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+
+        let key = &paths[..len];
+        let cached = cache.borrow().get(key).cloned();
+        let value = match cached {
+            Some(value) => value,
+            None => {
+                let parent_paths = &paths[..len - 1];
+                let value = if let Some(ref lazy) = self.lazy {
+                    let parent = get_lazy_data(lazy.as_ref(), parent_paths);
+                    computed.compute(parent.as_ref().unwrap_or(&Json::Null))?
+                } else {
+                    let mut ptr = Some(self.data());
+                    for p in parent_paths {
+                        ptr = get_data(ptr, p)?;
+                    }
+                    computed.compute(ptr.unwrap_or(&Json::Null))?
+                };
+                cache.borrow_mut().insert(key.to_vec(), value.clone());
+                value
+            }
+        };
+
+        let mut ptr = Some(&value);
+        for p in &paths[len..] {
+            ptr = get_data(ptr, p)?;
+        }
+        Ok(Some(ptr.cloned()))
     }
 
     /// Navigate the context with relative path and block scopes
@@ -169,6 +660,7 @@ impl Context {
         &'rc self,
         relative_path: &[PathSeg],
         block_contexts: &VecDeque<BlockContext<'reg>>,
+        computed_cache: &ComputedCache,
     ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
         // always use absolute at the moment until we get base_value lifetime issue fixed
         let resolved_visitor = parse_json_visitor(relative_path, block_contexts, true);
@@ -178,6 +670,22 @@ impl Context {
 
         match resolved_visitor {
             ResolvedPath::AbsolutePath(paths) => {
+                if self.iters.contains_key(&paths) {
+                    return Ok(ScopedJson::Lazy(Json::Null, paths));
+                }
+
+                if let Some(value) = self.navigate_computed(&paths, computed_cache)? {
+                    return Ok(value
+                        .map(|v| ScopedJson::Lazy(v, paths))
+                        .unwrap_or(ScopedJson::Missing));
+                }
+
+                if let Some(ref lazy) = self.lazy {
+                    return Ok(get_lazy_data(lazy.as_ref(), &paths)
+                        .map(|v| ScopedJson::Lazy(v, paths))
+                        .unwrap_or(ScopedJson::Missing));
+                }
+
                 let mut ptr = Some(self.data());
                 for p in paths.iter() {
                     ptr = get_data(ptr, p)?;
@@ -226,19 +734,27 @@ impl Context {
 #[cfg(test)]
 mod test {
     use crate::block::{BlockContext, BlockParams};
-    use crate::context::{self, Context};
+    use crate::context::{self, Context, ContextValue};
     use crate::error::RenderError;
     use crate::json::path::Path;
     use crate::json::value::{self, ScopedJson};
-    use serde_json::value::Map;
+    use crate::registry::Registry;
+    use serde_json::value::{Map, Value as Json};
+    use std::cell::RefCell;
     use std::collections::{HashMap, VecDeque};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
 
     fn navigate_from_root<'reg, 'rc>(
         ctx: &'rc Context,
         path: &str,
     ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
         let relative_path = Path::parse(path).unwrap();
-        ctx.navigate(relative_path.segs().unwrap(), &VecDeque::new())
+        ctx.navigate(
+            relative_path.segs().unwrap(),
+            &VecDeque::new(),
+            &RefCell::new(HashMap::new()),
+        )
     }
 
     #[derive(Serialize)]
@@ -407,11 +923,16 @@ mod test {
 
         let mut blocks = VecDeque::new();
         blocks.push_front(block);
+        let cache = RefCell::new(HashMap::new());
 
         assert_eq!(
-            ctx.navigate(&Path::parse("@root/b").unwrap().segs().unwrap(), &blocks)
-                .unwrap()
-                .render(),
+            ctx.navigate(
+                &Path::parse("@root/b").unwrap().segs().unwrap(),
+                &blocks,
+                &cache
+            )
+            .unwrap()
+            .render(),
             "2".to_string()
         );
     }
@@ -436,18 +957,174 @@ mod test {
 
         let mut blocks = VecDeque::new();
         blocks.push_front(block);
+        let cache = RefCell::new(HashMap::new());
 
         assert_eq!(
-            ctx.navigate(&Path::parse("z.[1]").unwrap().segs().unwrap(), &blocks)
-                .unwrap()
-                .render(),
+            ctx.navigate(
+                &Path::parse("z.[1]").unwrap().segs().unwrap(),
+                &blocks,
+                &cache
+            )
+            .unwrap()
+            .render(),
             "2".to_string()
         );
         assert_eq!(
-            ctx.navigate(&Path::parse("t").unwrap().segs().unwrap(), &blocks)
+            ctx.navigate(&Path::parse("t").unwrap().segs().unwrap(), &blocks, &cache)
                 .unwrap()
                 .render(),
             "good".to_string()
         );
     }
+
+    /// Json data recording the paths turned into JSON
+    struct Recorded {
+        path: String,
+        value: Json,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl ContextValue for Recorded {
+        fn get(&self, key: &str) -> Option<Box<dyn ContextValue + '_>> {
+            let value = ContextValue::get(&self.value, key)?.to_json();
+            Some(Box::new(Recorded {
+                path: format!("{}/{}", self.path, key),
+                value,
+                log: self.log.clone(),
+            }))
+        }
+
+        fn to_json(&self) -> Json {
+            self.log.lock().unwrap().push(self.path.clone());
+            self.value.clone()
+        }
+    }
+
+    #[test]
+    fn test_lazy_context() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let ctx = Context::lazy(Recorded {
+            path: String::new(),
+            value: json!({
+                "title": "report",
+                "user": {"name": "ning", "groups": ["a", "b"]},
+                "rows": [{"id": 1, "big": "..."}, {"id": 2, "big": "..."}]
+            }),
+            log: log.clone(),
+        });
+        assert_eq!(ctx.data(), &Json::Null);
+
+        let hbs = Registry::new();
+        let rendered = hbs.render_template_with_context(
+            "{{#with user}}{{name}} {{@root.title}}{{/with}}\
+             {{#each rows as |row|}} {{row.id}}{{#if @last}}{{../missing}}{{/if}}{{/each}}",
+            &ctx,
+        );
+        assert_eq!(rendered.unwrap(), "ning report 1 2");
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                "/user",
+                "/user/name",
+                "/title",
+                "/rows",
+                "/rows/0/id",
+                "/rows/1/id"
+            ]
+        );
+    }
+
+    /// A collection that can't be turned into JSON as a whole
+    struct Collection(Json);
+
+    impl ContextValue for Collection {
+        fn get(&self, key: &str) -> Option<Box<dyn ContextValue + '_>> {
+            match &self.0 {
+                Json::Array(_) | Json::Object(_) => ContextValue::get(&self.0, key)
+                    .map(|v| Box::new(Collection(v.to_json())) as Box<dyn ContextValue>),
+                _ => None,
+            }
+        }
+
+        fn to_json(&self) -> Json {
+            match &self.0 {
+                Json::Array(_) | Json::Object(_) => panic!("serialized a collection"),
+                v => v.clone(),
+            }
+        }
+
+        fn is_truthy(&self, include_zero: bool) -> bool {
+            ContextValue::is_truthy(&self.0, include_zero)
+        }
+
+        fn len(&self) -> Option<usize> {
+            ContextValue::len(&self.0)
+        }
+
+        fn keys(&self) -> Option<Vec<String>> {
+            ContextValue::keys(&self.0)
+        }
+    }
+
+    #[test]
+    fn test_lazy_block_helpers() {
+        let ctx = Context::lazy(Collection(json!({
+            "rows": [{"id": 1}, {"id": 2}],
+            "user": {"name": "ning", "age": 30},
+            "empty": []
+        })));
+
+        let hbs = Registry::new();
+        let rendered = hbs.render_template_with_context(
+            "{{#if rows}}{{#each rows as |row i|}}{{i}}:{{row.id}}{{#unless @last}},{{/unless}}{{/each}}{{/if}}\
+             |{{#with user}}{{name}}{{/with}}\
+             |{{#each user}}{{@key}}={{this}};{{/each}}\
+             |{{#each empty}}x{{else}}none{{/each}}{{#unless empty}} unless{{/unless}}",
+            &ctx,
+        );
+        assert_eq!(
+            rendered.unwrap(),
+            "0:1,1:2|ning|age=30;name=ning;|none unless"
+        );
+    }
+
+    #[test]
+    fn test_computed_values() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut ctx = Context::wraps(json!({"user": {"name": "ning"}})).unwrap();
+        let counter = calls.clone();
+        ctx.register_computed("user.tags", move |user: &Json| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(json!([user["name"], {"label": "admin"}]))
+        })
+        .unwrap();
+        ctx.register_computed("@root.now", |_: &Json| Ok(json!("today")))
+            .unwrap();
+        assert!(ctx
+            .register_computed("../now", |_: &Json| Ok(json!(1)))
+            .is_err());
+        assert!(ctx
+            .register_computed("@index", |_: &Json| Ok(json!(1)))
+            .is_err());
+
+        let hbs = Registry::new();
+        assert_eq!(
+            hbs.render_template_with_context("{{user.name}} {{now}}", &ctx)
+                .unwrap(),
+            "ning today"
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let template =
+            "{{#each user.tags}}{{#if @first}}{{this}}{{else}} {{label}}{{/if}}{{/each}}\
+                        {{#with user}} {{tags.1.label}} {{tags.[0]}}{{/with}}{{user.tags.2}}";
+        assert_eq!(
+            hbs.render_template_with_context(template, &ctx).unwrap(),
+            "ning admin admin ning"
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        hbs.render_template_with_context(template, &ctx).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
 }
@@ -1,6 +1,6 @@
 use crate::context::Context;
 use crate::decorators::{DecoratorDef, DecoratorResult};
-use crate::error::RenderError;
+use crate::error::{RenderError, RenderErrorReason};
 use crate::registry::Registry;
 use crate::render::{Decorator, RenderContext};
 
@@ -9,12 +9,12 @@ pub struct InlineDecorator;
 
 fn get_name<'reg: 'rc, 'rc>(d: &Decorator<'reg, 'rc>) -> Result<String, RenderError> {
     d.param(0)
-        .ok_or_else(|| RenderError::new("Param required for decorator \"inline\""))
+        .ok_or_else(|| RenderErrorReason::ParamNotFoundForIndex("inline".to_owned(), 0).into())
         .and_then(|v| {
             v.value()
                 .as_str()
                 .map(|v| v.to_owned())
-                .ok_or_else(|| RenderError::new("inline name must be string"))
+                .ok_or_else(|| RenderErrorReason::InvalidParamType("string".to_owned()).into())
         })
 }
 
@@ -30,7 +30,7 @@ impl DecoratorDef for InlineDecorator {
 
         let template = d
             .template()
-            .ok_or_else(|| RenderError::new("inline should have a block"))?;
+            .ok_or_else(|| RenderError::from(RenderErrorReason::BlockContentRequired))?;
 
         rc.set_partial(name, template);
         Ok(())
@@ -0,0 +1,284 @@
+use std::fmt;
+
+use crate::error::{RenderError, RenderErrorReason, TemplateError, TemplateErrorReason};
+use crate::registry::Registry;
+
+/// Lines shown before and after the offending line
+const CONTEXT_LINES: usize = 2;
+
+/// A template or render error prepared for display
+///
+/// It carries the offending template lines with the span of the failed
+/// expression, and a hint when there is one. `Display` prints it in a
+/// compiler like format for terminals, while the fields can be used to
+/// build other representations, like an HTML error page.
+///
+/// ```
+/// use handlebars::{Diagnostic, Handlebars};
+///
+/// let source = "<ul>\n{{#eahc items}}<li>{{this}}</li>{{/eahc}}\n</ul>";
+/// let mut handlebars = Handlebars::new();
+/// handlebars.register_template_string("list", source).unwrap();
+///
+/// let error = handlebars.render("list", &()).unwrap_err();
+/// let diagnostic = handlebars.diagnostic(&error).with_source(source);
+/// assert_eq!(diagnostic.help.as_deref(), Some("did you mean `each`?"));
+/// println!("{}", diagnostic);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// what went wrong
+    pub message: String,
+    pub template_name: Option<String>,
+    /// line of the error, starting from 1
+    pub line_no: Option<usize>,
+    /// column of the error, starting from 1
+    pub column_no: Option<usize>,
+    /// number of characters to underline from `column_no`
+    pub span: usize,
+    /// numbered template lines around the error, when the source is known
+    pub lines: Vec<(usize, String)>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    fn new(message: String, template_name: Option<String>) -> Diagnostic {
+        Diagnostic {
+            message,
+            template_name,
+            line_no: None,
+            column_no: None,
+            span: 1,
+            lines: Vec::new(),
+            help: None,
+        }
+    }
+
+    /// Attach the source of the template in error, to show its lines
+    pub fn with_source(mut self, source: &str) -> Diagnostic {
+        if let Some(line_no) = self.line_no {
+            let first = line_no.saturating_sub(CONTEXT_LINES).max(1);
+            self.lines = source
+                .lines()
+                .enumerate()
+                .map(|(idx, line)| (idx + 1, line.to_owned()))
+                .skip(first - 1)
+                .take(line_no + CONTEXT_LINES + 1 - first)
+                .collect();
+
+            let line = self
+                .lines
+                .iter()
+                .find(|(no, _)| *no == line_no)
+                .map(|(_, line)| line.as_str());
+            if let (Some(line), Some(col)) = (line, self.column_no) {
+                self.span = expression_span(line, col);
+            }
+        }
+        self
+    }
+
+    /// Set the help note
+    pub fn with_help<S: Into<String>>(mut self, help: S) -> Diagnostic {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+/// Length of the `{{...}}` expression starting at `col`, or 1
+fn expression_span(line: &str, col: usize) -> usize {
+    let rest: String = line.chars().skip(col.saturating_sub(1)).collect();
+    if rest.starts_with("{{") {
+        if let Some(end) = rest.find("}}") {
+            let end = end + rest[end..].chars().take_while(|c| *c == '}').count();
+            return rest[..end].chars().count();
+        }
+    }
+    1
+}
+
+impl<'a> From<&'a TemplateError> for Diagnostic {
+    fn from(e: &'a TemplateError) -> Diagnostic {
+        let mut d = Diagnostic::new(e.reason.to_string(), e.template_name.clone());
+        d.line_no = e.line_no;
+        d.column_no = e.column_no;
+        match e.reason {
+            TemplateErrorReason::MismatchingClosedHelper(ref open, _) => {
+                d.with_help(format!("did you mean `{{{{/{}}}}}`?", open))
+            }
+            TemplateErrorReason::MismatchingClosedDecorator(ref open, _) => {
+                d.with_help(format!("did you mean `{{{{/{}}}}}`?", open))
+            }
+            _ => d,
+        }
+    }
+}
+
+impl<'a> From<&'a RenderError> for Diagnostic {
+    fn from(e: &'a RenderError) -> Diagnostic {
+        if let RenderErrorReason::TemplateError(ref e) = e.reason() {
+            return Diagnostic::from(e);
+        }
+        let mut d = Diagnostic::new(e.desc.clone(), e.template_name.clone());
+        d.line_no = e.line_no;
+        d.column_no = e.column_no;
+        d
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "error: {}", self.message)?;
+
+        let width = self.lines.last().map_or(1, |(no, _)| no.to_string().len());
+        let pad = " ".repeat(width);
+        let name = self.template_name.as_deref().unwrap_or("Unnamed template");
+        match (self.line_no, self.column_no) {
+            (Some(line), Some(col)) => writeln!(f, "{}--> \"{}\":{}:{}", pad, name, line, col)?,
+            _ => writeln!(f, "{}--> \"{}\"", pad, name)?,
+        }
+
+        if !self.lines.is_empty() {
+            writeln!(f, "{} |", pad)?;
+            for (no, line) in &self.lines {
+                writeln!(f, "{:>width$} | {}", no, line, width = width)?;
+                if Some(*no) == self.line_no {
+                    let col = self.column_no.unwrap_or(1);
+                    writeln!(
+                        f,
+                        "{} | {}{}",
+                        pad,
+                        " ".repeat(col.saturating_sub(1)),
+                        "^".repeat(self.span.max(1))
+                    )?;
+                }
+            }
+            writeln!(f, "{} |", pad)?;
+        }
+
+        if let Some(ref help) = self.help {
+            writeln!(f, "{} = help: {}", pad, help)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn diagnostic(registry: &Registry<'_>, e: &RenderError) -> Diagnostic {
+    let d = Diagnostic::from(e);
+    let suggestion = match e.reason() {
+        RenderErrorReason::HelperNotFound(name) => {
+            suggest(name, registry.helper_names().into_iter())
+        }
+        RenderErrorReason::DecoratorNotFound(name) => {
+            suggest(name, registry.decorator_names().into_iter())
+        }
+        RenderErrorReason::TemplateNotFound(name) | RenderErrorReason::PartialNotFound(name) => {
+            suggest(name, registry.get_templates().keys().map(String::as_str))
+        }
+        _ => None,
+    };
+    match suggestion {
+        Some(s) => d.with_help(format!("did you mean `{}`?", s)),
+        None => d,
+    }
+}
+
+/// The candidate closest to `name`, if it's close enough to be a typo
+fn suggest<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let max = (name.chars().count() / 3).max(1);
+    candidates
+        .filter(|c| *c != name)
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(distance, _)| *distance <= max)
+        .min()
+        .map(|(_, c)| c)
+}
+
+/// Levenshtein distance, counting swapped adjacent characters as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{edit_distance, suggest, Diagnostic};
+    use crate::registry::Registry;
+    use crate::template::Template;
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(edit_distance("each", "eahc"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "if"), 2);
+        let names = ["each", "if", "unless", "with"];
+        assert_eq!(suggest("eahc", names.iter().cloned()), Some("each"));
+        assert_eq!(suggest("unles", names.iter().cloned()), Some("unless"));
+        assert_eq!(suggest("foo", names.iter().cloned()), None);
+    }
+
+    #[test]
+    fn test_render_error_diagnostic() {
+        let source = "<ul>\n  {{#eahc items}}\n    <li>{{this}}</li>\n  {{/eahc}}\n</ul>";
+        let mut hbs = Registry::new();
+        hbs.register_template_string("list", source).unwrap();
+
+        let e = hbs.render("list", &()).unwrap_err();
+        let d = hbs.diagnostic(&e).with_source(source);
+        assert_eq!(d.span, 15);
+        assert_eq!(
+            d.to_string(),
+            r#"error: Helper not defined: "eahc"
+ --> "list":2:3
+  |
+1 | <ul>
+2 |   {{#eahc items}}
+  |   ^^^^^^^^^^^^^^^
+3 |     <li>{{this}}</li>
+4 |   {{/eahc}}
+  |
+  = help: did you mean `each`?
+"#
+        );
+
+        let e = hbs.render("lsit", &()).unwrap_err();
+        let d = hbs.diagnostic(&e);
+        assert_eq!(d.help.as_deref(), Some("did you mean `list`?"));
+        assert_eq!(d.to_string(), "error: Template not found: lsit\n --> \"Unnamed template\"\n  = help: did you mean `list`?\n");
+    }
+
+    #[test]
+    fn test_template_error_diagnostic() {
+        let source = "{{#if a}}\n{{/fi}}";
+        let e = Template::compile_with_name(source, "t".to_owned()).unwrap_err();
+        let d = Diagnostic::from(&e).with_source(source);
+        assert_eq!(d.line_no, Some(2));
+        assert_eq!(
+            d.lines,
+            vec![(1, "{{#if a}}".to_owned()), (2, "{{/fi}}".to_owned())]
+        );
+        assert_eq!(d.help.as_deref(), Some("did you mean `{{/if}}`?"));
+    }
+}
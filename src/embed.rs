@@ -0,0 +1,126 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::error::TemplateError;
+use crate::sources::template_files;
+use crate::template::Template;
+
+/// Embed a directory of templates into your binary at compile time
+///
+/// Call this from your `build.rs`. Every template file under `dir_path`
+/// with `tpl_extension` is parsed, so a syntax error fails the build, and a
+/// rust source file is written to `out_path`. That file holds one expression,
+/// a `&[(&str, &str)]` of template names and sources pulled in with
+/// `include_str!`, to be passed to `Registry::register_embed_templates`.
+/// Templates are named exactly like `register_templates_directory` does.
+///
+/// Templates are still compiled when they are registered at runtime, the
+/// build time parsing only validates them.
+///
+/// This function is not available by default.
+/// You will need to enable the `dir_source` feature to use it, as a
+/// `build-dependencies` of your crate.
+///
+/// In `build.rs`:
+///
+/// ```no_run
+/// use std::env;
+/// use std::path::Path;
+///
+/// let out_dir = env::var("OUT_DIR").unwrap();
+/// handlebars::embed_templates_directory(
+///     ".hbs",
+///     "templates",
+///     Path::new(&out_dir).join("templates.rs"),
+/// )
+/// .unwrap();
+/// ```
+///
+/// In your crate:
+///
+/// ```ignore
+/// static TEMPLATES: &[(&str, &str)] = include!(concat!(env!("OUT_DIR"), "/templates.rs"));
+///
+/// let mut handlebars = Handlebars::new();
+/// handlebars.register_embed_templates(TEMPLATES).unwrap();
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "dir_source")))]
+pub fn embed_templates_directory<P, Q>(
+    tpl_extension: &str,
+    dir_path: P,
+    out_path: Q,
+) -> Result<(), TemplateError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let dir_path = dir_path.as_ref();
+    let out_path = out_path.as_ref();
+    let io_error = |e| TemplateError::from((e, out_path.to_string_lossy().into_owned()));
+
+    let mut code = String::from("&[\n");
+    for (tpl_name, tpl_path) in template_files(tpl_extension, dir_path)? {
+        let tpl_str = fs::read_to_string(&tpl_path)
+            .map_err(|e| TemplateError::from((e, tpl_name.clone())))?;
+        Template::compile_with_name(tpl_str, tpl_name.clone())?;
+
+        // relative paths in include_str! would resolve against the includer
+        let abs_path =
+            fs::canonicalize(&tpl_path).map_err(|e| TemplateError::from((e, tpl_name.clone())))?;
+        code.push_str(&format!(
+            "    ({:?}, include_str!({:?})),\n",
+            tpl_name,
+            abs_path.to_string_lossy()
+        ));
+    }
+    code.push_str("]\n");
+
+    let mut out = BufWriter::new(File::create(out_path).map_err(io_error)?);
+    out.write_all(code.as_bytes()).map_err(io_error)?;
+    out.flush().map_err(io_error)?;
+
+    println!("cargo:rerun-if-changed={}", dir_path.to_string_lossy());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::embed_templates_directory;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_embed_templates_directory() {
+        let dir = tempdir().unwrap();
+        let tpl_dir = dir.path().join("templates");
+        fs::create_dir_all(tpl_dir.join("partials")).unwrap();
+        {
+            let mut file1 = File::create(tpl_dir.join("index.hbs")).unwrap();
+            write!(file1, "{{{{> partials/nav}}}}").unwrap();
+            let mut file2 = File::create(tpl_dir.join("partials/nav.hbs")).unwrap();
+            write!(file2, "<nav></nav>").unwrap();
+            let mut file3 = File::create(tpl_dir.join(".hidden.hbs")).unwrap();
+            write!(file3, "{{{{#if}}}}").unwrap();
+        }
+
+        let out_path = dir.path().join("templates.rs");
+        embed_templates_directory(".hbs", &tpl_dir, &out_path).unwrap();
+
+        let code = fs::read_to_string(&out_path).unwrap();
+        assert!(code.starts_with("&[\n"));
+        assert!(code.contains("(\"index\", include_str!("));
+        assert!(code.contains("(\"partials/nav\", include_str!("));
+        assert!(!code.contains("hidden"));
+
+        {
+            let mut file4 = File::create(tpl_dir.join("broken.hbs")).unwrap();
+            write!(file4, "{{{{#if}}}}").unwrap();
+        }
+        let err = embed_templates_directory(".hbs", &tpl_dir, &out_path).unwrap_err();
+        assert_eq!(err.template_name.as_deref(), Some("broken"));
+
+        dir.close().unwrap();
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt;
 use std::io::Error as IOError;
@@ -5,6 +6,9 @@ use std::num::ParseIntError;
 use std::string::FromUtf8Error;
 
 use serde_json::error::Error as SerdeError;
+use serde_json::value::Value as Json;
+
+use crate::limits::LimitError;
 #[cfg(feature = "dir_source")]
 use walkdir::Error as WalkdirError;
 
@@ -18,6 +22,7 @@ pub struct RenderError {
     pub template_name: Option<String>,
     pub line_no: Option<usize>,
     pub column_no: Option<usize>,
+    reason: Box<RenderErrorReason>,
     cause: Option<Box<dyn Error + Send + Sync + 'static>>,
     unimplemented: bool,
 }
@@ -40,62 +45,84 @@ impl fmt::Display for RenderError {
 
 impl Error for RenderError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        self.cause
-            .as_ref()
-            .map(|e| e.as_ref() as &(dyn Error + 'static))
+        self.reason.source().or_else(|| {
+            self.cause
+                .as_ref()
+                .map(|e| e.as_ref() as &(dyn Error + 'static))
+        })
+    }
+}
+
+impl From<RenderErrorReason> for RenderError {
+    fn from(reason: RenderErrorReason) -> RenderError {
+        RenderError {
+            desc: reason.to_string(),
+            reason: Box::new(reason),
+            ..Default::default()
+        }
     }
 }
 
 impl From<IOError> for RenderError {
     fn from(e: IOError) -> RenderError {
-        RenderError::from_error("Cannot generate output.", e)
+        // a limited output reports the exceeded limit as an io error
+        match e
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<LimitError>())
+        {
+            Some(limit) => RenderError::from(limit.clone()),
+            None => RenderErrorReason::IoError(e).into(),
+        }
+    }
+}
+
+impl From<LimitError> for RenderError {
+    fn from(e: LimitError) -> RenderError {
+        RenderErrorReason::LimitExceeded(e).into()
     }
 }
 
 impl From<SerdeError> for RenderError {
     fn from(e: SerdeError) -> RenderError {
-        RenderError::from_error("Failed to access JSON data.", e)
+        RenderErrorReason::SerdeError(e).into()
     }
 }
 
 impl From<FromUtf8Error> for RenderError {
     fn from(e: FromUtf8Error) -> RenderError {
-        RenderError::from_error("Failed to generate bytes.", e)
+        RenderErrorReason::Utf8Error(e).into()
     }
 }
 
 impl From<ParseIntError> for RenderError {
     fn from(e: ParseIntError) -> RenderError {
-        RenderError::from_error("Cannot access array/vector with string index.", e)
+        RenderErrorReason::InvalidJsonIndex(e).into()
     }
 }
 
 impl From<TemplateError> for RenderError {
     fn from(e: TemplateError) -> RenderError {
-        RenderError::from_error("Failed to parse template.", e)
+        RenderErrorReason::TemplateError(e).into()
     }
 }
 
 #[cfg(feature = "script_helper")]
 impl From<Box<EvalAltResult>> for RenderError {
     fn from(e: Box<EvalAltResult>) -> RenderError {
-        RenderError::from_error("Cannot convert data to Rhai dynamic", e)
+        RenderErrorReason::ScriptEvalError(e).into()
     }
 }
 
 #[cfg(feature = "script_helper")]
 impl From<ScriptError> for RenderError {
     fn from(e: ScriptError) -> RenderError {
-        RenderError::from_error("Failed to load rhai script", e)
+        RenderErrorReason::ScriptLoadError(e).into()
     }
 }
 
 impl RenderError {
     pub fn new<T: AsRef<str>>(desc: T) -> RenderError {
-        RenderError {
-            desc: desc.as_ref().to_owned(),
-            ..Default::default()
-        }
+        RenderErrorReason::Other(desc.as_ref().to_owned()).into()
     }
 
     pub(crate) fn unimplemented() -> RenderError {
@@ -106,11 +133,7 @@ impl RenderError {
     }
 
     pub fn strict_error(path: Option<&String>) -> RenderError {
-        let msg = match path {
-            Some(path) => format!("Variable {:?} not found in strict mode.", path),
-            None => "Value is missing in strict mode".to_owned(),
-        };
-        RenderError::new(&msg)
+        RenderErrorReason::MissingVariable(path.cloned()).into()
     }
 
     pub fn from_error<E>(error_info: &str, cause: E) -> RenderError
@@ -123,6 +146,20 @@ impl RenderError {
         e
     }
 
+    /// Returns the reason of this error, for telling errors apart without
+    /// matching on the description
+    pub fn reason(&self) -> &RenderErrorReason {
+        &self.reason
+    }
+
+    /// Returns the render limit that stopped the rendering, if any
+    pub fn limit_exceeded(&self) -> Option<&LimitError> {
+        match *self.reason {
+            RenderErrorReason::LimitExceeded(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
     #[inline]
     pub(crate) fn is_unimplemented(&self) -> bool {
         self.unimplemented
@@ -130,36 +167,191 @@ impl RenderError {
 }
 
 quick_error! {
-/// Template parsing error
+/// Render error reason
     #[derive(Debug)]
-    pub enum TemplateErrorReason {
-        MismatchingClosedHelper(open: String, closed: String) {
-            display("helper {:?} was opened, but {:?} is closing",
-                open, closed)
+    #[non_exhaustive]
+    pub enum RenderErrorReason {
+        TemplateNotFound(name: String) {
+            display("Template not found: {}", name)
+        }
+        PartialNotFound(name: String) {
+            display("Partial not found: {}", name)
+        }
+        HelperNotFound(name: String) {
+            display("Helper not defined: {:?}", name)
+        }
+        DecoratorNotFound(name: String) {
+            display("Decorator not defined: {:?}", name)
+        }
+        MissingVariable(path: Option<String>) {
+            display("{}", match path {
+                Some(path) => format!("Variable {:?} not found in strict mode.", path),
+                None => "Value is missing in strict mode".to_owned(),
+            })
+        }
+        ParamNotFoundForIndex(helper: String, index: usize) {
+            display("Param {} not found for helper \"{}\"", index, helper)
+        }
+        ParamNotFoundForName(helper: String, name: String) {
+            display("`{}` helper: Couldn't read parameter {}", helper, name)
+        }
+        ParamTypeMismatchForName(helper: String, name: String, expected: String) {
+            display("`{}` helper: Couldn't convert parameter {} to type `{}`", helper, name, expected)
+        }
+        HashTypeMismatchForName(helper: String, name: String, expected: String) {
+            display("`{}` helper: Couldn't convert hash {} to type `{}`", helper, name, expected)
+        }
+        ParamValueMismatchForName(helper: String, name: String, expected: String, value: Json, params: Vec<Json>) {
+            display("`{}` helper: Couldn't convert parameter {} to type `{}`. \
+                     It's {:?} as JSON. Got these params: {:?}",
+                    helper, name, expected, value, params)
+        }
+        HashValueMismatchForName(helper: String, name: String, expected: String, value: Json, hash: BTreeMap<String, Json>) {
+            display("`{}` helper: Couldn't convert hash {} to type `{}`. \
+                     It's {:?} as JSON. Got these hash: {:?}",
+                    helper, name, expected, value, hash)
+        }
+        InvalidParamType(expected: String) {
+            display("Invalid param type, {} expected", expected)
+        }
+        ResultTooLarge(helper: String, limit: usize) {
+            display("`{}` helper: Result is larger than {}", helper, limit)
+        }
+        DivisionByZero(helper: String) {
+            display("`{}` helper: Division by zero", helper)
+        }
+        InvalidSubexpression {
+            display("Subexpression is not an expression")
+        }
+        BlockContentRequired {
+            display("Block content required")
+        }
+        BlockRequired(helper: String) {
+            display("`{}` can only be used in a block", helper)
+        }
+        IteratorConsumed(path: String) {
+            display("Iterator at `{}` is already consumed, it can only be iterated once", path)
+        }
+        #[cfg(feature = "async")]
+        AsyncHelperNotResolved(helper: String) {
+            display("Helper {:?} is async, render with `render_async` instead", helper)
+        }
+        UnsafeEscapeContext(context: String) {
+            display("Cannot safely render value in {}", context)
+        }
+        EscapeContextMismatch(helper: String) {
+            display("Branches of `{}` end in different HTML contexts", helper)
+        }
+        UnsafeUrl(url: String) {
+            display("Unsafe URL {:?}", url)
+        }
+        CannotIncludeSelf {
+            display("Cannot include self in >")
+        }
+        InvalidLoggingLevel(level: String) {
+            display("Unsupported logging level {}", level)
+        }
+        InvalidJsonPath(path: String) {
+            display("Invalid JSON path: {}", path)
+        }
+        InvalidJsonIndex(err: ParseIntError) {
+            display("Cannot access array/vector with string index.")
+            source(err)
+        }
+        LimitExceeded(err: LimitError) {
+            display("{}", err)
+            source(err)
+        }
+        IoError(err: IOError) {
+            display("Cannot generate output.")
+            source(err)
+        }
+        SerdeError(err: SerdeError) {
+            display("Failed to access JSON data.")
+            source(err)
+        }
+        Utf8Error(err: FromUtf8Error) {
+            display("Failed to generate bytes.")
+            source(err)
+        }
+        TemplateError(err: TemplateError) {
+            display("Failed to parse template.")
+            source(err)
         }
-        MismatchingClosedDecorator(open: String, closed: String) {
-            display("decorator {:?} was opened, but {:?} is closing",
-                open, closed)
+        #[cfg(feature = "script_helper")]
+        ScriptEvalError(err: Box<EvalAltResult>) {
+            display("Cannot convert data to Rhai dynamic")
+            source(err)
         }
-        InvalidSyntax {
-            display("invalid handlebars syntax.")
+        #[cfg(feature = "script_helper")]
+        ScriptLoadError(err: ScriptError) {
+            display("Failed to load rhai script")
+            source(err)
         }
-        InvalidParam (param: String) {
-            display("invalid parameter {:?}", param)
+        #[cfg(feature = "yaml_helper")]
+        YamlError(err: serde_yaml::Error) {
+            display("Failed to write YAML")
+            source(err)
         }
-        NestedSubexpression {
-            display("nested subexpression is not supported")
+        #[cfg(feature = "toml_helper")]
+        TomlError(err: toml::ser::Error) {
+            display("Failed to write TOML")
+            source(err)
         }
-        IoError(err: IOError, name: String) {
-             display("Template \"{}\": {}", name, err)
+        Other(desc: String) {
+            display("{}", desc)
         }
-        #[cfg(feature = "dir_source")]
-        WalkdirError(err: WalkdirError) {
-             display("Walk dir error: {}", err)
+    }
+}
+
+impl Default for RenderErrorReason {
+    fn default() -> RenderErrorReason {
+        RenderErrorReason::Other(String::new())
+    }
+}
+
+// the impls of quick_error match on the deprecated `NestedSubexpression`
+#[allow(deprecated)]
+mod template_error_reason {
+    use std::io::Error as IOError;
+    #[cfg(feature = "dir_source")]
+    use walkdir::Error as WalkdirError;
+
+    quick_error! {
+        /// Template parsing error
+        #[derive(Debug)]
+        pub enum TemplateErrorReason {
+            MismatchingClosedHelper(open: String, closed: String) {
+                display("helper {:?} was opened, but {:?} is closing",
+                    open, closed)
+            }
+            MismatchingClosedDecorator(open: String, closed: String) {
+                display("decorator {:?} was opened, but {:?} is closing",
+                    open, closed)
+            }
+            InvalidSyntax {
+                display("invalid handlebars syntax.")
+            }
+            InvalidParam (param: String) {
+                display("invalid parameter {:?}", param)
+            }
+            #[deprecated(note = "nested subexpressions are supported, this is never returned")]
+            NestedSubexpression {
+                display("nested subexpression is not supported")
+            }
+            IoError(err: IOError, name: String) {
+                 display("Template \"{}\": {}", name, err)
+            }
+            #[cfg(feature = "dir_source")]
+            WalkdirError(err: WalkdirError) {
+                 display("Walk dir error: {}", err)
+            }
         }
     }
 }
 
+pub use self::template_error_reason::TemplateErrorReason;
+
 /// Error on parsing template.
 #[derive(Debug)]
 pub struct TemplateError {
@@ -270,3 +462,61 @@ quick_error! {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::RenderErrorReason;
+    use crate::registry::Registry;
+
+    #[test]
+    fn test_render_error_reason() {
+        let mut hbs = Registry::new();
+        hbs.register_template_string("self", "{{> self}}").unwrap();
+
+        let e = hbs.render("missing", &()).unwrap_err();
+        assert!(
+            matches!(e.reason(), RenderErrorReason::TemplateNotFound(name) if name == "missing")
+        );
+        assert_eq!(e.desc, "Template not found: missing");
+
+        let e = hbs.render_template("{{foo 1}}", &()).unwrap_err();
+        assert!(matches!(e.reason(), RenderErrorReason::HelperNotFound(name) if name == "foo"));
+
+        let e = hbs.render_template("{{#if}}{{/if}}", &()).unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            RenderErrorReason::ParamNotFoundForIndex(helper, 0) if helper == "if"
+        ));
+
+        let e = hbs.render_template("{{len}}", &()).unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            RenderErrorReason::ParamNotFoundForName(helper, name) if helper == "len" && name == "x"
+        ));
+
+        let e = hbs.render("self", &()).unwrap_err();
+        assert!(matches!(e.reason(), RenderErrorReason::CannotIncludeSelf));
+
+        let e = hbs.render_template("{{*foo}}", &()).unwrap_err();
+        assert!(matches!(e.reason(), RenderErrorReason::DecoratorNotFound(name) if name == "foo"));
+
+        let e = hbs.render_template("{{#if}}", &()).unwrap_err();
+        assert!(matches!(e.reason(), RenderErrorReason::TemplateError(_)));
+        assert!(std::error::Error::source(&e).is_some());
+
+        hbs.set_strict_mode(true);
+        let e = hbs
+            .render_template("{{a.b}}", &json!({"a": {}}))
+            .unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            RenderErrorReason::MissingVariable(Some(path)) if path == "a.b"
+        ));
+        assert_eq!(
+            e.to_string(),
+            "Error rendering \"Unnamed template\" line 1, col 1: Variable \"a.b\" not found in strict mode."
+        );
+    }
+}
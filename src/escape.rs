@@ -0,0 +1,1029 @@
+//! Context-aware escaping
+//!
+//! The HTML context of each expression is tracked from the raw text before
+//! it, so values are escaped differently in element text, quoted attributes,
+//! URLs, scripts and styles. The context after a block or a partial is
+//! computed from their templates, not from what they rendered.
+
+use std::fmt::Write;
+
+use serde_json::value::Value as Json;
+
+use crate::error::{RenderError, RenderErrorReason};
+use crate::partial::PARTIAL_BLOCK;
+use crate::registry::Registry;
+use crate::render::RenderContext;
+use crate::support::str::escape_html;
+use crate::template::{DecoratorTemplate, Parameter, Template, TemplateElement};
+
+/// URL schemes allowed at the start of an URL attribute
+const SAFE_URL_SCHEMES: [&str; 5] = ["http", "https", "mailto", "tel", "ftp"];
+
+/// Keywords after which a `/` starts a js regexp rather than a division
+const REGEXP_KEYWORDS: [&str; 14] = [
+    "break",
+    "case",
+    "continue",
+    "delete",
+    "do",
+    "else",
+    "finally",
+    "in",
+    "instanceof",
+    "return",
+    "throw",
+    "try",
+    "typeof",
+    "void",
+];
+
+/// Elements whose content is not HTML text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Element {
+    Normal,
+    Script,
+    Style,
+}
+
+/// Kinds of attribute values
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Attr {
+    Normal,
+    Url,
+    Script,
+    Style,
+    /// HTML documents, like `srcdoc`, where values are refused
+    Html,
+}
+
+/// Where the output is in js code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Js {
+    /// between tokens, with whether a `/` would start a regexp rather than
+    /// a division, `None` when it depends on the branch a block took
+    Code(Option<bool>),
+    /// inside a string quoted by the char
+    Str(char),
+    /// inside a regexp literal, `true` in a char class
+    Regexp(bool),
+    /// inside comments, with the state of the code before them
+    LineComment(Option<bool>),
+    BlockComment(Option<bool>),
+    /// after a `/` that may start a regexp or be a division
+    Unknown,
+}
+
+/// The state at the start of a script
+const JS_START: Js = Js::Code(Some(true));
+
+/// Part of an URL attribute value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum UrlPart {
+    /// the scheme is still to come: only spaces, scheme chars and values
+    /// were written, which are checked with `push_url` when rendering
+    Start,
+    Path,
+    /// after `?` or `#`
+    Query,
+}
+
+/// Where the output is in the HTML document
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum HtmlContext {
+    Text,
+    Comment,
+    /// script content
+    Script(Js),
+    Style,
+    /// inside a tag, between attributes
+    Tag(Element),
+    AttrName(Element, String),
+    AfterAttrName(Element, String),
+    /// after `=`, before the value
+    BeforeValue(Element, Attr),
+    Value {
+        element: Element,
+        attr: Attr,
+        /// `None` for unquoted values
+        quote: Option<char>,
+        url: UrlPart,
+        /// where the output is in the js code of event handlers
+        js: Js,
+    },
+}
+
+fn element_of(name: &str) -> Element {
+    match name {
+        "script" => Element::Script,
+        "style" => Element::Style,
+        _ => Element::Normal,
+    }
+}
+
+fn attr_of(name: &str) -> Attr {
+    match name {
+        "style" => Attr::Style,
+        "href" | "src" | "action" | "formaction" | "cite" | "background" | "poster"
+        | "xlink:href" | "data" | "codebase" | "longdesc" | "manifest" | "ping" | "usemap"
+        | "icon" | "srcset" => Attr::Url,
+        "srcdoc" => Attr::Html,
+        _ if name.starts_with("on") => Attr::Script,
+        _ => Attr::Normal,
+    }
+}
+
+fn content_of(element: Element) -> HtmlContext {
+    match element {
+        Element::Normal => HtmlContext::Text,
+        Element::Script => HtmlContext::Script(JS_START),
+        Element::Style => HtmlContext::Style,
+    }
+}
+
+fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+    s.len() >= prefix.len() && s.as_bytes()[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())
+}
+
+/// Length of the text up to and including the next `>`
+fn until_tag_end(s: &str) -> usize {
+    s.find('>').map_or(s.len(), |i| i + 1)
+}
+
+fn is_js_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Length of a backslash and the char it escapes, which can't start the
+/// end tag of the script
+fn js_escape_len(s: &str) -> usize {
+    1 + s[1..]
+        .chars()
+        .next()
+        .filter(|&c| c != '<')
+        .map_or(0, char::len_utf8)
+}
+
+/// Move over the first chars of js code `s`, returning the new state and
+/// the number of bytes consumed
+///
+/// Whether a `/` starts a regexp is guessed from the token before it, like
+/// browsers do for most scripts.
+fn js_step(js: Js, s: &str) -> (Js, usize) {
+    let c = s.chars().next().unwrap();
+    let n = c.len_utf8();
+    match js {
+        Js::Code(regexp) => match c {
+            '"' | '\'' | '`' => (Js::Str(c), n),
+            '/' if s[n..].starts_with('/') => (Js::LineComment(regexp), 2),
+            '/' if s[n..].starts_with('*') => (Js::BlockComment(regexp), 2),
+            '/' => match regexp {
+                Some(true) => (Js::Regexp(false), n),
+                Some(false) => (Js::Code(Some(true)), n),
+                None => (Js::Unknown, n),
+            },
+            // postfix increments end an expression
+            '+' | '-' if s[n..].starts_with(c) => (Js::Code(Some(false)), 2),
+            ')' | ']' => (Js::Code(Some(false)), n),
+            _ if c.is_whitespace() => (js, n),
+            _ if is_js_ident(c) => {
+                let len = s.find(|c| !is_js_ident(c)).unwrap_or(s.len());
+                let is_keyword = REGEXP_KEYWORDS.contains(&&s[..len]);
+                (Js::Code(Some(is_keyword)), len)
+            }
+            _ => (Js::Code(Some(true)), n),
+        },
+        Js::Str(quote) => match c {
+            '\\' => (js, js_escape_len(s)),
+            _ if c == quote => (Js::Code(Some(false)), n),
+            _ => (js, n),
+        },
+        Js::Regexp(class) => match c {
+            '\\' => (js, js_escape_len(s)),
+            '[' => (Js::Regexp(true), n),
+            ']' => (Js::Regexp(false), n),
+            '/' if !class => (Js::Code(Some(false)), n),
+            _ => (js, n),
+        },
+        Js::LineComment(regexp) => match c {
+            '\n' | '\r' | '\u{2028}' | '\u{2029}' => (Js::Code(regexp), n),
+            _ => (js, n),
+        },
+        Js::BlockComment(regexp) if s.starts_with("*/") => (Js::Code(regexp), 2),
+        Js::BlockComment(_) | Js::Unknown => (js, n),
+    }
+}
+
+impl HtmlContext {
+    /// The context after some raw text
+    pub(crate) fn advance(mut self, text: &str) -> HtmlContext {
+        let mut rest = text;
+        while !rest.is_empty() {
+            let (next, consumed) = self.step(rest);
+            self = next;
+            rest = &rest[consumed..];
+        }
+        self
+    }
+
+    /// The context after a value written by an expression
+    ///
+    /// Values in js code are written as strings, after which a `/` is a
+    /// division. The scheme of an URL stays unknown after a value, as it
+    /// may be continued by the next ones.
+    pub(crate) fn after_value(self) -> HtmlContext {
+        self.with_code(Some(false))
+    }
+
+    /// The context with `regexp` as the meaning of a `/` in js code
+    fn with_code(self, regexp: Option<bool>) -> HtmlContext {
+        match self {
+            HtmlContext::Script(Js::Code(_)) => HtmlContext::Script(Js::Code(regexp)),
+            HtmlContext::Value {
+                element,
+                attr: Attr::Script,
+                quote,
+                url,
+                js: Js::Code(_),
+            } => HtmlContext::Value {
+                element,
+                attr: Attr::Script,
+                quote,
+                url,
+                js: Js::Code(regexp),
+            },
+            ctx => ctx,
+        }
+    }
+
+    /// Whether this is the start of a quoted URL value, see `UrlPart::Start`
+    fn is_url_start(&self) -> bool {
+        matches!(
+            *self,
+            HtmlContext::Value {
+                attr: Attr::Url,
+                quote: Some(_),
+                url: UrlPart::Start,
+                ..
+            }
+        )
+    }
+
+    /// Check raw `text` written in this context, when it is part of an URL
+    /// whose scheme is still unknown, see `push_url`
+    pub(crate) fn check_raw(&self, text: &str, url_prefix: &mut String) -> Result<(), RenderError> {
+        if !self.is_url_start() && !text.contains(&['"', '\''][..]) {
+            return Ok(());
+        }
+        let mut ctx = self.clone();
+        // where the text of the URL value starts
+        let mut start = if ctx.is_url_start() { Some(0) } else { None };
+        let mut pos = 0;
+        while pos < text.len() {
+            let (next, consumed) = ctx.step(&text[pos..]);
+            pos += consumed;
+            match (start, next.is_url_start()) {
+                (None, true) => {
+                    url_prefix.clear();
+                    start = Some(pos);
+                }
+                (Some(s), false) => {
+                    push_url(url_prefix, &text[s..pos])?;
+                    start = None;
+                }
+                _ => {}
+            }
+            ctx = next;
+        }
+        match start {
+            Some(s) => push_url(url_prefix, &text[s..]),
+            None => Ok(()),
+        }
+    }
+
+    /// Move over the first chars of `s`, returning the new context and the
+    /// number of bytes consumed
+    fn step(self, s: &str) -> (HtmlContext, usize) {
+        let c = s.chars().next().unwrap();
+        let n = c.len_utf8();
+        match self {
+            HtmlContext::Text => {
+                if s.starts_with("<!--") {
+                    (HtmlContext::Comment, 4)
+                } else if s.starts_with("</") {
+                    (HtmlContext::Text, until_tag_end(s))
+                } else if c == '<' {
+                    let name_len = s[1..]
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+                        .unwrap_or(s.len() - 1);
+                    if name_len > 0 && s[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+                        let name = s[1..=name_len].to_ascii_lowercase();
+                        (HtmlContext::Tag(element_of(&name)), 1 + name_len)
+                    } else {
+                        (HtmlContext::Text, 1)
+                    }
+                } else {
+                    (HtmlContext::Text, n)
+                }
+            }
+            HtmlContext::Comment => match s.find("-->") {
+                Some(i) => (HtmlContext::Text, i + 3),
+                None => (HtmlContext::Comment, s.len()),
+            },
+            HtmlContext::Script(js) => {
+                if starts_with_ignore_case(s, "</script") {
+                    (HtmlContext::Text, until_tag_end(s))
+                } else {
+                    let (js, consumed) = js_step(js, s);
+                    (HtmlContext::Script(js), consumed)
+                }
+            }
+            HtmlContext::Style => {
+                if starts_with_ignore_case(s, "</style") {
+                    (HtmlContext::Text, until_tag_end(s))
+                } else {
+                    (HtmlContext::Style, n)
+                }
+            }
+            HtmlContext::Tag(element) => match c {
+                '>' => (content_of(element), n),
+                '/' => (HtmlContext::Tag(element), n),
+                _ if c.is_whitespace() => (HtmlContext::Tag(element), n),
+                _ => (
+                    HtmlContext::AttrName(element, c.to_ascii_lowercase().to_string()),
+                    n,
+                ),
+            },
+            HtmlContext::AttrName(element, mut name) => match c {
+                '=' => (HtmlContext::BeforeValue(element, attr_of(&name)), n),
+                '>' => (content_of(element), n),
+                '/' => (HtmlContext::Tag(element), n),
+                _ if c.is_whitespace() => (HtmlContext::AfterAttrName(element, name), n),
+                _ => {
+                    name.push(c.to_ascii_lowercase());
+                    (HtmlContext::AttrName(element, name), n)
+                }
+            },
+            HtmlContext::AfterAttrName(element, name) => match c {
+                '=' => (HtmlContext::BeforeValue(element, attr_of(&name)), n),
+                '>' => (content_of(element), n),
+                '/' => (HtmlContext::Tag(element), n),
+                _ if c.is_whitespace() => (HtmlContext::AfterAttrName(element, name), n),
+                _ => (
+                    HtmlContext::AttrName(element, c.to_ascii_lowercase().to_string()),
+                    n,
+                ),
+            },
+            HtmlContext::BeforeValue(element, attr) => match c {
+                '>' => (content_of(element), n),
+                '"' | '\'' => (
+                    HtmlContext::Value {
+                        element,
+                        attr,
+                        quote: Some(c),
+                        url: UrlPart::Start,
+                        js: JS_START,
+                    },
+                    n,
+                ),
+                _ if c.is_whitespace() => (HtmlContext::BeforeValue(element, attr), n),
+                _ => HtmlContext::Value {
+                    element,
+                    attr,
+                    quote: None,
+                    url: UrlPart::Start,
+                    js: JS_START,
+                }
+                .step(s),
+            },
+            HtmlContext::Value {
+                element,
+                attr,
+                quote,
+                url,
+                js,
+            } => {
+                if quote == Some(c) {
+                    (HtmlContext::Tag(element), n)
+                } else if quote.is_none() && c == '>' {
+                    (content_of(element), n)
+                } else if quote.is_none() && c.is_whitespace() {
+                    (HtmlContext::Tag(element), n)
+                } else if attr == Attr::Script {
+                    // tokens end with the value
+                    let end = s
+                        .find(|c: char| match quote {
+                            Some(q) => c == q,
+                            None => c == '>' || c.is_whitespace(),
+                        })
+                        .unwrap_or(s.len());
+                    let (js, consumed) = js_step(js, &s[..end]);
+                    (
+                        HtmlContext::Value {
+                            element,
+                            attr,
+                            quote,
+                            url,
+                            js,
+                        },
+                        consumed,
+                    )
+                } else {
+                    // browsers skip spaces and controls before the scheme
+                    let url = match url {
+                        UrlPart::Query => UrlPart::Query,
+                        _ if c == '?' || c == '#' => UrlPart::Query,
+                        UrlPart::Start
+                            if c.is_alphanumeric()
+                                || c.is_whitespace()
+                                || c.is_control()
+                                || "+-.".contains(c) =>
+                        {
+                            UrlPart::Start
+                        }
+                        _ => UrlPart::Path,
+                    };
+                    (
+                        HtmlContext::Value {
+                            element,
+                            attr,
+                            quote,
+                            url,
+                            js,
+                        },
+                        n,
+                    )
+                }
+            }
+        }
+    }
+
+    /// Escape a value written in this context
+    ///
+    /// `url_prefix` is what was written of the URL value before, see
+    /// `push_url`.
+    pub(crate) fn escape(
+        &self,
+        value: &str,
+        url_prefix: &mut String,
+    ) -> Result<String, RenderError> {
+        match *self {
+            HtmlContext::Text | HtmlContext::Comment => Ok(escape_html(value)),
+            HtmlContext::Script(js) => escape_in_js(js, value),
+            HtmlContext::Style => Ok(escape_css(value)),
+            HtmlContext::Value {
+                attr,
+                quote: Some(_),
+                url,
+                js,
+                ..
+            } => match attr {
+                Attr::Normal => Ok(escape_html(value)),
+                Attr::Url => match url {
+                    UrlPart::Start => {
+                        push_url(url_prefix, value)?;
+                        Ok(escape_html(&normalize_url(value)))
+                    }
+                    UrlPart::Path => Ok(escape_html(&normalize_url(value))),
+                    UrlPart::Query => Ok(encode_url_component(value)),
+                },
+                Attr::Script => escape_in_js(js, value).map(|v| escape_html(&v)),
+                Attr::Style => Ok(escape_html(&escape_css(value))),
+                Attr::Html => Err(RenderErrorReason::UnsafeEscapeContext(
+                    "srcdoc attribute".to_owned(),
+                )
+                .into()),
+            },
+            HtmlContext::Value { quote: None, .. } => Err(RenderErrorReason::UnsafeEscapeContext(
+                "unquoted attribute value".to_owned(),
+            )
+            .into()),
+            HtmlContext::BeforeValue(_, _) => Err(RenderErrorReason::UnsafeEscapeContext(
+                "unquoted attribute value".to_owned(),
+            )
+            .into()),
+            HtmlContext::Tag(_)
+            | HtmlContext::AttrName(_, _)
+            | HtmlContext::AfterAttrName(_, _) => {
+                Err(RenderErrorReason::UnsafeEscapeContext("tag".to_owned()).into())
+            }
+        }
+    }
+}
+
+/// The context after two branches, when they can be continued the same way
+fn join(x: HtmlContext, y: HtmlContext) -> Option<HtmlContext> {
+    match (x, y) {
+        (x, y) if x == y => Some(x),
+        // js code where a `/` divides after one branch only
+        (x, y) if x.clone().with_code(None) == y.clone().with_code(None) => Some(x.with_code(None)),
+        // optional attributes, like `<input {{#if c}}checked{{/if}}>`
+        (
+            HtmlContext::Tag(x) | HtmlContext::AttrName(x, _) | HtmlContext::AfterAttrName(x, _),
+            HtmlContext::Tag(y) | HtmlContext::AttrName(y, _) | HtmlContext::AfterAttrName(y, _),
+        ) if x == y => Some(HtmlContext::Tag(x)),
+        _ => None,
+    }
+}
+
+/// Name of a partial, when it doesn't depend on the data
+fn static_name(name: &Parameter) -> Option<&str> {
+    match name {
+        Parameter::Literal(Json::String(s)) => Some(s),
+        _ => name.as_name(),
+    }
+}
+
+/// Walks templates to find the context after them
+struct Analyzer<'a> {
+    /// partials of the registry and the render context
+    lookup: &'a dyn Fn(&str) -> Option<&'a Template>,
+    /// inline partials and partial blocks in scope
+    partials: Vec<(&'a str, &'a Template)>,
+    /// partials being walked, to stop at recursive ones
+    stack: Vec<&'a str>,
+}
+
+impl<'a> Analyzer<'a> {
+    fn template_end(
+        &mut self,
+        ctx: HtmlContext,
+        template: &'a Template,
+    ) -> Result<HtmlContext, RenderError> {
+        let scope = self.partials.len();
+        // inline partials are usable in the whole template
+        for e in &template.elements {
+            if let TemplateElement::DecoratorBlock(ref d) = *e {
+                if let (Some("inline"), Some(name), Some(t)) = (
+                    d.name.as_name(),
+                    d.params.first().and_then(static_name),
+                    d.template.as_ref(),
+                ) {
+                    self.partials.push((name, t));
+                }
+            }
+        }
+        let result = template
+            .elements
+            .iter()
+            .try_fold(ctx, |ctx, e| self.element_end(ctx, e));
+        self.partials.truncate(scope);
+        result
+    }
+
+    fn element_end(
+        &mut self,
+        ctx: HtmlContext,
+        element: &'a TemplateElement,
+    ) -> Result<HtmlContext, RenderError> {
+        match *element {
+            TemplateElement::RawString(ref s) => Ok(ctx.advance(s)),
+            TemplateElement::Expression(_) | TemplateElement::HtmlExpression(_) => {
+                Ok(ctx.after_value())
+            }
+            TemplateElement::HelperBlock(ref ht) => {
+                // the helper renders either branch, and nothing when there
+                // is no inverse, so they must all end in the same context
+                let mut ends = Vec::with_capacity(2);
+                for t in ht.template.iter().chain(ht.inverse.iter()) {
+                    ends.push(self.template_end(ctx.clone(), t)?);
+                }
+                if ht.inverse.is_none() {
+                    ends.push(ctx);
+                }
+                let mut ends = ends.into_iter();
+                let first = ends.next().unwrap();
+                ends.try_fold(first, |end, branch| {
+                    join(end, branch).ok_or_else(|| {
+                        RenderErrorReason::EscapeContextMismatch(
+                            ht.name.as_name().unwrap_or("").to_owned(),
+                        )
+                        .into()
+                    })
+                })
+            }
+            TemplateElement::PartialExpression(ref d) | TemplateElement::PartialBlock(ref d) => {
+                self.partial_end(ctx, d)
+            }
+            TemplateElement::DecoratorExpression(_)
+            | TemplateElement::DecoratorBlock(_)
+            | TemplateElement::Comment(_) => Ok(ctx),
+        }
+    }
+
+    fn partial_end(
+        &mut self,
+        ctx: HtmlContext,
+        d: &'a DecoratorTemplate,
+    ) -> Result<HtmlContext, RenderError> {
+        let name = match static_name(&d.name) {
+            Some(name) => name,
+            // the partial is only known when rendering
+            None => return Ok(ctx.after_value()),
+        };
+        if self.stack.contains(&name) {
+            return Ok(ctx);
+        }
+        let partial = self
+            .partials
+            .iter()
+            .rev()
+            .find(|(n, _)| *n == name)
+            .map(|(_, t)| *t)
+            .or_else(|| (self.lookup)(name))
+            .or(d.template.as_ref());
+        let partial = match partial {
+            Some(partial) => partial,
+            None => return Ok(ctx.after_value()),
+        };
+
+        let scope = self.partials.len();
+        if let Some(ref block) = d.template {
+            self.partials.push((PARTIAL_BLOCK, block));
+        }
+        self.stack.push(name);
+        let result = self.template_end(ctx, partial);
+        self.stack.pop();
+        self.partials.truncate(scope);
+        result
+    }
+}
+
+/// The context after `element`, when it is rendered in `ctx`
+///
+/// Fails when the branches of a block end in different contexts, as the
+/// context after it would depend on the data.
+pub(crate) fn element_end(
+    registry: &Registry<'_>,
+    rc: &RenderContext<'_, '_>,
+    ctx: HtmlContext,
+    element: &TemplateElement,
+) -> Result<HtmlContext, RenderError> {
+    let lookup = |name: &str| rc.get_partial(name).or_else(|| registry.get_template(name));
+    Analyzer {
+        lookup: &lookup,
+        partials: Vec::new(),
+        stack: Vec::new(),
+    }
+    .element_end(ctx, element)
+}
+
+/// The lowercased scheme of an URL, empty when it has none, or `None` when
+/// the text may still be continued into a scheme
+fn url_scheme(url: &str) -> Option<String> {
+    let mut scheme = String::new();
+    // browsers skip leading spaces and controls, and tabs and newlines
+    for c in url.trim_start_matches(|c: char| c <= ' ').chars() {
+        match c {
+            '\t' | '\n' | '\r' => {}
+            ':' => return Some(scheme.to_ascii_lowercase()),
+            '/' | '?' | '#' => return Some(String::new()),
+            _ => scheme.push(c),
+        }
+    }
+    None
+}
+
+/// Whether the URL has no scheme, or a scheme that doesn't run code
+fn is_safe_url(url: &str) -> bool {
+    match url_scheme(url) {
+        Some(scheme) => scheme.is_empty() || SAFE_URL_SCHEMES.contains(&scheme.as_str()),
+        None => true,
+    }
+}
+
+/// Add `text` to `prefix`, what was written of an URL value while its
+/// scheme is unknown, and check the scheme once it is known
+///
+/// The scheme may be split between raw text and several values, so it is
+/// checked on everything written before it, not on each value alone.
+pub(crate) fn push_url(prefix: &mut String, text: &str) -> Result<(), RenderError> {
+    if url_scheme(prefix).is_some() {
+        return Ok(());
+    }
+    prefix.push_str(text);
+    if is_safe_url(prefix) {
+        Ok(())
+    } else {
+        Err(RenderErrorReason::UnsafeUrl(prefix.clone()).into())
+    }
+}
+
+fn percent_encode(out: &mut String, c: char) {
+    let mut buf = [0; 4];
+    for b in c.encode_utf8(&mut buf).bytes() {
+        let _ = write!(out, "%{:02X}", b);
+    }
+}
+
+/// Percent encode the chars that are not allowed in an URL
+fn normalize_url(url: &str) -> String {
+    let mut out = String::with_capacity(url.len());
+    for c in url.chars() {
+        if c.is_ascii_alphanumeric() || "-._~:/?#[]@!$&()*+,;=%".contains(c) {
+            out.push(c);
+        } else {
+            percent_encode(&mut out, c);
+        }
+    }
+    out
+}
+
+/// Percent encode everything but unreserved chars, for query values
+fn encode_url_component(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() || "-._~".contains(c) {
+            out.push(c);
+        } else {
+            percent_encode(&mut out, c);
+        }
+    }
+    out
+}
+
+fn push_js_char(out: &mut String, c: char) {
+    match c {
+        '\\' => out.push_str("\\\\"),
+        '/' => out.push_str("\\/"),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        '"' | '\'' | '`' | '<' | '>' | '&' | '=' | '\u{2028}' | '\u{2029}' => {
+            let _ = write!(out, "\\u{:04x}", c as u32);
+        }
+        _ if c.is_control() => {
+            let _ = write!(out, "\\u{:04x}", c as u32);
+        }
+        _ => out.push(c),
+    }
+}
+
+/// Escape a value inside a js string, so it can't end the string nor the
+/// script element
+///
+/// The result can't end a comment either, so it's also used in comments.
+fn escape_js(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        push_js_char(&mut out, c);
+    }
+    out
+}
+
+/// Escape a value inside a js regexp, matching it literally
+fn escape_js_regexp(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "^$.*+?()[]{}|-".contains(c) {
+            out.push('\\');
+            out.push(c);
+        } else {
+            push_js_char(&mut out, c);
+        }
+    }
+    out
+}
+
+/// Escape a value written in js code
+fn escape_in_js(js: Js, value: &str) -> Result<String, RenderError> {
+    match js {
+        Js::Code(_) => Ok(quote_js(value)),
+        Js::Str(_) | Js::LineComment(_) | Js::BlockComment(_) => Ok(escape_js(value)),
+        Js::Regexp(_) => Ok(escape_js_regexp(value)),
+        Js::Unknown => {
+            Err(RenderErrorReason::UnsafeEscapeContext("ambiguous script".to_owned()).into())
+        }
+    }
+}
+
+/// Write a value as a js string literal
+fn quote_js(value: &str) -> String {
+    format!("\"{}\"", escape_js(value))
+}
+
+/// Escape a value as CSS, keeping only chars that can't change the rule
+fn escape_css(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() || " #.,%-_".contains(c) {
+            out.push(c);
+        } else {
+            let _ = write!(out, "\\{:x} ", c as u32);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Attr, Element, HtmlContext, Js, UrlPart};
+    use crate::error::RenderErrorReason;
+    use crate::registry::Registry;
+    use serde_json::json;
+
+    #[test]
+    fn test_html_context() {
+        let ctx = |text: &str| HtmlContext::Text.advance(text);
+
+        assert_eq!(ctx("<p>hello</p> "), HtmlContext::Text);
+        assert_eq!(
+            ctx("<p class="),
+            HtmlContext::BeforeValue(Element::Normal, Attr::Normal)
+        );
+        assert_eq!(
+            ctx("<a title='x' HREF=\""),
+            HtmlContext::Value {
+                element: Element::Normal,
+                attr: Attr::Url,
+                quote: Some('"'),
+                url: UrlPart::Start,
+                js: Js::Code(Some(true)),
+            }
+        );
+        assert_eq!(
+            ctx("<a href=\"/search?q="),
+            HtmlContext::Value {
+                element: Element::Normal,
+                attr: Attr::Url,
+                quote: Some('"'),
+                url: UrlPart::Query,
+                js: Js::Code(Some(true)),
+            }
+        );
+        assert_eq!(ctx("<a href=\"x\">"), HtmlContext::Text);
+        assert_eq!(
+            ctx("<input disabled "),
+            HtmlContext::AfterAttrName(Element::Normal, "disabled".to_owned())
+        );
+        assert_eq!(
+            ctx("<script type=\"module\">var a = 'x\\'"),
+            HtmlContext::Script(Js::Str('\''))
+        );
+        assert_eq!(
+            ctx("<script>// it's\nvar a = b / 2 /* it's */"),
+            HtmlContext::Script(Js::Code(Some(false)))
+        );
+        assert_eq!(
+            ctx("<script>return /'[/']/.test(a)"),
+            HtmlContext::Script(Js::Code(Some(false)))
+        );
+        assert_eq!(
+            ctx("<a href=\" java"),
+            HtmlContext::Value {
+                element: Element::Normal,
+                attr: Attr::Url,
+                quote: Some('"'),
+                url: UrlPart::Start,
+                js: Js::Code(Some(true)),
+            }
+        );
+        assert_eq!(ctx("<script>var a = \"</script>"), HtmlContext::Text);
+        assert_eq!(ctx("<STYLE>p { color: "), HtmlContext::Style);
+        assert_eq!(ctx("<!-- <script> "), HtmlContext::Comment);
+        assert_eq!(ctx("<!-- <script> --> a < b"), HtmlContext::Text);
+    }
+
+    #[test]
+    fn test_contextual_escape() {
+        let mut hbs = Registry::new();
+        hbs.set_contextual_escape(true);
+
+        let data = json!({
+            "text": "<b>\"hi\"</b>",
+            "url": "/a b?c=\"d\"",
+            "query": "a&b c",
+            "js": "</script><script>alert('x')",
+            "css": "red; background: url(x)",
+            "bad_url": " JavaScript:alert(1)",
+            "scheme": "javascript",
+            "https": "https",
+            "code": "1;alert(1)",
+            "colon": ":alert(1)",
+            "items": [1, 2],
+        });
+        let render = |tpl: &str| hbs.render_template(tpl, &data);
+
+        assert_eq!(
+            render("<p>{{text}}</p>").unwrap(),
+            "<p>&lt;b&gt;&quot;hi&quot;&lt;/b&gt;</p>"
+        );
+        assert_eq!(render("<p>{{{text}}}</p>").unwrap(), "<p><b>\"hi\"</b></p>");
+        assert_eq!(
+            render("<a href=\"{{url}}\">{{text}}</a>").unwrap(),
+            "<a href=\"/a%20b?c&#x3D;%22d%22\">&lt;b&gt;&quot;hi&quot;&lt;/b&gt;</a>"
+        );
+        assert_eq!(
+            render("<a href='/search?q={{query}}'>").unwrap(),
+            "<a href='/search?q=a%26b%20c'>"
+        );
+        assert_eq!(
+            render("<script>var a = {{js}}, b = '{{js}}';</script>").unwrap(),
+            "<script>var a = \"\\u003c\\/script\\u003e\\u003cscript\\u003ealert(\\u0027x\\u0027)\", \
+             b = '\\u003c\\/script\\u003e\\u003cscript\\u003ealert(\\u0027x\\u0027)';</script>"
+        );
+        assert_eq!(
+            render("<button onclick=\"go({{query}})\">").unwrap(),
+            "<button onclick=\"go(&quot;a\\u0026b c&quot;)\">"
+        );
+        assert_eq!(
+            render("<p style=\"color: {{css}}\">").unwrap(),
+            "<p style=\"color: red\\3b  background\\3a  url\\28 x\\29 \">"
+        );
+        assert_eq!(
+            render("<ul>{{#each items}}<li id=\"i{{this}}\">{{this}}</li>{{/each}}</ul>").unwrap(),
+            "<ul><li id=\"i1\">1</li><li id=\"i2\">2</li></ul>"
+        );
+
+        let e = render("<a href=\"{{bad_url}}\">").unwrap_err();
+        assert!(matches!(e.reason(), RenderErrorReason::UnsafeUrl(_)));
+        let e = render("<a href=\"{{#if text}}{{bad_url}}{{/if}}\">").unwrap_err();
+        assert!(matches!(e.reason(), RenderErrorReason::UnsafeUrl(_)));
+        let e = render("<a href=\"{{#if text}}{{/if}}{{bad_url}}\">").unwrap_err();
+        assert!(matches!(e.reason(), RenderErrorReason::UnsafeUrl(_)));
+        for tpl in &[
+            "<a href=\"{{scheme}}{{colon}}\">",
+            "<a href=\"{{scheme}}:{{code}}\">",
+            "<a href=\"java{{bad_url}}\">",
+            "<a href=\" {{bad_url}}\">",
+            "<a href=\"{{#each items}}{{../scheme}}{{/each}}:x\">",
+        ] {
+            let e = render(tpl).unwrap_err();
+            assert!(matches!(e.reason(), RenderErrorReason::UnsafeUrl(_)));
+        }
+        assert_eq!(
+            render("<a href=\"{{https}}://{{query}}\">").unwrap(),
+            "<a href=\"https://a&amp;b%20c\">"
+        );
+        assert_eq!(
+            render("<script>// don't\nvar x = {{code}};</script>").unwrap(),
+            "<script>// don't\nvar x = \"1;alert(1)\";</script>"
+        );
+        assert_eq!(
+            render("<script>/* don't */ var r = /'/, x = {{code}} / 2;</script>").unwrap(),
+            "<script>/* don't */ var r = /'/, x = \"1;alert(1)\" / 2;</script>"
+        );
+        assert_eq!(
+            render("<script>var r = /a{{query}}/;</script>").unwrap(),
+            "<script>var r = /aa\\u0026b c/;</script>"
+        );
+        let e =
+            render("<script>{{#if text}}a{{else}}a ={{/if}} / 1; var x = '{{code}}';").unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            RenderErrorReason::UnsafeEscapeContext(_)
+        ));
+        let e = render("<iframe srcdoc=\"{{text}}\">").unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            RenderErrorReason::UnsafeEscapeContext(_)
+        ));
+        let e = render("{{#if text}}<script>{{/if}}var x = {{js}};").unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            RenderErrorReason::EscapeContextMismatch(_)
+        ));
+        assert_eq!(
+            render("{{#if text}}<script>{{else}}<script>{{/if}}var x = {{query}};").unwrap(),
+            "<script>var x = \"a\\u0026b c\";"
+        );
+        assert_eq!(
+            render("<input {{#if text}}checked{{/if}} value=\"{{query}}\">").unwrap(),
+            "<input checked value=\"a&amp;b c\">"
+        );
+        let e = render("<a href={{url}}>").unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            RenderErrorReason::UnsafeEscapeContext(_)
+        ));
+        let e = render("<a {{text}}>").unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            RenderErrorReason::UnsafeEscapeContext(_)
+        ));
+
+        hbs.register_partial("link", "<a href=\"{{this}}\">")
+            .unwrap();
+        assert!(hbs.render_template("{{> link bad_url}}", &data).is_err());
+        assert_eq!(
+            hbs.render_template("<p title=\"{{> link url}}\">", &data)
+                .unwrap_err()
+                .reason()
+                .to_string(),
+            "Cannot safely render value in tag"
+        );
+        hbs.register_partial("open", "<script>").unwrap();
+        assert_eq!(
+            hbs.render_template("{{> open}}var x = {{query}};", &data)
+                .unwrap(),
+            "<script>var x = \"a\\u0026b c\";"
+        );
+        assert_eq!(
+            hbs.render_template(
+                "{{#*inline \"pre\"}}<script>var y = 1;{{/inline}}{{#if text}}{{> pre}}{{else}}<script>{{/if}}var x = {{query}};",
+                &data
+            )
+            .unwrap(),
+            "<script>var y = 1;var x = \"a\\u0026b c\";"
+        );
+    }
+}
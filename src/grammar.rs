@@ -117,6 +117,7 @@ mod test {
             "[$id]",
             "$id",
             "this.[null]",
+            ".",
         ];
         for i in s.iter() {
             assert_rule!(Rule::reference, i);
@@ -369,8 +370,10 @@ mod test {
             "{{> hello a=1}}",
             "{{> (hello) a=1}}",
             "{{> hello.world}}",
+            "{{> ui::button}}",
             "{{> [a83?f4+.3]}}",
             "{{> 'anif?.bar'}}",
+            "{{> (lookup . 'kind') fallback=\"card\"}}",
         ];
         for i in s.iter() {
             assert_rule!(Rule::partial_expression, i);
@@ -379,7 +382,10 @@ mod test {
 
     #[test]
     fn test_partial_block() {
-        let s = vec!["{{#> hello}}nice{{/hello}}"];
+        let s = vec![
+            "{{#> hello}}nice{{/hello}}",
+            "{{#> [hello world]}}nice{{/[hello world]}}",
+        ];
         for i in s.iter() {
             assert_rule!(Rule::partial_block, i);
         }
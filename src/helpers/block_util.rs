@@ -1,5 +1,10 @@
 use crate::block::BlockContext;
-use crate::json::value::PathAndJson;
+use crate::context::Context;
+use crate::error::RenderError;
+#[cfg(feature = "async")]
+use crate::helpers::helper_async;
+use crate::json::value::{JsonTruthy, PathAndJson};
+use crate::render::RenderContext;
 
 pub(crate) fn create_block<'reg: 'rc, 'rc>(
     param: &'rc PathAndJson<'reg, 'rc>,
@@ -15,3 +20,43 @@ pub(crate) fn create_block<'reg: 'rc, 'rc>(
 
     block
 }
+
+#[cfg(feature = "async")]
+fn is_iter_empty(
+    ctx: &Context,
+    rc: &RenderContext<'_, '_>,
+    path: &[String],
+) -> Option<Result<bool, RenderError>> {
+    match rc.async_calls() {
+        Some(calls) => helper_async::is_iter_empty(&calls, ctx, path),
+        None => ctx.is_iter_empty(path),
+    }
+}
+
+#[cfg(not(feature = "async"))]
+fn is_iter_empty(
+    ctx: &Context,
+    _: &RenderContext<'_, '_>,
+    path: &[String],
+) -> Option<Result<bool, RenderError>> {
+    ctx.is_iter_empty(path)
+}
+
+/// Whether `param` is truthy, peeking context iterators and asking lazy
+/// values, which are not turned into JSON
+pub(crate) fn is_param_truthy(
+    param: &PathAndJson<'_, '_>,
+    ctx: &Context,
+    rc: &RenderContext<'_, '_>,
+    include_zero: bool,
+) -> Result<bool, RenderError> {
+    if let Some(path) = param.context_path() {
+        if let Some(empty) = is_iter_empty(ctx, rc, path) {
+            return empty.map(|e| !e);
+        }
+        if let Some(truthy) = ctx.with_lazy(path, |v| v.is_truthy(include_zero)) {
+            return Ok(truthy);
+        }
+    }
+    Ok(param.value().is_truthy(include_zero))
+}
@@ -0,0 +1,359 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::iter::Peekable;
+use std::mem;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+
+use log::Level;
+use serde_json::value::Value as Json;
+
+use crate::context::{iter_consumed, Context, ContextIter};
+use crate::error::{RenderError, RenderErrorReason};
+use crate::helpers::{HelperDef, HelperResult};
+use crate::json::value::ScopedJson;
+use crate::limits::{LimitState, RenderLimits};
+use crate::output::Output;
+use crate::registry::Registry;
+use crate::render::{do_escape, Helper, RenderContext};
+
+/// The future returned by an `AsyncHelperDef`
+pub type AsyncHelperFuture = Pin<Box<dyn Future<Output = Result<Json, RenderError>> + Send>>;
+
+/// Async helper definition
+///
+/// An async helper receives the evaluated values of its params and hash, and
+/// resolves to a JSON value which is rendered like the value of a simple
+/// helper: it's escaped in `{{}}`, and can be used in subexpressions and as
+/// param of other helpers, including `if` and `each`. Block form is not
+/// supported.
+///
+/// Async helpers are only available with `Registry::render_async` and its
+/// friends. Rendering them with the sync API returns a `RenderError`. They
+/// are only called on the branches of the final render, see
+/// `Registry::render_async`.
+///
+/// A closure that returns a future can be used as an async helper:
+///
+/// ```
+/// use handlebars::*;
+/// use serde_json::json;
+///
+/// # async fn fetch_name(id: i64) -> String { format!("user{}", id) }
+/// let mut hbs = Handlebars::new();
+/// hbs.register_async_helper(
+///     "user_name",
+///     Box::new(|params: Vec<JsonValue>, _| async move {
+///         let id = params.get(0).and_then(|v| v.as_i64()).unwrap_or(0);
+///         Ok(json!(fetch_name(id).await))
+///     }),
+/// );
+/// ```
+pub trait AsyncHelperDef {
+    fn call(&self, params: Vec<Json>, hash: BTreeMap<String, Json>) -> AsyncHelperFuture;
+}
+
+impl<F, Fut> AsyncHelperDef for F
+where
+    F: Fn(Vec<Json>, BTreeMap<String, Json>) -> Fut,
+    Fut: Future<Output = Result<Json, RenderError>> + Send + 'static,
+{
+    fn call(&self, params: Vec<Json>, hash: BTreeMap<String, Json>) -> AsyncHelperFuture {
+        Box::pin((*self)(params, hash))
+    }
+}
+
+type PendingCall = (String, AsyncHelperFuture);
+
+/// Async helper calls of a render pass
+///
+/// Calls resolved in previous passes are answered from `resolved`, the
+/// others are recorded in `pending` to be awaited before the next pass.
+/// Once a helper was given the placeholder of an unresolved call, the pass
+/// may render branches the final one won't, so new calls are left for the
+/// next pass.
+/// Context iterators are kept in `iters` with their consumed items, so each
+/// pass iterates them from the start. Records of the `log` helper are kept in
+/// `logs` and only emitted for the final pass.
+#[derive(Default)]
+pub(crate) struct AsyncCalls {
+    resolved: HashMap<String, Json>,
+    pending: Vec<PendingCall>,
+    iters: HashMap<Vec<String>, BufferedIter>,
+    /// iterators already iterated in this pass
+    iterated: Vec<Vec<String>>,
+    /// whether a placeholder was given to a helper in this pass
+    speculative: bool,
+    logs: Vec<(Level, String)>,
+    /// resources used by the previous passes
+    limits: Option<LimitState>,
+}
+
+/// A context iterator and the items consumed from it in previous passes
+pub(crate) struct BufferedIter {
+    items: Vec<Json>,
+    rest: Peekable<ContextIter>,
+}
+
+impl BufferedIter {
+    /// Iterate the consumed items, then the rest of the iterator
+    pub(crate) fn replay(&mut self) -> Replay<'_> {
+        Replay {
+            buffered: self,
+            pos: 0,
+        }
+    }
+
+    fn is_empty(&mut self) -> bool {
+        self.items.is_empty() && self.rest.peek().is_none()
+    }
+}
+
+pub(crate) struct Replay<'a> {
+    buffered: &'a mut BufferedIter,
+    pos: usize,
+}
+
+impl<'a> Iterator for Replay<'a> {
+    type Item = Result<Json, RenderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.buffered.items.get(self.pos) {
+            self.pos += 1;
+            return Some(Ok(item.clone()));
+        }
+        let item = self.buffered.rest.next()?;
+        if let Ok(ref item) = item {
+            self.buffered.items.push(item.clone());
+            self.pos += 1;
+        }
+        Some(item)
+    }
+}
+
+/// Take the iterator registered at `path` for a pass, with its buffered
+/// items, to be given back with `put_iter` once iterated
+pub(crate) fn take_iter(
+    calls: &RefCell<AsyncCalls>,
+    ctx: &Context,
+    path: &[String],
+) -> Option<Result<BufferedIter, RenderError>> {
+    let mut calls = calls.borrow_mut();
+    if let Some(buffered) = calls.iters.remove(path) {
+        calls.iterated.push(path.to_vec());
+        return Some(Ok(buffered));
+    }
+    if calls.iterated.iter().any(|p| p.as_slice() == path) {
+        return Some(Err(iter_consumed(path)));
+    }
+    let rest = ctx.take_iter(path)?;
+    calls.iterated.push(path.to_vec());
+    Some(rest.map(|rest| BufferedIter {
+        items: Vec::new(),
+        rest,
+    }))
+}
+
+pub(crate) fn put_iter(calls: &RefCell<AsyncCalls>, path: &[String], buffered: BufferedIter) {
+    calls.borrow_mut().iters.insert(path.to_vec(), buffered);
+}
+
+/// Keep a record of the `log` helper until the pass is known to be final
+#[cfg(not(feature = "no_logging"))]
+pub(crate) fn defer_log(calls: &RefCell<AsyncCalls>, level: Level, message: String) {
+    calls.borrow_mut().logs.push((level, message));
+}
+
+/// The limits of the render, with the resources used by the previous passes
+pub(crate) fn take_limits(calls: &RefCell<AsyncCalls>) -> Option<LimitState> {
+    calls.borrow_mut().limits.take()
+}
+
+pub(crate) fn put_limits(calls: &RefCell<AsyncCalls>, limits: LimitState) {
+    calls.borrow_mut().limits = Some(limits);
+}
+
+/// Whether the iterator registered at `path` has no items
+pub(crate) fn is_iter_empty(
+    calls: &RefCell<AsyncCalls>,
+    ctx: &Context,
+    path: &[String],
+) -> Option<Result<bool, RenderError>> {
+    match calls.borrow_mut().iters.get_mut(path) {
+        Some(buffered) => Some(Ok(buffered.is_empty())),
+        None => ctx.is_iter_empty(path),
+    }
+}
+
+/// Adapts an `AsyncHelperDef` to the sync helper interface
+pub(crate) struct AsyncHelper<'reg> {
+    pub(crate) def: Arc<dyn AsyncHelperDef + Send + Sync + 'reg>,
+}
+
+impl<'a> AsyncHelper<'a> {
+    /// The result of the call when it is resolved, otherwise record it to be
+    /// awaited before the next pass
+    ///
+    /// `is_param` is set for subexpressions, whose placeholder is given to
+    /// another helper.
+    fn resolve(
+        &self,
+        h: &Helper<'_, '_>,
+        rc: &RenderContext<'_, '_>,
+        is_param: bool,
+    ) -> Result<Option<Json>, RenderError> {
+        let calls = rc
+            .async_calls()
+            .ok_or_else(|| RenderErrorReason::AsyncHelperNotResolved(h.name().to_owned()))?;
+
+        let params: Vec<Json> = h.params().iter().map(|p| p.value().clone()).collect();
+        let hash: BTreeMap<String, Json> = h
+            .hash()
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), v.value().clone()))
+            .collect();
+        let key = serde_json::to_string(&(h.name(), &params, &hash))?;
+
+        let mut calls = calls.borrow_mut();
+        if let Some(value) = calls.resolved.get(&key) {
+            return Ok(Some(value.clone()));
+        }
+        if !calls.speculative && !calls.pending.iter().any(|(k, _)| *k == key) {
+            let future = self.def.call(params, hash);
+            calls.pending.push((key, future));
+        }
+        calls.speculative |= is_param;
+        Ok(None)
+    }
+}
+
+impl<'a> HelperDef for AsyncHelper<'a> {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Registry<'reg>,
+        _: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        // placeholder until the call is resolved
+        let value = self.resolve(h, rc, true)?.unwrap_or(Json::Null);
+        Ok(ScopedJson::Derived(value))
+    }
+
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Registry<'reg>,
+        _: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        // written values don't change what the pass renders
+        if let Some(value) = self.resolve(h, rc, false)? {
+            let output = do_escape(r, rc, ScopedJson::Derived(value).render())?;
+            out.write(output.as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+/// Run one render pass, returning its result and the async calls it made
+fn render_pass<F>(
+    render: &mut F,
+    mut calls: AsyncCalls,
+) -> (Result<String, RenderError>, AsyncCalls, Vec<PendingCall>)
+where
+    F: FnMut(Rc<RefCell<AsyncCalls>>) -> Result<String, RenderError>,
+{
+    calls.iterated.clear();
+    calls.speculative = false;
+    calls.logs.clear();
+    let calls = Rc::new(RefCell::new(calls));
+    let result = render(calls.clone());
+    let mut calls = calls.replace(AsyncCalls::default());
+    let pending = mem::take(&mut calls.pending);
+    (result, calls, pending)
+}
+
+/// Render until all async helper calls are resolved
+///
+/// Each pass renders the template synchronously. Async helpers that aren't
+/// resolved yet render `null` and record their future, which are awaited
+/// concurrently before rendering again with the results. The output of the
+/// first pass without new async calls is returned, and only its `log`
+/// records are emitted. The render `limits` apply to all the passes
+/// together.
+pub(crate) async fn render_resolved<F>(
+    limits: &RenderLimits,
+    mut render: F,
+) -> Result<String, RenderError>
+where
+    F: FnMut(Rc<RefCell<AsyncCalls>>) -> Result<String, RenderError>,
+{
+    let mut calls = AsyncCalls {
+        limits: LimitState::new(limits),
+        ..Default::default()
+    };
+    loop {
+        let (result, c, pending) = render_pass(&mut render, calls);
+        calls = c;
+        // errors may be caused by placeholders, they are only final when
+        // there is nothing left to resolve
+        if pending.is_empty() {
+            #[cfg(not(feature = "no_logging"))]
+            for (level, message) in calls.logs {
+                log!(level, "{}", message);
+            }
+            return result;
+        }
+
+        for (key, value) in JoinAll::new(pending).await {
+            calls.resolved.insert(key, value?);
+        }
+    }
+}
+
+/// Await a set of futures concurrently
+struct JoinAll {
+    futures: Vec<(String, Option<AsyncHelperFuture>)>,
+    results: Vec<Option<Result<Json, RenderError>>>,
+}
+
+impl JoinAll {
+    fn new(pending: Vec<PendingCall>) -> JoinAll {
+        let results = pending.iter().map(|_| None).collect();
+        let futures = pending.into_iter().map(|(k, f)| (k, Some(f))).collect();
+        JoinAll { futures, results }
+    }
+}
+
+impl Future for JoinAll {
+    type Output = Vec<(String, Result<Json, RenderError>)>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut done = true;
+        for (i, (_, future)) in this.futures.iter_mut().enumerate() {
+            if let Some(f) = future {
+                match f.as_mut().poll(cx) {
+                    Poll::Ready(r) => {
+                        this.results[i] = Some(r);
+                        *future = None;
+                    }
+                    Poll::Pending => done = false,
+                }
+            }
+        }
+
+        if done {
+            let keys = this.futures.drain(..).map(|(k, _)| k);
+            let results = this.results.drain(..).map(|r| r.unwrap());
+            Poll::Ready(keys.zip(results).collect())
+        } else {
+            Poll::Pending
+        }
+    }
+}
@@ -0,0 +1,391 @@
+//! Collection helpers, enabled with the `collection_helpers` feature and
+//! registered with `Registry::register_collection_helpers`
+//!
+//! They return new arrays, so they can be used as subexpressions of `each`.
+//! Keys are dotted paths into the items, like `author.name`.
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use serde_json::Value as Json;
+
+use crate::error::{RenderError, RenderErrorReason};
+use crate::registry::Registry;
+
+/// Ranges longer than this are rejected by `range` instead of being allocated
+const MAX_RANGE_LEN: usize = 1_000_000;
+
+/// Get the value at the dotted path `key` of `item`
+fn get<'a>(item: &'a Json, key: &str) -> Option<&'a Json> {
+    key.split('.').try_fold(item, |value, part| match value {
+        Json::Object(m) => m.get(part),
+        Json::Array(a) => part.parse::<usize>().ok().and_then(|i| a.get(i)),
+        _ => None,
+    })
+}
+
+/// Order values of different types as null, bools, numbers, strings, then
+/// arrays and objects, which are all equal
+fn compare(x: &Json, y: &Json) -> Ordering {
+    fn rank(v: &Json) -> u8 {
+        match v {
+            Json::Null => 0,
+            Json::Bool(_) => 1,
+            Json::Number(_) => 2,
+            Json::String(_) => 3,
+            Json::Array(_) | Json::Object(_) => 4,
+        }
+    }
+    match (x, y) {
+        (Json::Bool(x), Json::Bool(y)) => x.cmp(y),
+        (Json::Number(x), Json::Number(y)) => match (x.as_i64(), y.as_i64()) {
+            (Some(x), Some(y)) => x.cmp(&y),
+            _ => x
+                .as_f64()
+                .partial_cmp(&y.as_f64())
+                .unwrap_or(Ordering::Equal),
+        },
+        (Json::String(x), Json::String(y)) => x.cmp(y),
+        _ => rank(x).cmp(&rank(y)),
+    }
+}
+
+/// A value hashed consistently with its equality, for grouping items
+///
+/// Object fields are hashed in key order, as maps with `preserve_order`
+/// are equal whatever their order.
+struct Key<'a>(&'a Json);
+
+impl<'a> PartialEq for Key<'a> {
+    fn eq(&self, other: &Key<'a>) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<'a> Eq for Key<'a> {}
+
+impl<'a> Hash for Key<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self.0 {
+            Json::Null => 0u8.hash(state),
+            Json::Bool(b) => (1u8, b).hash(state),
+            Json::Number(n) => {
+                2u8.hash(state);
+                if let Some(u) = n.as_u64() {
+                    (0u8, u).hash(state);
+                } else if let Some(i) = n.as_i64() {
+                    (1u8, i).hash(state);
+                } else if let Some(f) = n.as_f64() {
+                    // 0.0 and -0.0 are equal
+                    let f = if f == 0.0 { 0.0 } else { f };
+                    (2u8, f.to_bits()).hash(state);
+                }
+            }
+            Json::String(s) => (3u8, s).hash(state),
+            Json::Array(a) => {
+                (4u8, a.len()).hash(state);
+                a.iter().for_each(|v| Key(v).hash(state));
+            }
+            Json::Object(m) => {
+                (5u8, m.len()).hash(state);
+                let mut fields: Vec<_> = m.iter().collect();
+                fields.sort_by(|x, y| x.0.cmp(y.0));
+                for (k, v) in fields {
+                    k.hash(state);
+                    Key(v).hash(state);
+                }
+            }
+        }
+    }
+}
+
+/// Resolve a possibly negative index against `len`
+fn index(i: i64, len: usize) -> usize {
+    if i < 0 {
+        len.saturating_sub(i.saturating_neg() as usize)
+    } else {
+        (i as usize).min(len)
+    }
+}
+
+fn type_mismatch(helper: &str, name: &str, expected: &str) -> RenderError {
+    RenderErrorReason::ParamTypeMismatchForName(
+        helper.to_owned(),
+        name.to_owned(),
+        expected.to_owned(),
+    )
+    .into()
+}
+
+handlebars_helper!(sort_by: |items: array, key: str, {desc: bool = false}| {
+    let mut sorted = items.clone();
+    // a stable sort keeps items with equal keys in their order
+    sorted.sort_by(|x, y| {
+        let ord = compare(
+            get(x, key).unwrap_or(&Json::Null),
+            get(y, key).unwrap_or(&Json::Null),
+        );
+        if desc { ord.reverse() } else { ord }
+    });
+    sorted
+});
+handlebars_helper!(filter_by: |items: array, key: str, value: Json| {
+    items
+        .iter()
+        .filter(|item| get(item, key) == Some(value))
+        .cloned()
+        .collect::<Vec<Json>>()
+});
+handlebars_helper!(group_by: |items: array, key: str| {
+    // groups are kept in the order their keys first appear
+    let mut groups: Vec<(&Json, Vec<Json>)> = Vec::new();
+    let mut indexes: HashMap<Key<'_>, usize> = HashMap::new();
+    for item in items {
+        let k = get(item, key).unwrap_or(&Json::Null);
+        match indexes.get(&Key(k)) {
+            Some(&i) => groups[i].1.push(item.clone()),
+            None => {
+                indexes.insert(Key(k), groups.len());
+                groups.push((k, vec![item.clone()]));
+            }
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(k, members)| json!({"key": k, "items": members}))
+        .collect::<Vec<Json>>()
+});
+handlebars_helper!(slice: |items: array, start: i64, end: i64| {
+    let start = index(start, items.len());
+    let end = index(end, items.len()).max(start);
+    items[start..end].to_vec()
+});
+handlebars_helper!(pluck: |items: array, key: str| {
+    items
+        .iter()
+        .map(|item| get(item, key).cloned().unwrap_or(Json::Null))
+        .collect::<Vec<Json>>()
+});
+handlebars_helper!(unique: |items: array| {
+    let mut seen: HashSet<Key<'_>> = HashSet::with_capacity(items.len());
+    items
+        .iter()
+        .filter(|item| seen.insert(Key(item)))
+        .cloned()
+        .collect::<Vec<Json>>()
+});
+handlebars_helper!(reverse: |value: Json| {
+    match value {
+        Json::Array(items) => Json::Array(items.iter().rev().cloned().collect()),
+        Json::String(s) => Json::String(s.chars().rev().collect()),
+        _ => return Err(type_mismatch("reverse", "value", "array or string")),
+    }
+});
+handlebars_helper!(first: |items: array| items.first().cloned().unwrap_or(Json::Null));
+handlebars_helper!(last: |items: array| items.last().cloned().unwrap_or(Json::Null));
+handlebars_helper!(keys: |value: Json| {
+    match value {
+        Json::Object(m) => m.keys().map(|k| Json::String(k.clone())).collect::<Vec<Json>>(),
+        Json::Array(a) => (0..a.len()).map(Json::from).collect::<Vec<Json>>(),
+        _ => return Err(type_mismatch("keys", "value", "object or array")),
+    }
+});
+handlebars_helper!(values: |value: Json| {
+    match value {
+        Json::Object(m) => m.values().cloned().collect::<Vec<Json>>(),
+        Json::Array(a) => a.clone(),
+        _ => return Err(type_mismatch("values", "value", "object or array")),
+    }
+});
+handlebars_helper!(range: |start: i64, end: i64| {
+    if (end as i128) - (start as i128) > MAX_RANGE_LEN as i128 {
+        return Err(RenderError::from(RenderErrorReason::ResultTooLarge(
+            "range".to_owned(),
+            MAX_RANGE_LEN,
+        )));
+    }
+    (start..end).collect::<Vec<i64>>()
+});
+handlebars_helper!(includes: |collection: Json, value: Json| {
+    match collection {
+        Json::Array(a) => a.contains(value),
+        Json::Object(m) => m.values().any(|v| v == value),
+        _ => return Err(type_mismatch("includes", "collection", "array or object")),
+    }
+});
+
+pub(crate) fn register(r: &mut Registry<'_>) {
+    r.register_helper("sort-by", Box::new(sort_by));
+    r.register_helper("filter-by", Box::new(filter_by));
+    r.register_helper("group-by", Box::new(group_by));
+    r.register_helper("slice", Box::new(slice));
+    r.register_helper("pluck", Box::new(pluck));
+    r.register_helper("unique", Box::new(unique));
+    r.register_helper("reverse", Box::new(reverse));
+    r.register_helper("first", Box::new(first));
+    r.register_helper("last", Box::new(last));
+    r.register_helper("keys", Box::new(keys));
+    r.register_helper("values", Box::new(values));
+    r.register_helper("range", Box::new(range));
+    r.register_helper("includes", Box::new(includes));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::RenderErrorReason;
+    use crate::registry::Registry;
+
+    fn registry() -> Registry<'static> {
+        let mut hbs = Registry::new();
+        hbs.register_collection_helpers();
+        hbs
+    }
+
+    fn render(template: &str) -> String {
+        registry()
+            .render_template(
+                template,
+                &json!({
+                    "items": [
+                        {"name": "pen", "price": 2.5, "category": "office", "active": true},
+                        {"name": "mug", "price": 8, "category": "kitchen", "active": false},
+                        {"name": "ink", "price": 12, "category": "office", "active": true},
+                        {"name": "cap", "category": "misc", "active": true, "meta": {"rank": 1}},
+                    ],
+                    "tags": ["b", "a", "b", 1, "a", 1],
+                    "prices": {"pen": 2.5, "mug": 8},
+                }),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_sort_and_filter() {
+        assert_eq!(
+            render("{{#each (sort-by items \"price\")}}{{name}} {{/each}}"),
+            "cap pen mug ink "
+        );
+        assert_eq!(
+            render("{{#each (sort-by items \"price\" desc=true)}}{{name}} {{/each}}"),
+            "ink mug pen cap "
+        );
+        assert_eq!(
+            render("{{#each (sort-by items \"name\")}}{{@index}}{{name}} {{/each}}"),
+            "0cap 1ink 2mug 3pen "
+        );
+        assert_eq!(
+            render("{{#each (filter-by items \"active\" true)}}{{name}} {{/each}}"),
+            "pen ink cap "
+        );
+        assert_eq!(
+            render("{{#each (filter-by items \"meta.rank\" 1)}}{{name}}{{/each}}"),
+            "cap"
+        );
+        assert_eq!(
+            render("{{#each (filter-by items \"price\" 100)}}{{name}}{{else}}none{{/each}}"),
+            "none"
+        );
+    }
+
+    #[test]
+    fn test_group_by() {
+        assert_eq!(
+            render(
+                "{{#each (group-by items \"category\")}}{{key}}:{{#each items}} {{name}}{{/each}};{{/each}}"
+            ),
+            "office: pen ink;kitchen: mug;misc: cap;"
+        );
+
+        let data = json!({"items": [
+            {"k": {"a": 1, "b": [0.0]}}, {"k": 1}, {"k": {"b": [-0.0], "a": 1}}, {}, {"k": 1.0},
+        ]});
+        assert_eq!(
+            registry()
+                .render_template(
+                    "{{#each (group-by items \"k\")}}{{len items}}{{/each}}",
+                    &data
+                )
+                .unwrap(),
+            "2111"
+        );
+    }
+
+    #[test]
+    fn test_opt_in() {
+        let data = json!({"first": "a", "keys": "b"});
+        assert_eq!(
+            Registry::new()
+                .render_template("{{first}}{{keys}}", &data)
+                .unwrap(),
+            "ab"
+        );
+    }
+
+    #[test]
+    fn test_slice_and_pick() {
+        assert_eq!(
+            render("{{#each (slice items 1 3)}}{{name}} {{/each}}"),
+            "mug ink "
+        );
+        assert_eq!(
+            render("{{#each (slice items -2 10)}}{{name}} {{/each}}"),
+            "ink cap "
+        );
+        assert_eq!(render("{{len (slice items 3 1)}}"), "0");
+        assert_eq!(
+            render("{{#each (pluck items \"name\")}}{{this}} {{/each}}"),
+            "pen mug ink cap "
+        );
+        assert_eq!(
+            render("{{first (pluck items \"name\")}} {{last (pluck items \"name\")}}"),
+            "pen cap"
+        );
+        assert_eq!(render("[{{first (slice items 0 0)}}]"), "[]");
+        assert_eq!(render("{{#each (unique tags)}}{{this}}{{/each}}"), "ba1");
+        assert_eq!(
+            render("{{#each (reverse (unique tags))}}{{this}}{{/each}} {{reverse \"abc\"}}"),
+            "1ab cba"
+        );
+    }
+
+    #[test]
+    fn test_keys_values_range_includes() {
+        assert_eq!(
+            render("{{#each (keys prices)}}{{this}} {{/each}}{{#each (values prices)}}{{this}} {{/each}}"),
+            "mug pen 8 2.5 "
+        );
+        assert_eq!(render("{{#each (keys tags)}}{{this}}{{/each}}"), "012345");
+        assert_eq!(
+            render("{{#each (range 1 5)}}{{this}}{{/each}}|{{#each (range 3 3)}}x{{/each}}"),
+            "1234|"
+        );
+        assert_eq!(
+            render("{{includes tags 1}} {{includes tags \"c\"}} {{includes prices 8}}"),
+            "true false true"
+        );
+        assert_eq!(
+            render("{{#if (includes (pluck items \"category\") \"misc\")}}yes{{/if}}"),
+            "yes"
+        );
+
+        let hbs = registry();
+        assert!(hbs.render_template("{{keys 1}}", &()).is_err());
+        let e = hbs
+            .render_template("{{#each (range 0 1000000000000)}}{{/each}}", &())
+            .unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            RenderErrorReason::ResultTooLarge(_, _)
+        ));
+        assert!(hbs
+            .render_template(
+                "{{len (range -9223372036854775808 9223372036854775807)}}",
+                &()
+            )
+            .is_err());
+        assert!(hbs
+            .render_template("{{includes \"abc\" \"a\"}}", &())
+            .is_err());
+        assert!(hbs.render_template("{{sort-by 1 \"a\"}}", &()).is_err());
+    }
+}
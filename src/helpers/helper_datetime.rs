@@ -0,0 +1,308 @@
+//! Date and time helpers, enabled with the `datetime` feature
+//!
+//! Dates are RFC 3339 strings or seconds since the unix epoch.
+use std::fmt::{Display, Write};
+
+use chrono::{DateTime, FixedOffset, Local, Offset, TimeZone, Utc};
+use serde_json::Value as Json;
+
+use crate::context::Context;
+use crate::error::{RenderError, RenderErrorReason};
+use crate::helpers::HelperDef;
+use crate::json::value::ScopedJson;
+use crate::registry::Registry;
+use crate::render::{Helper, RenderContext};
+
+fn parse_datetime(helper: &str, value: &Json) -> Result<DateTime<FixedOffset>, RenderError> {
+    let parsed = match value {
+        Json::String(s) => DateTime::parse_from_rfc3339(s).ok(),
+        Json::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(secs), _) => Utc.timestamp_opt(secs, 0).single(),
+            (None, Some(secs)) if secs.is_finite() && secs.abs() < i64::MAX as f64 => {
+                let whole = secs.floor();
+                let nanos = ((secs - whole) * 1e9) as u32;
+                Utc.timestamp_opt(whole as i64, nanos).single()
+            }
+            _ => None,
+        }
+        .map(|dt| dt.with_timezone(&Utc.fix())),
+        _ => None,
+    };
+    parsed.ok_or_else(|| {
+        RenderErrorReason::ParamTypeMismatchForName(
+            helper.to_owned(),
+            "date".to_owned(),
+            "RFC 3339 string or epoch seconds".to_owned(),
+        )
+        .into()
+    })
+}
+
+/// Parse an offset like `+09:00` or `-0530`
+fn parse_offset(tz: &str) -> Option<FixedOffset> {
+    let (sign, rest) = match tz.as_bytes().first()? {
+        b'+' => (1, &tz[1..]),
+        b'-' => (-1, &tz[1..]),
+        _ => return None,
+    };
+    let digits: String = rest.chars().filter(|c| *c != ':').collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+fn format_in<Tz: TimeZone>(dt: DateTime<Tz>, format: &str) -> Result<String, RenderError>
+where
+    Tz::Offset: Display,
+{
+    let mut formatted = String::new();
+    write!(formatted, "{}", dt.format(format)).map_err(|_| {
+        RenderError::from(RenderErrorReason::HashTypeMismatchForName(
+            "format-date".to_owned(),
+            "format".to_owned(),
+            "strftime pattern".to_owned(),
+        ))
+    })?;
+    Ok(formatted)
+}
+
+// `tz` is "UTC", "local" or a fixed offset. Named zones like "Europe/Paris"
+// are rejected rather than guessed, they would need the tz database to
+// follow daylight saving time.
+handlebars_helper!(format_date: |date: Json, {format: str = "%Y-%m-%dT%H:%M:%S%:z", tz: str = ""}| {
+    let dt = parse_datetime("format-date", date)?;
+    // without `tz`, dates are formatted in their own offset
+    match tz {
+        "" => format_in(dt, format)?,
+        "UTC" | "utc" => format_in(dt.with_timezone(&Utc), format)?,
+        "local" => format_in(dt.with_timezone(&Local), format)?,
+        _ => match parse_offset(tz) {
+            Some(offset) => format_in(dt.with_timezone(&offset), format)?,
+            None => {
+                return Err(RenderError::from(RenderErrorReason::HashTypeMismatchForName(
+                    "format-date".to_owned(),
+                    "tz".to_owned(),
+                    "\"UTC\", \"local\" or an offset like \"+09:00\", not a named zone".to_owned(),
+                )))
+            }
+        },
+    }
+});
+
+/// Describe the time between `then` and `now`, like `3 days ago` or
+/// `in 2 hours`
+fn relative_time(now: DateTime<Utc>, then: DateTime<FixedOffset>) -> String {
+    let seconds = now.signed_duration_since(then).num_seconds();
+    let elapsed = seconds.saturating_abs();
+    if elapsed == 0 {
+        return "just now".to_owned();
+    }
+    let (count, unit) = match elapsed {
+        s if s < 60 => (s, "second"),
+        s if s < 3600 => (s / 60, "minute"),
+        s if s < 86400 => (s / 3600, "hour"),
+        s if s < 86400 * 30 => (s / 86400, "day"),
+        s if s < 86400 * 365 => (s / (86400 * 30), "month"),
+        s => (s / (86400 * 365), "year"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    if seconds > 0 {
+        format!("{} {}{} ago", count, unit, plural)
+    } else {
+        format!("in {} {}{}", count, unit, plural)
+    }
+}
+
+/// The `relative-time` helper, rendering dates like `3 days ago`
+///
+/// It is registered with the system clock. Register it again with
+/// `with_clock` to render against another clock, in tests for example:
+///
+/// ```
+/// use chrono::{DateTime, Utc};
+/// use handlebars::{Handlebars, RelativeTimeHelper};
+/// use serde_json::json;
+///
+/// let mut hbs = Handlebars::new();
+/// hbs.register_helper(
+///     "relative-time",
+///     Box::new(RelativeTimeHelper::with_clock(|| {
+///         DateTime::parse_from_rfc3339("2021-07-04T12:00:00Z")
+///             .unwrap()
+///             .with_timezone(&Utc)
+///     })),
+/// );
+/// assert_eq!(
+///     hbs.render_template(
+///         "{{relative-time posted}}",
+///         &json!({"posted": "2021-07-01T08:00:00Z"})
+///     )
+///     .unwrap(),
+///     "3 days ago"
+/// );
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "datetime")))]
+pub struct RelativeTimeHelper {
+    clock: Box<dyn Fn() -> DateTime<Utc> + Send + Sync>,
+}
+
+impl RelativeTimeHelper {
+    /// Create the helper with the system clock
+    pub fn new() -> RelativeTimeHelper {
+        RelativeTimeHelper::with_clock(Utc::now)
+    }
+
+    /// Create the helper with a clock returning the current time
+    pub fn with_clock<F>(clock: F) -> RelativeTimeHelper
+    where
+        F: Fn() -> DateTime<Utc> + Send + Sync + 'static,
+    {
+        RelativeTimeHelper {
+            clock: Box::new(clock),
+        }
+    }
+}
+
+impl Default for RelativeTimeHelper {
+    fn default() -> RelativeTimeHelper {
+        RelativeTimeHelper::new()
+    }
+}
+
+impl HelperDef for RelativeTimeHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Registry<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        let date = h.param(0).ok_or_else(|| {
+            RenderErrorReason::ParamNotFoundForIndex("relative-time".to_owned(), 0)
+        })?;
+        let then = parse_datetime("relative-time", date.value())?;
+        Ok(ScopedJson::Derived(Json::String(relative_time(
+            (self.clock)(),
+            then,
+        ))))
+    }
+}
+
+pub(crate) fn register(r: &mut Registry<'_>) {
+    r.register_helper("format-date", Box::new(format_date));
+    r.register_helper("relative-time", Box::new(RelativeTimeHelper::new()));
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{DateTime, Utc};
+
+    use super::RelativeTimeHelper;
+    use crate::error::RenderErrorReason;
+    use crate::registry::Registry;
+
+    fn render(template: &str) -> String {
+        let mut hbs = Registry::new();
+        hbs.register_helper(
+            "relative-time",
+            Box::new(RelativeTimeHelper::with_clock(|| {
+                DateTime::parse_from_rfc3339("2021-07-04T12:00:00Z")
+                    .unwrap()
+                    .with_timezone(&Utc)
+            })),
+        );
+        hbs.render_template(
+            template,
+            &json!({
+                "posted": "2021-07-01T08:30:00+02:00",
+                "epoch": 1625400000,
+                "precise": 1625400000.25,
+            }),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(
+            render("{{format-date posted}}"),
+            "2021-07-01T08:30:00+02:00"
+        );
+        assert_eq!(
+            render("{{format-date posted format=\"%d/%m/%Y %H:%M\" tz=\"UTC\"}}"),
+            "01/07/2021 06:30"
+        );
+        assert_eq!(
+            render("{{format-date posted format=\"%H:%M %z\" tz=\"-0530\"}}"),
+            "01:00 -0530"
+        );
+        assert_eq!(
+            render("{{format-date epoch format=\"%a %b %e %T %Z\"}}"),
+            "Sun Jul  4 12:00:00 +00:00"
+        );
+        assert_eq!(
+            render("{{format-date epoch format=\"%Y-%m-%d %H:%M %Z\" tz=\"utc\"}}"),
+            "2021-07-04 12:00 UTC"
+        );
+        assert_eq!(
+            render("{{format-date precise format=\"%S%.3f\" tz=\"+09:00\"}}"),
+            "00.250"
+        );
+        assert!(render("{{format-date epoch tz=\"local\"}}").starts_with("2021-07-0"));
+    }
+
+    #[test]
+    fn test_format_date_errors() {
+        let hbs = Registry::new();
+        for t in [
+            "{{format-date \"yesterday\"}}",
+            "{{format-date true}}",
+            "{{format-date 0 tz=\"Mars/Olympus\"}}",
+            "{{format-date 0 tz=\"Europe/Paris\"}}",
+            "{{format-date 0 tz=\"+9\"}}",
+            "{{format-date 0 format=\"%Q\"}}",
+        ]
+        .iter()
+        {
+            let e = hbs.render_template(t, &()).unwrap_err();
+            assert!(matches!(
+                e.reason(),
+                RenderErrorReason::ParamTypeMismatchForName(_, _, _)
+                    | RenderErrorReason::HashTypeMismatchForName(_, _, _)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_relative_time() {
+        assert_eq!(render("{{relative-time posted}}"), "3 days ago");
+        assert_eq!(render("{{relative-time epoch}}"), "just now");
+        assert_eq!(
+            render("{{relative-time \"2021-07-04T11:59:01Z\"}}"),
+            "59 seconds ago"
+        );
+        assert_eq!(
+            render("{{relative-time \"2021-07-04T11:00:00Z\"}}"),
+            "1 hour ago"
+        );
+        assert_eq!(
+            render("{{relative-time \"2021-07-04T12:01:30Z\"}}"),
+            "in 1 minute"
+        );
+        assert_eq!(
+            render("{{relative-time \"2021-09-10T12:00:00Z\"}}"),
+            "in 2 months"
+        );
+        assert_eq!(
+            render("{{relative-time \"2019-01-01T00:00:00Z\"}}"),
+            "2 years ago"
+        );
+        assert_eq!(
+            render("{{#if (eq (relative-time posted) \"3 days ago\")}}yes{{/if}}"),
+            "yes"
+        );
+    }
+}
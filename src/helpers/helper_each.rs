@@ -3,12 +3,15 @@ use serde_json::value::Value as Json;
 use super::block_util::create_block;
 use crate::block::{BlockContext, BlockParams};
 use crate::context::Context;
-use crate::error::RenderError;
+use crate::error::{RenderError, RenderErrorReason};
+#[cfg(feature = "async")]
+use crate::helpers::helper_async;
 use crate::helpers::{HelperDef, HelperResult};
 use crate::json::value::to_json;
 use crate::output::Output;
 use crate::registry::Registry;
 use crate::render::{Helper, RenderContext, Renderable};
+use crate::template::Template;
 use crate::util::copy_on_push_vec;
 
 fn update_block_context<'reg>(
@@ -60,6 +63,103 @@ fn set_block_param<'reg: 'rc, 'rc>(
     Ok(())
 }
 
+/// Iterate an iterator registered in the context, see `Context::register_iter`
+fn render_iter<'reg: 'rc, 'rc, I>(
+    iter: I,
+    t: &'reg Template,
+    h: &Helper<'reg, 'rc>,
+    r: &'reg Registry<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult
+where
+    I: Iterator<Item = Result<Json, RenderError>>,
+{
+    let mut iter = iter.peekable();
+    if iter.peek().is_none() {
+        if let Some(else_template) = h.inverse() {
+            return else_template.render(r, ctx, rc, out);
+        }
+    }
+
+    rc.push_block(BlockContext::new());
+
+    let mut i = 0;
+    while let Some(v) = iter.next() {
+        let v = v?;
+        if let Some(ref mut block) = rc.block_mut() {
+            let is_first = i == 0usize;
+            let is_last = iter.peek().is_none();
+
+            let index = to_json(i);
+            block.set_local_var("first", to_json(is_first));
+            block.set_local_var("last", to_json(is_last));
+            block.set_local_var("index", index.clone());
+
+            update_block_context(block, None, i.to_string(), is_first, &v);
+            set_block_param(block, h, None, &index, &v)?;
+        }
+
+        if let Some(limits) = rc.limits() {
+            limits.count_iteration()?;
+        }
+        t.render(r, ctx, rc, out)?;
+        i += 1;
+    }
+
+    rc.pop_block();
+    Ok(())
+}
+
+/// Iterate a lazy value by its keys, array indexes when `is_array`, see
+/// `ContextValue`
+#[allow(clippy::too_many_arguments)]
+fn render_lazy<'reg: 'rc, 'rc>(
+    keys: Vec<String>,
+    is_array: bool,
+    t: &'reg Template,
+    h: &Helper<'reg, 'rc>,
+    r: &'reg Registry<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = h.param(0).unwrap();
+    if keys.is_empty() {
+        if let Some(else_template) = h.inverse() {
+            return else_template.render(r, ctx, rc, out);
+        }
+    }
+
+    rc.push_block(create_block(value));
+
+    let len = keys.len();
+    let path = value.context_path();
+    for (i, k) in keys.into_iter().enumerate() {
+        if let Some(ref mut block) = rc.block_mut() {
+            let is_first = i == 0usize;
+            let is_last = i == len - 1;
+
+            let key = if is_array { to_json(i) } else { to_json(&k) };
+            block.set_local_var("first", to_json(is_first));
+            block.set_local_var("last", to_json(is_last));
+            block.set_local_var(if is_array { "index" } else { "key" }, key.clone());
+
+            update_block_context(block, path, k, is_first, &Json::Null);
+            set_block_param(block, h, path, &key, &Json::Null)?;
+        }
+
+        if let Some(limits) = rc.limits() {
+            limits.count_iteration()?;
+        }
+        t.render(r, ctx, rc, out)?;
+    }
+
+    rc.pop_block();
+    Ok(())
+}
+
 #[derive(Clone, Copy)]
 pub struct EachHelper;
 
@@ -74,10 +174,32 @@ impl HelperDef for EachHelper {
     ) -> HelperResult {
         let value = h
             .param(0)
-            .ok_or_else(|| RenderError::new("Param not found for helper \"each\""))?;
+            .ok_or_else(|| RenderErrorReason::ParamNotFoundForIndex("each".to_owned(), 0))?;
 
         let template = h.template();
 
+        if let (Some(t), Some(path)) = (template, value.context_path()) {
+            #[cfg(feature = "async")]
+            if let Some(calls) = rc.async_calls() {
+                if let Some(buffered) = helper_async::take_iter(&calls, ctx, path) {
+                    let mut buffered = buffered?;
+                    let result = render_iter(buffered.replay(), t, h, r, ctx, rc, out);
+                    helper_async::put_iter(&calls, path, buffered);
+                    return result;
+                }
+            }
+            if let Some(iter) = ctx.take_iter(path) {
+                return render_iter(iter?, t, h, r, ctx, rc, out);
+            }
+            let lazy_keys = ctx.with_lazy(path, |v| match v.len() {
+                Some(len) => Some(((0..len).map(|i| i.to_string()).collect(), true)),
+                None => v.keys().map(|keys| (keys, false)),
+            });
+            if let Some((keys, is_array)) = lazy_keys.flatten() {
+                return render_lazy(keys, is_array, t, h, r, ctx, rc, out);
+            }
+        }
+
         match template {
             Some(t) => match *value.value() {
                 Json::Array(ref list)
@@ -104,6 +226,9 @@ impl HelperDef for EachHelper {
                             set_block_param(block, h, array_path, &index, v)?;
                         }
 
+                        if let Some(limits) = rc.limits() {
+                            limits.count_iteration()?;
+                        }
                         t.render(r, ctx, rc, out)?;
                     }
 
@@ -134,6 +259,9 @@ impl HelperDef for EachHelper {
                             set_block_param(block, h, obj_path, &key, v)?;
                         }
 
+                        if let Some(limits) = rc.limits() {
+                            limits.count_iteration()?;
+                        }
                         t.render(r, ctx, rc, out)?;
                     }
 
@@ -159,6 +287,7 @@ pub static EACH_HELPER: EachHelper = EachHelper;
 
 #[cfg(test)]
 mod test {
+    use crate::context::Context;
     use crate::json::value::to_json;
     use crate::registry::Registry;
     use serde_json::value::Value as Json;
@@ -597,4 +726,22 @@ mod test {
             reg.render_template(tpl, &json!({"a": [0, 1]})).unwrap()
         );
     }
+
+    #[test]
+    fn test_each_iter() {
+        let reg = Registry::new();
+        let mut ctx = Context::wraps(json!({"sep": "|", "report": {"title": "t"}})).unwrap();
+        ctx.register_iter("report.rows", (1..4).map(|i| json!({"id": i})))
+            .unwrap();
+        ctx.register_iter("empty", Vec::<u8>::new()).unwrap();
+
+        let tpl = "{{#with report}}{{rows}}{{#if rows}}T{{/if}}{{#each rows as |row i|}}\
+                   {{@index}}{{i}}:{{row.id}}{{#if @first}}F{{/if}}{{#if @last}}L{{/if}}{{@root.sep}}\
+                   {{/each}}{{/with}}{{#unless empty}}E{{/unless}}{{#each empty}}x{{else}}none{{/each}}";
+        assert_eq!(
+            reg.render_template_with_context(tpl, &ctx).unwrap(),
+            "T00:1F|11:2|22:3L|Enone"
+        );
+        assert!(reg.render_template_with_context(tpl, &ctx).is_err());
+    }
 }
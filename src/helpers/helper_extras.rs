@@ -1,14 +1,36 @@
 //! Helpers for boolean operations
+use std::cmp::Ordering;
+
 use serde_json::Value as Json;
 
+use crate::error::{RenderError, RenderErrorReason};
 use crate::json::value::JsonTruthy;
 
+/// Compare two numbers, as integers when both are
+fn compare(helper: &str, x: &Json, y: &Json) -> Result<Ordering, RenderError> {
+    let number = |name: &str, v: &Json| {
+        v.as_f64().ok_or_else(|| {
+            RenderError::from(RenderErrorReason::ParamTypeMismatchForName(
+                helper.to_owned(),
+                name.to_owned(),
+                "number".to_owned(),
+            ))
+        })
+    };
+    match (x.as_i64(), y.as_i64()) {
+        (Some(x), Some(y)) => Ok(x.cmp(&y)),
+        _ => Ok(number("x", x)?
+            .partial_cmp(&number("y", y)?)
+            .unwrap_or(Ordering::Equal)),
+    }
+}
+
 handlebars_helper!(eq: |x: Json, y: Json| x == y);
 handlebars_helper!(ne: |x: Json, y: Json| x != y);
-handlebars_helper!(gt: |x: i64, y: i64| x > y);
-handlebars_helper!(gte: |x: i64, y: i64| x >= y);
-handlebars_helper!(lt: |x: i64, y: i64| x < y);
-handlebars_helper!(lte: |x: i64, y: i64| x <= y);
+handlebars_helper!(gt: |x: Json, y: Json| compare("gt", x, y)? == Ordering::Greater);
+handlebars_helper!(gte: |x: Json, y: Json| compare("gte", x, y)? != Ordering::Less);
+handlebars_helper!(lt: |x: Json, y: Json| compare("lt", x, y)? == Ordering::Less);
+handlebars_helper!(lte: |x: Json, y: Json| compare("lte", x, y)? != Ordering::Greater);
 handlebars_helper!(and: |x: Json, y: Json| x.is_truthy(false) && y.is_truthy(false));
 handlebars_helper!(or: |x: Json, y: Json| x.is_truthy(false) || y.is_truthy(false));
 handlebars_helper!(not: |x: Json| !x.is_truthy(false));
@@ -47,6 +69,22 @@ mod test_conditions {
         test_condition("(and null 4)", false);
     }
 
+    #[test]
+    fn test_compare_floats() {
+        test_condition("(gt 9.999 9.99)", true);
+        test_condition("(gt 9 9.99)", false);
+        test_condition("(gte 10 9.99)", true);
+        test_condition("(lt -0.5 0)", true);
+        test_condition("(lte 2.0 2)", true);
+        test_condition("(gt 9223372036854775807 9223372036854775806)", true);
+        test_condition("(gt 18446744073709551615 9223372036854775807)", true);
+
+        let handlebars = crate::Handlebars::new();
+        assert!(handlebars
+            .render_template("{{#if (gt price \"9.99\")}}x{{/if}}", &json!({"price": 10}))
+            .is_err());
+    }
+
     #[test]
     fn test_eq() {
         test_condition("(eq 5 5)", true);
@@ -1,7 +1,7 @@
+use super::block_util::is_param_truthy;
 use crate::context::Context;
-use crate::error::RenderError;
+use crate::error::RenderErrorReason;
 use crate::helpers::{HelperDef, HelperResult};
-use crate::json::value::JsonTruthy;
 use crate::output::Output;
 use crate::registry::Registry;
 use crate::render::{Helper, RenderContext, Renderable};
@@ -22,13 +22,13 @@ impl HelperDef for IfHelper {
     ) -> HelperResult {
         let param = h
             .param(0)
-            .ok_or_else(|| RenderError::new("Param not found for helper \"if\""))?;
+            .ok_or_else(|| RenderErrorReason::ParamNotFoundForIndex("if".to_owned(), 0))?;
         let include_zero = h
             .hash_get("includeZero")
             .and_then(|v| v.value().as_bool())
             .unwrap_or(false);
 
-        let mut value = param.value().is_truthy(include_zero);
+        let mut value = is_param_truthy(param, ctx, rc, include_zero)?;
 
         if !self.positive {
             value = !value;
@@ -186,4 +186,35 @@ mod test {
             .unwrap()
         );
     }
+
+    #[test]
+    fn test_chained_else() {
+        let hbs = Registry::new();
+        let tpl = "{{#if a}}A{{else if b}}B{{else unless c}}C{{else}}D{{/if}}";
+        for (data, expected) in [
+            (json!({"a": true}), "A"),
+            (json!({"b": true}), "B"),
+            (json!({"c": false}), "C"),
+            (json!({"c": true}), "D"),
+        ]
+        .iter()
+        {
+            assert_eq!(hbs.render_template(tpl, data).unwrap(), *expected);
+        }
+
+        let tpl = "{{#each list}}{{this}}{{else with obj as |o|}}{{o.x}}{{else}}none{{/each}}";
+        let data = json!({"list": [], "obj": {"x": 1}});
+        assert_eq!(hbs.render_template(tpl, &data).unwrap(), "1");
+        assert_eq!(hbs.render_template(tpl, &json!({})).unwrap(), "none");
+
+        let tpl = "{{#if a}}\n  A\n{{~else if b~}}\n  B\n{{else}}\n  C\n{{/if}}\n";
+        assert_eq!(
+            hbs.render_template(tpl, &json!({"b": true})).unwrap(),
+            "B\n"
+        );
+        assert_eq!(hbs.render_template(tpl, &json!({})).unwrap(), "  C\n");
+        assert!(hbs
+            .render_template("{{#if a}}{{else if b}}{{/unless}}", &())
+            .is_err());
+    }
 }
@@ -0,0 +1,314 @@
+//! Helpers for serializing values into the output, and parsing JSON strings
+//!
+//! `to-yaml` and `to-toml` are enabled with the `yaml_helper` and
+//! `toml_helper` features.
+use serde_json::Value as Json;
+
+use crate::context::Context;
+use crate::error::{RenderError, RenderErrorReason};
+use crate::escape::{Attr, HtmlContext, Js};
+use crate::helpers::HelperDef;
+use crate::json::value::{SafeString, ScopedJson};
+use crate::registry::Registry;
+use crate::render::{Helper, RenderContext};
+use crate::support::str::escape_html;
+
+/// Escape the chars that may close a script block or end a JS string
+///
+/// They can only appear in JSON strings, where `\uXXXX` is equivalent.
+fn escape_script(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => escaped.push_str("\\u003c"),
+            '>' => escaped.push_str("\\u003e"),
+            '&' => escaped.push_str("\\u0026"),
+            '\u{2028}' => escaped.push_str("\\u2028"),
+            '\u{2029}' => escaped.push_str("\\u2029"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The `json` helper
+///
+/// Its result is escaped like other values, except where it is read as
+/// javascript by contextual escaping: in scripts, and entity encoded in
+/// event handlers.
+#[derive(Clone, Copy)]
+pub(crate) struct JsonHelper;
+
+impl HelperDef for JsonHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Registry<'reg>,
+        _: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        let value = h
+            .param(0)
+            .ok_or_else(|| RenderErrorReason::ParamNotFoundForIndex("json".to_owned(), 0))?;
+        let pretty = match h.hash_get("pretty").map(|v| v.value()) {
+            None => false,
+            Some(Json::Bool(b)) => *b,
+            Some(_) => {
+                return Err(RenderErrorReason::HashTypeMismatchForName(
+                    "json".to_owned(),
+                    "pretty".to_owned(),
+                    "bool".to_owned(),
+                )
+                .into())
+            }
+        };
+        let serialized = if pretty {
+            serde_json::to_string_pretty(value.value())?
+        } else {
+            serde_json::to_string(value.value())?
+        };
+        let serialized = escape_script(&serialized);
+        match rc.html_context() {
+            Some(HtmlContext::Script(Js::Code(_))) if r.contextual_escape() => {
+                Ok(SafeString::new(serialized).into())
+            }
+            Some(HtmlContext::Value {
+                attr: Attr::Script,
+                quote: Some(_),
+                js: Js::Code(_),
+                ..
+            }) if r.contextual_escape() => Ok(SafeString::new(escape_html(&serialized)).into()),
+            _ => Ok(ScopedJson::Derived(Json::String(serialized))),
+        }
+    }
+}
+
+pub(crate) static JSON_HELPER: JsonHelper = JsonHelper;
+
+handlebars_helper!(parse_json: |s: str| serde_json::from_str::<Json>(s)?);
+
+/// Write a value as YAML, without the document start
+#[cfg(feature = "yaml_helper")]
+fn to_yaml(value: &Json) -> Result<String, RenderError> {
+    let yaml = serde_yaml::to_string(value).map_err(RenderErrorReason::YamlError)?;
+    let yaml = yaml.strip_prefix("---\n").unwrap_or(&yaml);
+    Ok(yaml.trim_end_matches('\n').to_owned())
+}
+
+#[cfg(feature = "yaml_helper")]
+handlebars_helper!(to_yaml_helper: |value: Json| to_yaml(value)?);
+
+/// The value without its `null` object fields, which TOML can't write
+#[cfg(feature = "toml_helper")]
+fn without_nulls(value: &Json) -> Json {
+    match value {
+        Json::Object(m) => Json::Object(
+            m.iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k.clone(), without_nulls(v)))
+                .collect(),
+        ),
+        Json::Array(a) => Json::Array(a.iter().map(without_nulls).collect()),
+        _ => value.clone(),
+    }
+}
+
+/// Write an object as a TOML document
+#[cfg(feature = "toml_helper")]
+fn to_toml(value: &Json) -> Result<String, RenderError> {
+    if !value.is_object() {
+        return Err(RenderErrorReason::ParamTypeMismatchForName(
+            "to-toml".to_owned(),
+            "value".to_owned(),
+            "object".to_owned(),
+        )
+        .into());
+    }
+    // converted to a TOML value first, so tables are written after fields
+    let toml = ::toml::Value::try_from(without_nulls(value))
+        .and_then(|v| ::toml::to_string(&v))
+        .map_err(RenderErrorReason::TomlError)?;
+    Ok(toml.trim_end_matches('\n').to_owned())
+}
+
+#[cfg(feature = "toml_helper")]
+handlebars_helper!(to_toml_helper: |value: Json| to_toml(value)?);
+
+/// Register `json` and `parse-json`, and `to-yaml` and `to-toml` with their
+/// features
+pub(crate) fn register(r: &mut Registry<'_>) {
+    r.register_helper("json", Box::new(JSON_HELPER));
+    r.register_helper("parse-json", Box::new(parse_json));
+    #[cfg(feature = "yaml_helper")]
+    r.register_helper("to-yaml", Box::new(to_yaml_helper));
+    #[cfg(feature = "toml_helper")]
+    r.register_helper("to-toml", Box::new(to_toml_helper));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::registry::Registry;
+
+    fn registry() -> Registry<'static> {
+        let mut hbs = Registry::new();
+        hbs.register_json_helpers();
+        hbs
+    }
+
+    fn render(template: &str) -> String {
+        registry()
+            .render_template(
+                template,
+                &json!({
+                    "user": {"name": "</script><script>alert(1)</script>", "tags": ["a&b"]},
+                    "raw": "{\"items\": [{\"id\": 1}, {\"id\": 2}], \"title\": \"T\"}",
+                    "sep": "\u{2028}",
+                }),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(
+            render("<script>var u = {{{json user}}};</script>"),
+            "<script>var u = {\"name\":\"\\u003c/script\\u003e\\u003cscript\\u003ealert(1)\\u003c/script\\u003e\",\"tags\":[\"a\\u0026b\"]};</script>"
+        );
+        assert_eq!(
+            render("{{{json user.tags pretty=true}}}"),
+            "[\n  \"a\\u0026b\"\n]"
+        );
+        assert_eq!(
+            render("{{{json sep}}} {{json 1.5}} {{json missing}}"),
+            "\"\\u2028\" 1.5 null"
+        );
+        assert_eq!(
+            render("<div data-x=\"{{json user.tags}}\">"),
+            "<div data-x=\"[&quot;a\\u0026b&quot;]\">"
+        );
+
+        let parsed: serde_json::Value =
+            serde_json::from_str(&render("{{{json user pretty=true}}}")).unwrap();
+        assert_eq!(parsed["name"], "</script><script>alert(1)</script>");
+    }
+
+    #[test]
+    fn test_json_contextual_escape() {
+        let mut hbs = registry();
+        hbs.set_contextual_escape(true);
+        let data = json!({"v": {"a": "\"><script>x</script>", "b": "'"}});
+        let render = |tpl: &str| hbs.render_template(tpl, &data).unwrap();
+
+        assert_eq!(
+            render("<script>var v = {{json v}};</script>"),
+            "<script>var v = {\"a\":\"\\\"\\u003e\\u003cscript\\u003ex\\u003c/script\\u003e\",\"b\":\"'\"};</script>"
+        );
+        assert_eq!(
+            render("<button onclick=\"go({{json v.b}})\">"),
+            "<button onclick=\"go(&quot;&#x27;&quot;)\">"
+        );
+        assert_eq!(
+            render("<div data-x=\"{{json v}}\" title='{{json v.b}}'>"),
+            "<div data-x=\"{&quot;a&quot;:&quot;\\&quot;\\u003e\\u003cscript\\u003ex\\u003c/script\\u003e&quot;,&quot;b&quot;:&quot;&#x27;&quot;}\" title='&quot;&#x27;&quot;'>"
+        );
+    }
+
+    #[test]
+    fn test_parse_json() {
+        assert_eq!(
+            render("{{#each (lookup (parse-json raw) \"items\")}}{{id}}{{/each}}"),
+            "12"
+        );
+        assert_eq!(
+            render("{{#with (parse-json raw)}}{{title}} {{len items}}{{/with}}"),
+            "T 2"
+        );
+        assert_eq!(
+            render("{{{json (parse-json raw)}}}"),
+            "{\"items\":[{\"id\":1},{\"id\":2}],\"title\":\"T\"}"
+        );
+        assert!(registry()
+            .render_template("{{parse-json \"{oops\"}}", &())
+            .is_err());
+    }
+
+    #[test]
+    fn test_json_opt_in() {
+        let data = json!({"json": "x"});
+        assert_eq!(
+            Registry::new().render_template("{{json}}", &data).unwrap(),
+            "x"
+        );
+        assert_eq!(
+            registry().render_template("{{json json}}", &data).unwrap(),
+            "&quot;x&quot;"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "yaml_helper")]
+    fn test_to_yaml() {
+        use super::to_yaml;
+
+        let value = json!({
+            "name": "site",
+            "quoted": ["yes", "a: b", "", "two\nlines", null],
+            "servers": [{"host": "a.example.com", "ports": [80, 443]}],
+        });
+        let yaml = to_yaml(&value).unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<serde_json::Value>(&yaml).unwrap(),
+            value
+        );
+        assert!(!yaml.starts_with("---"));
+        assert_eq!(to_yaml(&json!("plain")).unwrap(), "plain");
+        assert_eq!(
+            registry()
+                .render_template("{{{to-yaml this}}}", &json!({"a": [1]}))
+                .unwrap(),
+            "a:\n  - 1"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "toml_helper")]
+    fn test_to_toml() {
+        use super::to_toml;
+        use crate::error::RenderErrorReason;
+
+        let value = json!({
+            "title": "site",
+            "skip": null,
+            "ports": [80, 443],
+            "owner": {"name": "Tom", "address": {"city": "a b"}},
+            "servers": [{"host": "a"}, {"host": "b", "tags": ["x"]}],
+        });
+        assert_eq!(
+            to_toml(&value).unwrap(),
+            "ports = [80, 443]
+title = \"site\"
+
+[[servers]]
+host = \"a\"
+
+[[servers]]
+host = \"b\"
+tags = [\"x\"]
+
+[owner]
+name = \"Tom\"
+
+[owner.address]
+city = \"a b\""
+        );
+        assert!(to_toml(&json!([1])).is_err());
+        let e = to_toml(&json!({"a": [null]})).unwrap_err();
+        assert!(matches!(e.reason(), RenderErrorReason::TomlError(_)));
+        assert_eq!(
+            registry()
+                .render_template("{{{to-toml this}}}", &json!({"a": {"b": true}}))
+                .unwrap(),
+            "[a]\nb = true"
+        );
+    }
+}
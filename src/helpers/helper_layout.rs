@@ -0,0 +1,235 @@
+use crate::context::Context;
+use crate::error::{RenderError, RenderErrorReason};
+use crate::helpers::{HelperDef, HelperResult};
+use crate::output::Output;
+use crate::registry::Registry;
+use crate::render::{Evaluable, Helper, RenderContext, Renderable};
+use crate::template::Template;
+use crate::template::TemplateElement::*;
+
+/// A `block` being rendered
+///
+/// Its content is looked up by level: the overrides from templates
+/// extending the layout, from the outermost one, then the default content
+/// of the block. `super` renders the next level.
+#[derive(Clone, Debug)]
+pub(crate) struct BlockFrame<'reg> {
+    pub(crate) name: String,
+    pub(crate) level: usize,
+    pub(crate) default: Option<&'reg Template>,
+}
+
+fn render_block<'reg: 'rc, 'rc>(
+    frame: BlockFrame<'reg>,
+    r: &'reg Registry<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let overrides = rc.layout_blocks(&frame.name);
+    let content = if frame.level < overrides.len() {
+        Some(overrides[frame.level])
+    } else if frame.level == overrides.len() {
+        frame.default
+    } else {
+        None
+    };
+
+    if let Some(t) = content {
+        rc.push_block_frame(frame);
+        let result = t.render(r, ctx, rc, out);
+        rc.pop_block_frame();
+        result
+    } else {
+        Ok(())
+    }
+}
+
+fn name_param(h: &Helper<'_, '_>) -> Result<String, RenderError> {
+    h.param(0)
+        .ok_or_else(|| RenderErrorReason::ParamNotFoundForIndex(h.name().to_owned(), 0))?
+        .value()
+        .as_str()
+        .map(str::to_owned)
+        .ok_or_else(|| RenderErrorReason::InvalidParamType("string".to_owned()).into())
+}
+
+/// Render a layout, overriding its blocks with the `block`s given inside
+///
+/// Content outside of `block`s is ignored, except decorators like `*inline`.
+#[derive(Clone, Copy)]
+pub struct ExtendHelper;
+
+impl HelperDef for ExtendHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Registry<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let name = name_param(h)?;
+        if rc.is_current_template(&name) {
+            return Err(RenderErrorReason::CannotIncludeSelf.into());
+        }
+
+        let scope = rc.get_scope_template_name().map(String::as_str);
+        let layout = match r.get_or_load_partial(&name, scope) {
+            Some((found, _)) if rc.is_current_template(&found) => {
+                return Err(RenderErrorReason::CannotIncludeSelf.into());
+            }
+            Some((_, layout)) => layout?,
+            None => return Err(RenderErrorReason::TemplateNotFound(name).into()),
+        };
+
+        // clone to avoid lifetime issue, like partials
+        let mut local_rc = rc.clone();
+        if let Some(t) = h.template() {
+            for e in &t.elements {
+                match *e {
+                    HelperBlock(ref ht) if ht.name.as_name() == Some("block") => {
+                        let block_name = match ht.params.first() {
+                            Some(p) => p.expand(r, ctx, &mut local_rc)?,
+                            None => continue,
+                        };
+                        if let (Some(block_name), Some(content)) =
+                            (block_name.value().as_str(), ht.template.as_ref())
+                        {
+                            local_rc.add_layout_block(block_name.to_owned(), content);
+                        }
+                    }
+                    DecoratorExpression(_) | DecoratorBlock(_) => e.eval(r, ctx, &mut local_rc)?,
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(limits) = local_rc.limits() {
+            limits.enter_partial()?;
+        }
+        let result = layout.render(r, ctx, &mut local_rc, out);
+
+        if let Some(limits) = local_rc.limits() {
+            limits.leave_partial();
+        }
+        result
+    }
+}
+
+/// Define a block in a layout with its default content, or override it
+/// inside `extend`
+#[derive(Clone, Copy)]
+pub struct BlockHelper;
+
+impl HelperDef for BlockHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Registry<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let frame = BlockFrame {
+            name: name_param(h)?,
+            level: 0,
+            default: h.template(),
+        };
+        render_block(frame, r, ctx, rc, out)
+    }
+}
+
+/// Render the content a block override replaces
+#[derive(Clone, Copy)]
+pub struct SuperHelper;
+
+impl HelperDef for SuperHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        _: &Helper<'reg, 'rc>,
+        r: &'reg Registry<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let mut frame = rc
+            .block_frame()
+            .cloned()
+            .ok_or_else(|| RenderErrorReason::BlockRequired("super".to_owned()))?;
+        frame.level += 1;
+        render_block(frame, r, ctx, rc, out)
+    }
+}
+
+pub static EXTEND_HELPER: ExtendHelper = ExtendHelper;
+pub static BLOCK_HELPER: BlockHelper = BlockHelper;
+pub static SUPER_HELPER: SuperHelper = SuperHelper;
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::error::RenderErrorReason;
+    use crate::registry::Registry;
+
+    fn layouts() -> Registry<'static> {
+        let mut hbs = Registry::new();
+        hbs.register_layout_helpers();
+        hbs.register_template_string(
+            "base",
+            "<title>{{#block \"title\"}}Site{{/block}}</title>\
+             <main>{{#block \"content\"}}nothing{{/block}}</main>\
+             <aside>{{#block \"sidebar\"}}links{{/block}}</aside>",
+        )
+        .unwrap();
+        hbs.register_template_string(
+            "docs",
+            "{{#extend \"base\"}}\
+               {{#block \"title\"}}Docs - {{super}}{{/block}}\
+               {{#block \"sidebar\"}}toc, {{super}}{{/block}}\
+             {{/extend}}",
+        )
+        .unwrap();
+        hbs
+    }
+
+    #[test]
+    fn test_layout() {
+        let hbs = layouts();
+        assert_eq!(
+            hbs.render("base", &()).unwrap(),
+            "<title>Site</title><main>nothing</main><aside>links</aside>"
+        );
+        assert_eq!(
+            hbs.render("docs", &()).unwrap(),
+            "<title>Docs - Site</title><main>nothing</main><aside>toc, links</aside>"
+        );
+
+        let page = "{{#extend \"docs\"}}\
+                      ignored\
+                      {{#block \"title\"}}{{name}} | {{super}}{{/block}}\
+                      {{#block \"content\"}}{{#each items}}{{#block \"item\"}}{{this}}{{/block}}{{/each}}{{/block}}\
+                    {{/extend}}";
+        let data = json!({"name": "Intro", "items": [1, 2]});
+        assert_eq!(
+            hbs.render_template(page, &data).unwrap(),
+            "<title>Intro | Docs - Site</title><main>12</main><aside>toc, links</aside>"
+        );
+    }
+
+    #[test]
+    fn test_layout_errors() {
+        let mut hbs = layouts();
+        hbs.register_template_string("self", "{{#extend \"self\"}}{{/extend}}")
+            .unwrap();
+
+        let e = hbs.render("self", &()).unwrap_err();
+        assert!(matches!(e.reason(), RenderErrorReason::CannotIncludeSelf));
+        let e = hbs
+            .render_template("{{#extend \"missing\"}}{{/extend}}", &())
+            .unwrap_err();
+        assert!(matches!(e.reason(), RenderErrorReason::TemplateNotFound(_)));
+        assert!(hbs.render_template("{{super}}", &()).is_err());
+    }
+}
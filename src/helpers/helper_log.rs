@@ -1,6 +1,8 @@
 use crate::context::Context;
 #[cfg(not(feature = "no_logging"))]
-use crate::error::RenderError;
+use crate::error::RenderErrorReason;
+#[cfg(all(feature = "async", not(feature = "no_logging")))]
+use crate::helpers::helper_async;
 use crate::helpers::{HelperDef, HelperResult};
 #[cfg(not(feature = "no_logging"))]
 use crate::json::value::JsonRender;
@@ -15,6 +17,20 @@ use std::str::FromStr;
 #[derive(Clone, Copy)]
 pub struct LogHelper;
 
+/// Log a record, deferred to the final pass of an async render
+#[cfg(all(feature = "async", not(feature = "no_logging")))]
+fn emit(rc: &RenderContext<'_, '_>, level: Level, message: String) {
+    match rc.async_calls() {
+        Some(calls) => helper_async::defer_log(&calls, level, message),
+        None => log!(level, "{}", message),
+    }
+}
+
+#[cfg(all(not(feature = "async"), not(feature = "no_logging")))]
+fn emit(_: &RenderContext<'_, '_>, level: Level, message: String) {
+    log!(level, "{}", message)
+}
+
 #[cfg(not(feature = "no_logging"))]
 impl HelperDef for LogHelper {
     fn call<'reg: 'rc, 'rc>(
@@ -22,7 +38,7 @@ impl HelperDef for LogHelper {
         h: &Helper<'reg, 'rc>,
         _: &'reg Registry<'reg>,
         _: &'rc Context,
-        _: &mut RenderContext<'reg, 'rc>,
+        rc: &mut RenderContext<'reg, 'rc>,
         _: &mut dyn Output,
     ) -> HelperResult {
         let param_to_log = h
@@ -44,12 +60,9 @@ impl HelperDef for LogHelper {
             .unwrap_or("info");
 
         if let Ok(log_level) = Level::from_str(level) {
-            log!(log_level, "{}", param_to_log)
+            emit(rc, log_level, param_to_log)
         } else {
-            return Err(RenderError::new(&format!(
-                "Unsupported logging level {}",
-                level
-            )));
+            return Err(RenderErrorReason::InvalidLoggingLevel(level.to_owned()).into());
         }
         Ok(())
     }
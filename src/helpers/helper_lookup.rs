@@ -1,7 +1,7 @@
 use serde_json::value::Value as Json;
 
 use crate::context::Context;
-use crate::error::RenderError;
+use crate::error::{RenderError, RenderErrorReason};
 use crate::helpers::HelperDef;
 use crate::json::value::ScopedJson;
 use crate::registry::Registry;
@@ -20,10 +20,10 @@ impl HelperDef for LookupHelper {
     ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
         let collection_value = h
             .param(0)
-            .ok_or_else(|| RenderError::new("Param not found for helper \"lookup\""))?;
+            .ok_or_else(|| RenderErrorReason::ParamNotFoundForIndex("lookup".to_owned(), 0))?;
         let index = h
             .param(1)
-            .ok_or_else(|| RenderError::new("Insufficient params for helper \"lookup\""))?;
+            .ok_or_else(|| RenderErrorReason::ParamNotFoundForIndex("lookup".to_owned(), 1))?;
 
         let value = match *collection_value.value() {
             Json::Array(ref v) => index
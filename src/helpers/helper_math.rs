@@ -0,0 +1,390 @@
+//! Numeric helpers, enabled with the `math_helpers` feature
+//!
+//! Integers stay integers as long as the result fits in an `i64`, other
+//! numbers are computed as floats.
+use serde_json::Value as Json;
+
+use crate::error::{RenderError, RenderErrorReason};
+use crate::registry::Registry;
+
+/// Decimals above this are rejected by `format-number` instead of being
+/// allocated
+const MAX_PRECISION: i64 = 1_000;
+
+/// Decimals of `round`, `floor` and `ceil` within the exponents of floats,
+/// others are rejected
+const MAX_ROUND_PRECISION: i64 = f64::MAX_10_EXP as i64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn from_json(helper: &str, name: &str, value: &Json) -> Result<Number, RenderError> {
+        match value {
+            Json::Number(n) => Ok(n
+                .as_i64()
+                .map(Number::Int)
+                .unwrap_or_else(|| Number::Float(n.as_f64().unwrap_or(f64::NAN)))),
+            _ => Err(RenderErrorReason::ParamTypeMismatchForName(
+                helper.to_owned(),
+                name.to_owned(),
+                "number".to_owned(),
+            )
+            .into()),
+        }
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+
+    fn is_zero(self) -> bool {
+        match self {
+            Number::Int(i) => i == 0,
+            Number::Float(f) => f == 0.0,
+        }
+    }
+
+    /// Apply `int` on integers, falling back to `float` when either is a
+    /// float or the result overflows
+    fn apply(
+        self,
+        other: Number,
+        int: fn(i64, i64) -> Option<i64>,
+        float: fn(f64, f64) -> f64,
+    ) -> Number {
+        if let (Number::Int(x), Number::Int(y)) = (self, other) {
+            if let Some(r) = int(x, y) {
+                return Number::Int(r);
+            }
+        }
+        Number::Float(float(self.as_f64(), other.as_f64()))
+    }
+
+    fn into_json(self) -> Json {
+        match self {
+            Number::Int(i) => Json::from(i),
+            Number::Float(f) => Json::from(f),
+        }
+    }
+}
+
+fn operands(helper: &str, x: &Json, y: &Json) -> Result<(Number, Number), RenderError> {
+    Ok((
+        Number::from_json(helper, "x", x)?,
+        Number::from_json(helper, "y", y)?,
+    ))
+}
+
+fn divisor(helper: &str, x: &Json, y: &Json) -> Result<(Number, Number), RenderError> {
+    let (x, y) = operands(helper, x, y)?;
+    if y.is_zero() {
+        Err(RenderErrorReason::DivisionByZero(helper.to_owned()).into())
+    } else {
+        Ok((x, y))
+    }
+}
+
+/// Round to `precision` decimals, keeping integers as they are
+fn round_with(
+    helper: &str,
+    x: Number,
+    precision: i64,
+    f: fn(f64) -> f64,
+) -> Result<Number, RenderError> {
+    if !(-MAX_ROUND_PRECISION..=MAX_ROUND_PRECISION).contains(&precision) {
+        return Err(RenderErrorReason::HashTypeMismatchForName(
+            helper.to_owned(),
+            "precision".to_owned(),
+            format!(
+                "integer from -{} to {}",
+                MAX_ROUND_PRECISION, MAX_ROUND_PRECISION
+            ),
+        )
+        .into());
+    }
+    match x {
+        Number::Int(_) if precision >= 0 => Ok(x),
+        _ => {
+            let factor = 10f64.powi(precision as i32);
+            let scaled = x.as_f64() * factor;
+            // more decimals than a float has leave it as it is
+            if !scaled.is_finite() {
+                return Ok(x);
+            }
+            let rounded = f(scaled) / factor;
+            if precision <= 0 && rounded.abs() < i64::MAX as f64 {
+                Ok(Number::Int(rounded as i64))
+            } else {
+                Ok(Number::Float(rounded))
+            }
+        }
+    }
+}
+
+/// The smallest or largest of the params, or of the items of a single array
+fn extremum(
+    helper: &str,
+    args: Vec<&Json>,
+    pick: fn(&Number, &Number) -> bool,
+) -> Result<Json, RenderError> {
+    let values = match args.as_slice() {
+        [Json::Array(items)] => items.iter().collect(),
+        _ => args,
+    };
+    let mut result: Option<Number> = None;
+    for v in values {
+        let n = Number::from_json(helper, "x", v)?;
+        if result.map(|r| pick(&n, &r)).unwrap_or(true) {
+            result = Some(n);
+        }
+    }
+    result
+        .map(Number::into_json)
+        .ok_or_else(|| RenderErrorReason::ParamNotFoundForIndex(helper.to_owned(), 0).into())
+}
+
+fn less(x: &Number, y: &Number) -> bool {
+    match (x, y) {
+        (Number::Int(x), Number::Int(y)) => x < y,
+        _ => x.as_f64() < y.as_f64(),
+    }
+}
+
+/// Insert `separator` between groups of 3 digits
+fn group_digits(digits: &str, separator: &str) -> String {
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3 * separator.len());
+    // digits before the first separator
+    let head = digits.len() % 3;
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && i % 3 == head {
+            grouped.push_str(separator);
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+handlebars_helper!(add: |x: Json, y: Json| {
+    let (x, y) = operands("add", x, y)?;
+    x.apply(y, i64::checked_add, |x, y| x + y).into_json()
+});
+handlebars_helper!(sub: |x: Json, y: Json| {
+    let (x, y) = operands("sub", x, y)?;
+    x.apply(y, i64::checked_sub, |x, y| x - y).into_json()
+});
+handlebars_helper!(mul: |x: Json, y: Json| {
+    let (x, y) = operands("mul", x, y)?;
+    x.apply(y, i64::checked_mul, |x, y| x * y).into_json()
+});
+handlebars_helper!(div: |x: Json, y: Json| {
+    let (x, y) = divisor("div", x, y)?;
+    // integers are kept only when the division is exact
+    x.apply(
+        y,
+        |x, y| x.checked_rem(y).filter(|r| *r == 0).and_then(|_| x.checked_div(y)),
+        |x, y| x / y,
+    )
+    .into_json()
+});
+handlebars_helper!(modulo: |x: Json, y: Json| {
+    let (x, y) = divisor("mod", x, y)?;
+    x.apply(y, i64::checked_rem, |x, y| x % y).into_json()
+});
+handlebars_helper!(round: |x: Json, {precision: i64 = 0}| {
+    round_with("round", Number::from_json("round", "x", x)?, precision, f64::round)?.into_json()
+});
+handlebars_helper!(floor: |x: Json, {precision: i64 = 0}| {
+    round_with("floor", Number::from_json("floor", "x", x)?, precision, f64::floor)?.into_json()
+});
+handlebars_helper!(ceil: |x: Json, {precision: i64 = 0}| {
+    round_with("ceil", Number::from_json("ceil", "x", x)?, precision, f64::ceil)?.into_json()
+});
+handlebars_helper!(abs: |x: Json| {
+    match Number::from_json("abs", "x", x)? {
+        Number::Int(i) => i
+            .checked_abs()
+            .map(Number::Int)
+            .unwrap_or_else(|| Number::Float((i as f64).abs())),
+        Number::Float(f) => Number::Float(f.abs()),
+    }
+    .into_json()
+});
+handlebars_helper!(min: |*args| extremum("min", args, less)?);
+handlebars_helper!(max: |*args| extremum("max", args, |x, y| less(y, x))?);
+handlebars_helper!(format_number: |x: Json, {precision: i64 = -1, separator: str = "", decimal: str = "."}| {
+    if precision > MAX_PRECISION {
+        return Err(RenderError::from(RenderErrorReason::ResultTooLarge(
+            "format-number".to_owned(),
+            MAX_PRECISION as usize,
+        )));
+    }
+    let x = Number::from_json("format-number", "x", x)?;
+    let formatted = match (x, precision) {
+        (Number::Int(i), p) if p <= 0 => i.to_string(),
+        (_, p) if p >= 0 => format!("{:.*}", p as usize, x.as_f64()),
+        _ => x.as_f64().to_string(),
+    };
+    let (sign, unsigned) = match formatted.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", formatted.as_str()),
+    };
+    let mut parts = unsigned.splitn(2, '.');
+    let int_part = parts.next().unwrap_or("");
+    match parts.next() {
+        Some(fraction) => format!("{}{}{}{}", sign, group_digits(int_part, separator), decimal, fraction),
+        None => format!("{}{}", sign, group_digits(int_part, separator)),
+    }
+});
+
+pub(crate) fn register(r: &mut Registry<'_>) {
+    r.register_helper("add", Box::new(add));
+    r.register_helper("sub", Box::new(sub));
+    r.register_helper("mul", Box::new(mul));
+    r.register_helper("div", Box::new(div));
+    r.register_helper("mod", Box::new(modulo));
+    r.register_helper("round", Box::new(round));
+    r.register_helper("floor", Box::new(floor));
+    r.register_helper("ceil", Box::new(ceil));
+    r.register_helper("abs", Box::new(abs));
+    r.register_helper("min", Box::new(min));
+    r.register_helper("max", Box::new(max));
+    r.register_helper("format-number", Box::new(format_number));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::RenderErrorReason;
+    use crate::registry::Registry;
+
+    fn render(template: &str) -> String {
+        Registry::new()
+            .render_template(
+                template,
+                &json!({
+                    "price": 9.5,
+                    "qty": 3,
+                    "big": i64::MAX,
+                    "list": [4, -2, 8.0],
+                }),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(
+            render("{{add 1 2}} {{add 1 0.5}} {{add price qty}}"),
+            "3 1.5 12.5"
+        );
+        assert_eq!(
+            render("{{sub 1 2}} {{mul price qty}} {{mul 2 3}}"),
+            "-1 28.5 6"
+        );
+        assert_eq!(
+            render("{{div 6 3}} {{div 7 2}} {{div 1.5 0.5}}"),
+            "2 3.5 3.0"
+        );
+        assert_eq!(render("{{mod 7 3}} {{mod -7 3}} {{mod 7.5 2}}"), "1 -1 1.5");
+        assert_eq!(render("{{add big 1}}"), "9.223372036854776e+18");
+        assert_eq!(render("{{abs -3}} {{abs -2.5}}"), "3 2.5");
+        assert_eq!(render("{{mul (add 1 (div 9 3)) (sub 10 qty)}}"), "28");
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let hbs = Registry::new();
+        for t in ["{{div 1 0}}", "{{div 1.5 0.0}}", "{{mod 1 0}}"].iter() {
+            let e = hbs.render_template(t, &()).unwrap_err();
+            assert!(matches!(e.reason(), RenderErrorReason::DivisionByZero(_)));
+        }
+        let e = hbs.render_template("{{add 1 \"2\"}}", &()).unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            RenderErrorReason::ParamTypeMismatchForName(_, _, _)
+        ));
+    }
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(render("{{round 2.5}} {{round -2.5}} {{round 7}}"), "3 -3 7");
+        assert_eq!(
+            render("{{round 3.14159 precision=2}} {{round 1234 precision=-2}}"),
+            "3.14 1200"
+        );
+        assert_eq!(
+            render("{{floor 2.7}} {{floor -2.1}} {{ceil 2.1}} {{ceil 2.123 precision=1}}"),
+            "2 -3 3 2.2"
+        );
+        assert_eq!(
+            Registry::new()
+                .render_template(
+                    "{{round big precision=300}} {{round 2.5 precision=-308}}",
+                    &json!({"big": 1.5e300})
+                )
+                .unwrap(),
+            "1.5e+300 0"
+        );
+
+        for t in [
+            "{{round 2.5 precision=309}}",
+            "{{floor 2.5 precision=-1000}}",
+            "{{ceil 1 precision=-9223372036854775808}}",
+        ]
+        .iter()
+        {
+            let e = Registry::new().render_template(t, &()).unwrap_err();
+            assert!(matches!(
+                e.reason(),
+                RenderErrorReason::HashTypeMismatchForName(_, _, _)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_min_max() {
+        assert_eq!(
+            render("{{min 3 1.5 2}} {{max 3 1.5 2}} {{max qty}}"),
+            "1.5 3 3"
+        );
+        assert_eq!(render("{{min list}} {{max list}}"), "-2 8.0");
+        assert!(Registry::new().render_template("{{max}}", &()).is_err());
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(
+            render("{{format-number 1234567.891 precision=2 separator=\",\"}}"),
+            "1,234,567.89"
+        );
+        assert_eq!(
+            render("{{format-number -1234567.891 precision=1 separator=\".\" decimal=\",\"}}"),
+            "-1.234.567,9"
+        );
+        assert_eq!(
+            render("{{{format-number 1234 separator=\"'\"}}} {{format-number 123}}"),
+            "1'234 123"
+        );
+        assert_eq!(
+            render("{{format-number qty precision=2}} {{format-number price}}"),
+            "3.00 9.5"
+        );
+        assert_eq!(
+            render("{{format-number 999.999 precision=2 separator=\",\"}}"),
+            "1,000.00"
+        );
+        let e = Registry::new()
+            .render_template("{{format-number 1 precision=1000000000000}}", &())
+            .unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            RenderErrorReason::ResultTooLarge(_, _)
+        ));
+    }
+}
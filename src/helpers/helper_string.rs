@@ -0,0 +1,204 @@
+//! String helpers, enabled with the `string_helpers` feature
+//!
+//! Lengths and positions are counted in chars, not bytes.
+
+use crate::error::{RenderError, RenderErrorReason};
+use crate::json::value::JsonRender;
+use crate::registry::Registry;
+
+/// Widths above this are rejected by `pad` instead of being allocated
+const MAX_PAD_WIDTH: u64 = 65_536;
+
+handlebars_helper!(upper: |s: str| s.to_uppercase());
+handlebars_helper!(lower: |s: str| s.to_lowercase());
+handlebars_helper!(trim: |s: str| s.trim());
+handlebars_helper!(capitalize: |s: str| {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+        None => String::new(),
+    }
+});
+handlebars_helper!(truncate: |s: str, length: u64, {suffix: str = ""}| {
+    let length = length as usize;
+    match s.char_indices().nth(length) {
+        Some((end, _)) => format!("{}{}", &s[..end], suffix),
+        None => s.to_owned(),
+    }
+});
+handlebars_helper!(replace: |s: str, from: str, to: str| s.replace(from, to));
+handlebars_helper!(split: |s: str, separator: str| {
+    if separator.is_empty() {
+        s.chars().map(String::from).collect::<Vec<String>>()
+    } else {
+        s.split(separator).map(str::to_owned).collect::<Vec<String>>()
+    }
+});
+handlebars_helper!(join: |list: array, separator: str| {
+    list.iter()
+        .map(|item| item.render())
+        .collect::<Vec<String>>()
+        .join(separator)
+});
+handlebars_helper!(pad: |s: str, width: u64, {fill: str = " ", side: str = "left"}| {
+    if width > MAX_PAD_WIDTH {
+        return Err(RenderError::from(RenderErrorReason::ResultTooLarge(
+            "pad".to_owned(),
+            MAX_PAD_WIDTH as usize,
+        )));
+    }
+    let mut fill_chars = fill.chars();
+    let fill = match (fill_chars.next(), fill_chars.next()) {
+        (Some(c), None) => c,
+        _ => {
+            return Err(RenderError::from(RenderErrorReason::HashTypeMismatchForName(
+                "pad".to_owned(),
+                "fill".to_owned(),
+                "char".to_owned(),
+            )))
+        }
+    };
+    let missing = (width as usize).saturating_sub(s.chars().count());
+    let (left, right) = match side {
+        "left" => (missing, 0),
+        "right" => (0, missing),
+        "both" => (missing / 2, missing - missing / 2),
+        _ => {
+            return Err(RenderError::from(RenderErrorReason::HashTypeMismatchForName(
+                "pad".to_owned(),
+                "side".to_owned(),
+                "\"left\", \"right\" or \"both\"".to_owned(),
+            )))
+        }
+    };
+    let mut padded = String::with_capacity(s.len() + missing * fill.len_utf8());
+    for _ in 0..left {
+        padded.push(fill);
+    }
+    padded.push_str(s);
+    for _ in 0..right {
+        padded.push(fill);
+    }
+    padded
+});
+handlebars_helper!(slugify: |s: str| {
+    let mut slug = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_owned()
+});
+handlebars_helper!(contains: |s: str, needle: str| s.contains(needle));
+
+pub(crate) fn register(r: &mut Registry<'_>) {
+    r.register_helper("upper", Box::new(upper));
+    r.register_helper("lower", Box::new(lower));
+    r.register_helper("trim", Box::new(trim));
+    r.register_helper("capitalize", Box::new(capitalize));
+    r.register_helper("truncate", Box::new(truncate));
+    r.register_helper("replace", Box::new(replace));
+    r.register_helper("split", Box::new(split));
+    r.register_helper("join", Box::new(join));
+    r.register_helper("pad", Box::new(pad));
+    r.register_helper("slugify", Box::new(slugify));
+    r.register_helper("contains", Box::new(contains));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::RenderErrorReason;
+    use crate::registry::Registry;
+
+    fn render(template: &str, data: &serde_json::Value) -> String {
+        Registry::new().render_template(template, data).unwrap()
+    }
+
+    #[test]
+    fn test_case() {
+        let data = json!({"s": "straße ǆ élan"});
+        assert_eq!(render("{{upper s}}", &data), "STRASSE Ǆ ÉLAN");
+        assert_eq!(render("{{lower \"ÉLAN Σ\"}}", &data), "élan σ");
+        assert_eq!(render("{{capitalize \"ǆemal\"}}", &data), "Ǆemal");
+        assert_eq!(render("{{capitalize \"ßa b\"}}", &data), "SSa b");
+        assert_eq!(render("{{capitalize \"\"}}", &data), "");
+        assert_eq!(
+            render("[{{trim t}}]", &json!({"t": "\u{3000} a b \n"})),
+            "[a b]"
+        );
+    }
+
+    #[test]
+    fn test_truncate_and_pad() {
+        let data = json!({"s": "añb😀cd"});
+        assert_eq!(render("{{truncate s 4}}", &data), "añb😀");
+        assert_eq!(render("{{truncate s 4 suffix=\"…\"}}", &data), "añb😀…");
+        assert_eq!(render("{{truncate s 6 suffix=\"…\"}}", &data), "añb😀cd");
+        assert_eq!(render("{{truncate s 0}}", &data), "");
+
+        assert_eq!(render("[{{pad s 8}}]", &data), "[  añb😀cd]");
+        assert_eq!(
+            render("[{{pad s 9 side=\"both\" fill=\"·\"}}]", &data),
+            "[·añb😀cd··]"
+        );
+        assert_eq!(
+            render("[{{pad s 7 side=\"right\" fill=\"0\"}}]", &data),
+            "[añb😀cd0]"
+        );
+        assert_eq!(render("[{{pad s 2}}]", &data), "[añb😀cd]");
+
+        let hbs = Registry::new();
+        assert!(hbs
+            .render_template("{{pad s 8 fill=\"ab\"}}", &data)
+            .is_err());
+        assert!(hbs
+            .render_template("{{pad s 8 side=\"up\"}}", &data)
+            .is_err());
+        let e = hbs
+            .render_template("{{pad s 1000000000000}}", &data)
+            .unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            RenderErrorReason::ResultTooLarge(_, _)
+        ));
+    }
+
+    #[test]
+    fn test_replace_split_join() {
+        let data = json!({"tags": ["a", 1, true, null], "csv": "x,y,,z"});
+        assert_eq!(render("{{replace csv \",\" \";\"}}", &data), "x;y;;z");
+        assert_eq!(render("{{join tags \", \"}}", &data), "a, 1, true, ");
+        assert_eq!(
+            render("{{#each (split csv \",\")}}[{{this}}]{{/each}}", &data),
+            "[x][y][][z]"
+        );
+        assert_eq!(
+            render("{{join (split \"añ😀\" \"\") \"-\"}}", &data),
+            "a-ñ-😀"
+        );
+    }
+
+    #[test]
+    fn test_slugify_and_contains() {
+        let data = json!({"title": "  Summer SALE: Crème Brûlée -- 50% off! "});
+        assert_eq!(
+            render("{{slugify title}}", &data),
+            "summer-sale-crème-brûlée-50-off"
+        );
+        assert_eq!(render("{{slugify \"--\"}}", &data), "");
+        assert_eq!(
+            render("{{#if (contains (lower title) \"sale\")}}yes{{/if}}", &data),
+            "yes"
+        );
+        assert_eq!(
+            render(
+                "{{#if (contains title \"sale\")}}yes{{else}}no{{/if}}",
+                &data
+            ),
+            "no"
+        );
+    }
+}
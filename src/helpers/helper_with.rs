@@ -1,9 +1,8 @@
-use super::block_util::create_block;
+use super::block_util::{create_block, is_param_truthy};
 use crate::block::BlockParams;
 use crate::context::Context;
-use crate::error::RenderError;
+use crate::error::{RenderError, RenderErrorReason};
 use crate::helpers::{HelperDef, HelperResult};
-use crate::json::value::JsonTruthy;
 use crate::output::Output;
 use crate::registry::Registry;
 use crate::render::{Helper, RenderContext, Renderable};
@@ -22,9 +21,9 @@ impl HelperDef for WithHelper {
     ) -> HelperResult {
         let param = h
             .param(0)
-            .ok_or_else(|| RenderError::new("Param not found for helper \"with\""))?;
+            .ok_or_else(|| RenderErrorReason::ParamNotFoundForIndex("with".to_owned(), 0))?;
 
-        if param.value().is_truthy(false) {
+        if is_param_truthy(param, ctx, rc, false)? {
             let mut block = create_block(param);
 
             if let Some(block_param) = h.block_param() {
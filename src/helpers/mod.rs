@@ -5,8 +5,13 @@ use crate::output::Output;
 use crate::registry::Registry;
 use crate::render::{do_escape, Helper, RenderContext};
 
+#[cfg(feature = "async")]
+pub use self::helper_async::{AsyncHelperDef, AsyncHelperFuture};
+#[cfg(feature = "datetime")]
+pub use self::helper_datetime::RelativeTimeHelper;
 pub use self::helper_each::EACH_HELPER;
 pub use self::helper_if::{IF_HELPER, UNLESS_HELPER};
+pub use self::helper_layout::{BLOCK_HELPER, EXTEND_HELPER, SUPER_HELPER};
 pub use self::helper_log::LOG_HELPER;
 pub use self::helper_lookup::LOOKUP_HELPER;
 pub use self::helper_raw::RAW_HELPER;
@@ -124,8 +129,13 @@ pub trait HelperDef {
                 if r.strict_mode() && result.is_missing() {
                     Err(RenderError::strict_error(None))
                 } else {
-                    // auto escape according to settings
-                    let output = do_escape(r, rc, result.render());
+                    // auto escape according to settings, unless the helper
+                    // returns a safe string
+                    let output = if result.is_safe() {
+                        result.render()
+                    } else {
+                        do_escape(r, rc, result.render())?
+                    };
                     out.write(output.as_ref())?;
                     Ok(())
                 }
@@ -166,12 +176,24 @@ impl<
 }
 
 mod block_util;
+#[cfg(feature = "async")]
+pub(crate) mod helper_async;
+#[cfg(feature = "collection_helpers")]
+pub(crate) mod helper_collection;
+#[cfg(feature = "datetime")]
+pub(crate) mod helper_datetime;
 mod helper_each;
 pub(crate) mod helper_extras;
 mod helper_if;
+pub(crate) mod helper_json;
+pub(crate) mod helper_layout;
 mod helper_log;
 mod helper_lookup;
+#[cfg(feature = "math_helpers")]
+pub(crate) mod helper_math;
 mod helper_raw;
+#[cfg(feature = "string_helpers")]
+pub(crate) mod helper_string;
 mod helper_with;
 #[cfg(feature = "script_helper")]
 pub(crate) mod scripting;
@@ -186,10 +208,12 @@ pub(crate) mod scripting;
 mod test {
     use std::collections::BTreeMap;
 
+    use serde_json::json;
+
     use crate::context::Context;
     use crate::error::RenderError;
     use crate::helpers::HelperDef;
-    use crate::json::value::JsonRender;
+    use crate::json::value::{JsonRender, SafeString, ScopedJson};
     use crate::output::Output;
     use crate::registry::Registry;
     use crate::render::{Helper, RenderContext, Renderable};
@@ -289,4 +313,49 @@ mod test {
 
         assert_eq!(r2.ok().unwrap(), "bar0".to_string());
     }
+
+    #[derive(Clone, Copy)]
+    struct BoldHelper;
+
+    impl HelperDef for BoldHelper {
+        fn call_inner<'reg: 'rc, 'rc>(
+            &self,
+            h: &Helper<'reg, 'rc>,
+            r: &'reg Registry<'reg>,
+            _: &'rc Context,
+            _: &mut RenderContext<'reg, 'rc>,
+        ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+            let text = h.param(0).unwrap().value().render();
+            let bold = format!("<b>{}</b>", r.get_escape_fn()(&text));
+            Ok(SafeString::new(bold).into())
+        }
+    }
+
+    #[test]
+    fn test_safe_string() {
+        let mut handlebars = Registry::new();
+        handlebars.register_helper("bold", Box::new(BoldHelper));
+        let data = json!({"name": "<i>x</i>"});
+
+        assert_eq!(
+            handlebars
+                .render_template("{{bold name}} {{name}}", &data)
+                .unwrap(),
+            "<b>&lt;i&gt;x&lt;/i&gt;</b> &lt;i&gt;x&lt;/i&gt;"
+        );
+        assert_eq!(
+            handlebars
+                .render_template("{{#if (bold name)}}{{bold \"&\"}}{{/if}}", &data)
+                .unwrap(),
+            "<b>&amp;</b>"
+        );
+
+        handlebars.set_contextual_escape(true);
+        assert_eq!(
+            handlebars
+                .render_template("<p title=\"{{name}}\">{{bold name}}</p>", &data)
+                .unwrap(),
+            "<p title=\"&lt;i&gt;x&lt;/i&gt;\"><b>&lt;i&gt;x&lt;/i&gt;</b></p>"
+        );
+    }
 }
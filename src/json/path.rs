@@ -3,7 +3,7 @@ use std::iter::Peekable;
 use pest::iterators::Pair;
 use pest::Parser;
 
-use crate::error::RenderError;
+use crate::error::{RenderError, RenderErrorReason};
 use crate::grammar::{HandlebarsParser, Rule};
 
 #[derive(PartialEq, Clone, Debug)]
@@ -38,10 +38,11 @@ impl Path {
                 let segs = parse_json_path_from_iter(&mut parsed.peekable(), raw.len());
                 Ok(Path::new(raw, segs))
             })
-            .map_err(|_| RenderError::new("Invalid JSON path"))?
+            .map_err(|_| RenderError::from(RenderErrorReason::InvalidJsonPath(raw.to_owned())))?
     }
 
-    pub(crate) fn raw(&self) -> &str {
+    /// The path as written in the template
+    pub fn raw(&self) -> &str {
         match self {
             Path::Relative((_, ref raw)) => raw,
             Path::Local((_, _, ref raw)) => raw,
@@ -8,6 +8,9 @@ pub(crate) static DEFAULT_VALUE: Json = Json::Null;
 /// * Constant: the JSON value hardcoded into template
 /// * Context:  the JSON value referenced in your provided data context
 /// * Derived:  the owned JSON value computed during rendering process
+/// * Safe:     the pre-escaped string returned by helper, see `SafeString`
+/// * Lazy:     the JSON value resolved from a lazy context or computed
+///   while rendering, see `ContextValue` and `ComputedValue`
 ///
 #[derive(Debug)]
 pub enum ScopedJson<'reg: 'rc, 'rc> {
@@ -16,6 +19,10 @@ pub enum ScopedJson<'reg: 'rc, 'rc> {
     // represents a json reference to context value, its full path
     Context(&'rc Json, Vec<String>),
     Missing,
+    Safe(Json),
+    // represents a value resolved from a lazy context or computed, its full
+    // path
+    Lazy(Json, Vec<String>),
 }
 
 impl<'reg: 'rc, 'rc> ScopedJson<'reg, 'rc> {
@@ -25,6 +32,8 @@ impl<'reg: 'rc, 'rc> ScopedJson<'reg, 'rc> {
             ScopedJson::Constant(j) => j,
             ScopedJson::Derived(ref j) => j,
             ScopedJson::Context(j, _) => j,
+            ScopedJson::Safe(ref j) => j,
+            ScopedJson::Lazy(ref j, _) => j,
             _ => &DEFAULT_VALUE,
         }
     }
@@ -37,6 +46,11 @@ impl<'reg: 'rc, 'rc> ScopedJson<'reg, 'rc> {
         matches!(self, ScopedJson::Missing)
     }
 
+    /// Test if the value is a `SafeString`, written without escaping
+    pub fn is_safe(&self) -> bool {
+        matches!(self, ScopedJson::Safe(_))
+    }
+
     pub fn into_derived(self) -> ScopedJson<'reg, 'rc> {
         let v = self.as_json();
         ScopedJson::Derived(v.clone())
@@ -44,7 +58,7 @@ impl<'reg: 'rc, 'rc> ScopedJson<'reg, 'rc> {
 
     pub fn context_path(&self) -> Option<&Vec<String>> {
         match self {
-            ScopedJson::Context(_, ref p) => Some(p),
+            ScopedJson::Context(_, ref p) | ScopedJson::Lazy(_, ref p) => Some(p),
             _ => None,
         }
     }
@@ -56,6 +70,63 @@ impl<'reg: 'rc, 'rc> From<Json> for ScopedJson<'reg, 'rc> {
     }
 }
 
+/// A string that is already escaped, like markup generated by a helper
+///
+/// When a helper returns it from `HelperDef::call_inner`, it is written as
+/// is, instead of going through the escape fn. Other values are still
+/// escaped, so escaping doesn't need to be disabled for the whole template.
+/// Content from user data must be escaped by the helper itself:
+///
+/// ```
+/// use handlebars::*;
+///
+/// struct LinkHelper;
+///
+/// impl HelperDef for LinkHelper {
+///     fn call_inner<'reg: 'rc, 'rc>(
+///         &self,
+///         h: &Helper<'reg, 'rc>,
+///         r: &'reg Handlebars<'reg>,
+///         _: &'rc Context,
+///         _: &mut RenderContext<'reg, 'rc>,
+///     ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+///         let url = h.param(0).map(|p| p.value().render()).unwrap_or_default();
+///         let escape = r.get_escape_fn();
+///         let link = format!("<a href=\"{}\">{}</a>", escape(&url), escape(&url));
+///         Ok(SafeString::new(link).into())
+///     }
+/// }
+///
+/// let mut handlebars = Handlebars::new();
+/// handlebars.register_helper("link", Box::new(LinkHelper));
+/// let rendered = handlebars
+///     .render_template("{{link url}} {{url}}", &serde_json::json!({"url": "/a?b&c"}))
+///     .unwrap();
+/// assert_eq!(rendered, "<a href=\"/a?b&amp;c\">/a?b&amp;c</a> /a?b&amp;c");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct SafeString(String);
+
+impl SafeString {
+    pub fn new<S: Into<String>>(s: S) -> SafeString {
+        SafeString(s.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl<'reg: 'rc, 'rc> From<SafeString> for ScopedJson<'reg, 'rc> {
+    fn from(s: SafeString) -> ScopedJson<'reg, 'rc> {
+        ScopedJson::Safe(Json::String(s.0))
+    }
+}
+
 /// Json wrapper that holds the Json value and reference path information
 ///
 #[derive(Debug)]
@@ -63,11 +63,57 @@
 //! The built-in helpers like `if` and `each` were written with these
 //! helper APIs and the APIs are fully available to developers.
 //!
+//! ### Async helpers
+//!
+//! With the `async` feature, helpers can also be defined as async functions
+//! with `AsyncHelperDef`, to fetch data while rendering. Templates using them
+//! are rendered with `render_async`, or written to an `AsyncOutput` like a
+//! tokio `AsyncWrite` with `render_to_async_write`. Templates are rendered
+//! again once async calls are resolved, so sync helpers may run more than once
+//! for a single render.
+//!
 //! ### Auto-reload in dev mode
 //!
 //! By turning on `dev_mode`, handlebars auto reloads any template and scripts that
 //! loaded from files or directory. This can be handy for template development.
 //!
+//! Dev mode recompiles templates on every render. To reload only templates
+//! that changed, share your registry behind an `Arc<RwLock<_>>` and watch
+//! your files with a `TemplateWatcher`.
+//!
+//! ### Embedding templates
+//!
+//! With the `dir_source` feature, `embed_templates_directory` can be called
+//! from `build.rs` to check a template directory at build time and embed it
+//! into your binary, for `register_embed_templates` at runtime.
+//!
+//! With the `collection_helpers` feature, helpers returning new arrays are
+//! registered with `Handlebars::register_collection_helpers()`, for use as
+//! subexpressions of `each`. Keys are dotted paths into the items:
+//!
+//! * `{{sort-by items "price" desc=true}}` sorts items by a key, nulls,
+//!   bools, numbers and strings in this order
+//! * `{{filter-by items "active" true}}` keeps items whose key equals a value
+//! * `{{group-by items "category"}}` returns `{"key": ..., "items": [...]}`
+//!   groups, in the order their keys first appear
+//! * `{{slice items 0 10}}` with negative indexes counting from the end,
+//!   `{{first items}}` and `{{last items}}`
+//! * `{{pluck items "name"}}`, `{{unique items}}` and `{{reverse items}}`
+//! * `{{keys obj}}` and `{{values obj}}` of objects or arrays
+//! * `{{range 1 10}}` for the integers from 1 to 9, at most a million
+//! * `{{includes items value}}` tests if an array or object contains a value
+//!
+//! With the `datetime` feature, date helpers are registered too. Dates are
+//! RFC 3339 strings or seconds since the unix epoch:
+//!
+//! * `{{format-date d format="%Y-%m-%d" tz="+09:00"}}` formats a date with a
+//!   strftime pattern, in `tz` which is `UTC`, `local` or an offset. Dates
+//!   are formatted in their own offset by default. Named zones like
+//!   `Europe/Paris` are rejected, as they need a time zone database, pass
+//!   the offset of the date in that zone instead
+//! * `{{relative-time d}}` renders `3 days ago` or `in 2 hours`, see
+//!   `RelativeTimeHelper` for using another clock
+//!
 //! ### Template inheritance
 //!
 //! Every time I look into a templating system, I will investigate its
@@ -108,10 +154,6 @@
 //! First of all, mustache blocks are not supported. I suggest you to use `#if` and `#each` for
 //! the same functionality.
 //!
-//! There are some other minor features missing:
-//!
-//! * Chained else [#12](https://github.com/sunng87/handlebars-rust/issues/12)
-//!
 //! Feel free to file an issue on [github](https://github.com/sunng87/handlebars-rust/issues) if
 //! you find missing features.
 //!
@@ -329,6 +371,7 @@
 //!
 //! * `{{{{raw}}}} ... {{{{/raw}}}}` escape handlebars expression within the block
 //! * `{{#if ...}} ... {{else}} ... {{/if}}` if-else block
+//!   Block helpers can be chained in their else branch, like `{{#if a}} ... {{else if b}} ... {{else}} ... {{/if}}`.
 //!    (See [the handlebarjs documentation](https://handlebarsjs.com/guide/builtin-helpers.html#if) on how to use this helper.)
 //! * `{{#unless ...}} ... {{else}} .. {{/unless}}` if-not-else block
 //!    (See [the handlebarjs documentation](https://handlebarsjs.com/guide/builtin-helpers.html#unless) on how to use this helper.)
@@ -340,7 +383,8 @@
 //!    (See [the handlebarjs documentation](https://handlebarsjs.com/guide/builtin-helpers.html#lookup) on how to use this helper.)
 //! * `{{> ...}}` include template by its name
 //! * `{{log ...}}` log value with rust logger, default level: INFO. Currently you cannot change the level.
-//! * Boolean helpers that can be used in `if` as subexpression, for example `{{#if (gt 2 1)}} ...`:
+//! * Boolean helpers that can be used in `if` as subexpression, for example `{{#if (gt 2 1)}} ...`,
+//!   `gt`, `gte`, `lt` and `lte` compare integers and floats:
 //!   * `eq`
 //!   * `ne`
 //!   * `gt`
@@ -351,12 +395,84 @@
 //!   * `or`
 //!   * `not`
 //! * `{{len ...}}` returns length of array/object/string
+//! * `{{#extend ...}} ... {{/extend}}`, `{{#block ...}} ... {{/block}}` and
+//!   `{{super}}` for layouts, registered with
+//!   `Handlebars::register_layout_helpers()`, see below
+//! * `{{json value pretty=true}}` writes a value as JSON. `<`, `>`, `&`,
+//!   U+2028 and U+2029 are written as `\uXXXX`, so `{{{json value}}}` is safe
+//!   inside `<script>` blocks. The result is HTML escaped otherwise, except in
+//!   scripts and event handlers with contextual escaping
+//! * `{{parse-json s}}` parses a JSON string, for use in `each`, `lookup` or
+//!   other helpers
+//! * `{{to-yaml value}}` and `{{to-toml value}}` write a value as YAML or
+//!   TOML, with the `yaml_helper` and `toml_helper` features. TOML has no
+//!   null, `null` table fields are skipped
+//!
+//! The JSON, YAML and TOML helpers are registered with
+//! `Handlebars::register_json_helpers()`.
+//!
+//! With the `string_helpers` feature, string helpers are registered too.
+//! Lengths and positions are counted in chars:
+//!
+//! * `{{upper s}}`, `{{lower s}}`, `{{capitalize s}}` and `{{trim s}}`
+//! * `{{truncate s 10 suffix="…"}}` keeps the first chars, and appends the
+//!   suffix when the string is truncated
+//! * `{{replace s "from" "to"}}` replaces all occurrences
+//! * `{{split s ","}}` returns an array, split in chars when the separator
+//!   is empty, and `{{join list ", "}}` renders the items of an array
+//! * `{{pad s 8 fill="0" side="left"}}` pads the string to a width, on the
+//!   `left`, `right` or `both` sides. Widths above 65536 are an error
+//! * `{{slugify s}}` lowercases letters and digits and joins them with `-`
+//! * `{{contains s "sub"}}` tests if a string contains another
+//!
+//! With the `math_helpers` feature, numeric helpers are registered too.
+//! Integers stay integers while the result fits in an `i64`:
+//!
+//! * `{{add x y}}`, `{{sub x y}}`, `{{mul x y}}`, `{{div x y}}` and
+//!   `{{mod x y}}`, dividing by zero is an error
+//! * `{{round x}}`, `{{floor x}}` and `{{ceil x}}`, with an optional
+//!   `precision=2` for decimals, from -308 to 308, and `{{abs x}}`
+//! * `{{min x y ...}}` and `{{max x y ...}}`, of the params or the items of
+//!   an array
+//! * `{{format-number x precision=2 separator="," decimal="."}}` formats a
+//!   number with a thousands separator and decimal mark, with at most 1000
+//!   decimals
 //!
 //! ### Template inheritance
 //!
 //! Handlebars.js' partial system is fully supported in this implementation.
 //! Check [example](https://github.com/sunng87/handlebars-rust/blob/master/examples/partials.rs#L49) for details.
 //!
+//! The partial name can be computed by a subexpression, like
+//! `{{> (lookup . 'kind') fallback="card"}}`, where the `fallback` partial
+//! is rendered when the computed one doesn't exist. Missing partials render
+//! nothing, unless `Handlebars::set_strict_partials()` is enabled.
+//!
+//! Layouts can also define named blocks with default content, and templates
+//! extending them override any of these blocks. `{{super}}` renders the
+//! content of the block being overridden, and layouts can extend other
+//! layouts. These helpers are registered with `register_layout_helpers()`:
+//!
+//! ```
+//! use handlebars::Handlebars;
+//!
+//! let mut handlebars = Handlebars::new();
+//! handlebars.register_layout_helpers();
+//! handlebars.register_template_string(
+//!     "base",
+//!     "<title>{{#block \"title\"}}Site{{/block}}</title>{{#block \"content\"}}{{/block}}",
+//! ).unwrap();
+//! handlebars.register_template_string(
+//!     "page",
+//!     "{{#extend \"base\"}}{{#block \"title\"}}Page - {{super}}{{/block}}\
+//!      {{#block \"content\"}}Hello{{/block}}{{/extend}}",
+//! ).unwrap();
+//! assert_eq!(
+//!     handlebars.render("page", &()).unwrap(),
+//!     "<title>Page - Site</title>Hello"
+//! );
+//! ```
+//!
 //!
 
 #![allow(dead_code, clippy::upper_case_acronyms)]
@@ -382,36 +498,58 @@ extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
 
+pub use self::analysis::{Dependencies, DependencyGraph, ValidationError};
 pub use self::block::{BlockContext, BlockParams};
-pub use self::context::Context;
+pub use self::context::{ComputedValue, Context, ContextValue};
 pub use self::decorators::DecoratorDef;
-pub use self::error::{RenderError, TemplateError};
+pub use self::diagnostic::Diagnostic;
+#[cfg(feature = "dir_source")]
+pub use self::embed::embed_templates_directory;
+pub use self::error::{RenderError, RenderErrorReason, TemplateError, TemplateErrorReason};
+#[cfg(feature = "datetime")]
+pub use self::helpers::RelativeTimeHelper;
+#[cfg(feature = "async")]
+pub use self::helpers::{AsyncHelperDef, AsyncHelperFuture};
 pub use self::helpers::{HelperDef, HelperResult};
 pub use self::json::path::Path;
-pub use self::json::value::{to_json, JsonRender, PathAndJson, ScopedJson};
+pub use self::json::value::{to_json, JsonRender, PathAndJson, SafeString, ScopedJson};
+pub use self::limits::{LimitError, RenderLimits};
+#[cfg(feature = "async")]
+pub use self::output::{AsyncOutput, AsyncOutputFuture, AsyncWriteOutput};
 pub use self::output::{Output, StringOutput};
 pub use self::registry::{html_escape, no_escape, EscapeFn, Registry as Handlebars};
 pub use self::render::{Decorator, Evaluable, Helper, RenderContext, Renderable};
+pub use self::sources::{DirectoryLoader, FileLoader, MapLoader, TemplateLoader};
 pub use self::template::Template;
+pub use self::watcher::{TemplateWatcher, WatchError, WatcherHandle};
 
 #[doc(hidden)]
 pub use self::serde_json::Value as JsonValue;
 
 #[macro_use]
 mod macros;
+mod analysis;
 mod block;
 mod context;
 mod decorators;
+mod diagnostic;
+#[cfg(feature = "dir_source")]
+mod embed;
 mod error;
+mod escape;
 mod grammar;
 mod helpers;
 mod json;
+mod limits;
 mod local_vars;
 mod output;
 mod partial;
+mod printer;
 mod registry;
 mod render;
 mod sources;
 mod support;
 pub mod template;
 mod util;
+pub mod visitor;
+mod watcher;
@@ -0,0 +1,261 @@
+use std::cell::Cell;
+use std::io::{Error as IOError, ErrorKind};
+use std::time::{Duration, Instant};
+
+use crate::error::RenderError;
+use crate::output::Output;
+
+/// Limits on the resources a single render may use
+///
+/// Each limit is disabled when `None`, which is the default. When a limit is
+/// exceeded, rendering stops with a `RenderError` whose `limit_exceeded()`
+/// tells which one. They are meant to be set when rendering templates from
+/// untrusted sources. Async renders count the resources of all their passes
+/// together, and their timeout includes the time spent awaiting async
+/// helpers:
+///
+/// ```
+/// use std::time::Duration;
+/// use handlebars::{Handlebars, RenderLimits};
+///
+/// let mut handlebars = Handlebars::new();
+/// handlebars.set_render_limits(RenderLimits {
+///     max_output_bytes: Some(1 << 20),
+///     max_iterations: Some(10_000),
+///     timeout: Some(Duration::from_secs(1)),
+///     ..Default::default()
+/// });
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RenderLimits {
+    /// maximum nesting of partials
+    pub max_partial_depth: Option<usize>,
+    /// maximum size of the output in bytes
+    pub max_output_bytes: Option<usize>,
+    /// maximum number of iterations of `each`, over all blocks
+    pub max_iterations: Option<usize>,
+    /// maximum number of helper calls, including subexpressions
+    pub max_helper_calls: Option<usize>,
+    /// maximum time a render may take
+    ///
+    /// The deadline is checked between operations, when entering a partial,
+    /// iterating, calling a helper or writing output. A helper call, a write
+    /// or an awaited async helper that is slow or blocked is not interrupted,
+    /// the render stops at the next check after it returns.
+    pub timeout: Option<Duration>,
+}
+
+quick_error! {
+/// The render limit that was exceeded
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum LimitError {
+        PartialDepth(limit: usize) {
+            display("Partials are nested deeper than {} levels", limit)
+        }
+        OutputSize(limit: usize) {
+            display("Output is larger than {} bytes", limit)
+        }
+        Iterations(limit: usize) {
+            display("More than {} iterations", limit)
+        }
+        HelperCalls(limit: usize) {
+            display("More than {} helper calls", limit)
+        }
+        Timeout(limit: Duration) {
+            display("Rendering takes longer than {:?}", limit)
+        }
+    }
+}
+
+/// Resources used by a render, checked against its limits
+#[derive(Debug)]
+pub(crate) struct LimitState {
+    limits: RenderLimits,
+    deadline: Option<Instant>,
+    partial_depth: Cell<usize>,
+    output_bytes: Cell<usize>,
+    iterations: Cell<usize>,
+    helper_calls: Cell<usize>,
+}
+
+fn count(counter: &Cell<usize>, n: usize, limit: Option<usize>) -> bool {
+    let total = counter.get().saturating_add(n);
+    counter.set(total);
+    limit.filter(|limit| total > *limit).is_none()
+}
+
+impl LimitState {
+    /// Start tracking a render, or `None` when no limit is set
+    pub(crate) fn new(limits: &RenderLimits) -> Option<LimitState> {
+        if *limits == RenderLimits::default() {
+            return None;
+        }
+        Some(LimitState {
+            limits: limits.clone(),
+            deadline: limits.timeout.map(|t| Instant::now() + t),
+            partial_depth: Cell::new(0),
+            output_bytes: Cell::new(0),
+            iterations: Cell::new(0),
+            helper_calls: Cell::new(0),
+        })
+    }
+
+    /// The resources used so far, for the next pass of an async render,
+    /// which starts outside of partials
+    #[cfg(feature = "async")]
+    pub(crate) fn after_pass(&self) -> LimitState {
+        LimitState {
+            limits: self.limits.clone(),
+            deadline: self.deadline,
+            partial_depth: Cell::new(0),
+            output_bytes: self.output_bytes.clone(),
+            iterations: self.iterations.clone(),
+            helper_calls: self.helper_calls.clone(),
+        }
+    }
+
+    pub(crate) fn check_deadline(&self) -> Result<(), LimitError> {
+        match (self.deadline, self.limits.timeout) {
+            (Some(deadline), Some(timeout)) if Instant::now() > deadline => {
+                Err(LimitError::Timeout(timeout))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn enter_partial(&self) -> Result<(), RenderError> {
+        self.check_deadline()?;
+        if count(&self.partial_depth, 1, self.limits.max_partial_depth) {
+            Ok(())
+        } else {
+            self.leave_partial();
+            Err(LimitError::PartialDepth(self.limits.max_partial_depth.unwrap_or_default()).into())
+        }
+    }
+
+    pub(crate) fn leave_partial(&self) {
+        self.partial_depth.set(self.partial_depth.get() - 1);
+    }
+
+    pub(crate) fn count_iteration(&self) -> Result<(), RenderError> {
+        self.check_deadline()?;
+        if count(&self.iterations, 1, self.limits.max_iterations) {
+            Ok(())
+        } else {
+            Err(LimitError::Iterations(self.limits.max_iterations.unwrap_or_default()).into())
+        }
+    }
+
+    pub(crate) fn count_helper_call(&self) -> Result<(), RenderError> {
+        self.check_deadline()?;
+        if count(&self.helper_calls, 1, self.limits.max_helper_calls) {
+            Ok(())
+        } else {
+            Err(LimitError::HelperCalls(self.limits.max_helper_calls.unwrap_or_default()).into())
+        }
+    }
+
+    fn count_output(&self, bytes: usize) -> Result<(), LimitError> {
+        self.check_deadline()?;
+        if count(&self.output_bytes, bytes, self.limits.max_output_bytes) {
+            Ok(())
+        } else {
+            Err(LimitError::OutputSize(
+                self.limits.max_output_bytes.unwrap_or_default(),
+            ))
+        }
+    }
+}
+
+/// Output counting the bytes written against the limits
+pub(crate) struct LimitedOutput<'a> {
+    pub(crate) output: &'a mut dyn Output,
+    pub(crate) state: &'a LimitState,
+}
+
+impl<'a> Output for LimitedOutput<'a> {
+    // `io::Error::other` is not available on our minimum rust version
+    #[allow(clippy::io_other_error)]
+    fn write(&mut self, seg: &str) -> Result<(), IOError> {
+        self.state
+            .count_output(seg.len())
+            .map_err(|e| IOError::new(ErrorKind::Other, e))?;
+        self.output.write(seg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::{LimitError, RenderLimits};
+    use crate::registry::Registry;
+
+    fn limited(limits: RenderLimits) -> Registry<'static> {
+        let mut hbs = Registry::new();
+        hbs.set_render_limits(limits);
+        hbs.register_template_string("node", "[{{#each children}}{{> node}}{{/each}}]")
+            .unwrap();
+        hbs
+    }
+
+    #[test]
+    fn test_render_limits() {
+        let tree = json!({"children": [{"children": [{"children": [{"children": []}]}]}, {"children": []}]});
+        let items = json!({"items": [1, 2, 3, 4, 5]});
+
+        let hbs = limited(RenderLimits::default());
+        assert_eq!(hbs.render("node", &tree).unwrap(), "[[[[]]][]]");
+
+        let hbs = limited(RenderLimits {
+            max_partial_depth: Some(2),
+            ..Default::default()
+        });
+        let e = hbs.render("node", &tree).unwrap_err();
+        assert_eq!(e.limit_exceeded(), Some(&LimitError::PartialDepth(2)));
+        assert_eq!(hbs.render_template("{{> node}}", &json!({})).unwrap(), "[]");
+
+        let hbs = limited(RenderLimits {
+            max_output_bytes: Some(4),
+            ..Default::default()
+        });
+        assert_eq!(hbs.render_template("{{this}}", &"abcd").unwrap(), "abcd");
+        let e = hbs.render_template("ab{{this}}", &"abc").unwrap_err();
+        assert_eq!(e.limit_exceeded(), Some(&LimitError::OutputSize(4)));
+
+        let hbs = limited(RenderLimits {
+            max_iterations: Some(6),
+            ..Default::default()
+        });
+        let tpl = "{{#each items}}{{this}}{{/each}}{{#each items}}{{this}}{{/each}}";
+        let e = hbs.render_template(tpl, &items).unwrap_err();
+        assert_eq!(e.limit_exceeded(), Some(&LimitError::Iterations(6)));
+
+        let hbs = limited(RenderLimits {
+            max_helper_calls: Some(2),
+            ..Default::default()
+        });
+        assert!(hbs
+            .render_template("{{len items}}{{#if 1}}{{/if}}", &items)
+            .is_ok());
+        let e = hbs
+            .render_template("{{#if (len items)}}{{len items}}{{/if}}", &items)
+            .unwrap_err();
+        assert_eq!(e.limit_exceeded(), Some(&LimitError::HelperCalls(2)));
+        assert!(e.to_string().contains("More than 2 helper calls"));
+
+        let hbs = limited(RenderLimits {
+            timeout: Some(Duration::from_nanos(1)),
+            ..Default::default()
+        });
+        let e = hbs
+            .render_template("{{#each items}}{{/each}}", &items)
+            .unwrap_err();
+        assert_eq!(
+            e.limit_exceeded(),
+            Some(&LimitError::Timeout(Duration::from_nanos(1)))
+        );
+    }
+}
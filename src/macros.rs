@@ -47,7 +47,6 @@ macro_rules! handlebars_helper {
         pub struct $struct_name;
 
         impl $crate::HelperDef for $struct_name {
-            #[allow(unused_assignments)]
             fn call_inner<'reg: 'rc, 'rc>(
                 &self,
                 h: &$crate::Helper<'reg, 'rc>,
@@ -55,7 +54,7 @@ macro_rules! handlebars_helper {
                 _: &'rc $crate::Context,
                 _: &mut $crate::RenderContext<'reg, 'rc>,
             ) -> Result<$crate::ScopedJson<'reg, 'rc>, $crate::RenderError> {
-                let mut param_idx = 0;
+                let param_idx = 0;
 
                 $(
                     let $name = h.param(param_idx)
@@ -66,21 +65,21 @@ macro_rules! handlebars_helper {
                                 Some(x.value())
                             }
                         })
-                        .ok_or_else(|| $crate::RenderError::new(&format!(
-                            "`{}` helper: Couldn't read parameter {}",
-                            stringify!($struct_name), stringify!($name),
+                        .ok_or_else(|| $crate::RenderError::from($crate::RenderErrorReason::ParamNotFoundForName(
+                            stringify!($struct_name).to_owned(), stringify!($name).to_owned(),
                         )))
                         .and_then(|x|
                                   handlebars_helper!(@as_json_value x, $tpe$(<$($gen),+>)?)
-                                  .ok_or_else(|| $crate::RenderError::new(&format!(
-                                      "`{}` helper: Couldn't convert parameter {} to type `{}`. \
-                                       It's {:?} as JSON. Got these params: {:?}",
-                                      stringify!($struct_name), stringify!($name), stringify!($tpe$(<$($gen),+>)?),
-                                      x, h.params(),
+                                  .ok_or_else(|| $crate::RenderError::from($crate::RenderErrorReason::ParamValueMismatchForName(
+                                      stringify!($struct_name).to_owned(), stringify!($name).to_owned(),
+                                      stringify!($tpe$(<$($gen),+>)?).to_owned(),
+                                      x.clone(), h.params().iter().map(|p| p.value().clone()).collect(),
                                   )))
                         )?;
-                    param_idx += 1;
+                    let param_idx = param_idx + 1;
                 )*
+                // `r` and `param_idx` are unused by helpers without params
+                let _ = (r, param_idx);
 
                     $(
                         $(
@@ -88,11 +87,10 @@ macro_rules! handlebars_helper {
                                 .map(|x| x.value())
                                 .map(|x|
                                      handlebars_helper!(@as_json_value x, $hash_tpe)
-                                     .ok_or_else(|| $crate::RenderError::new(&format!(
-                                         "`{}` helper: Couldn't convert hash {} to type `{}`. \
-                                          It's {:?} as JSON. Got these hash: {:?}",
-                                         stringify!($struct_name), stringify!($hash_name), stringify!($hash_tpe),
-                                         x, h.hash(),
+                                     .ok_or_else(|| $crate::RenderError::from($crate::RenderErrorReason::HashValueMismatchForName(
+                                         stringify!($struct_name).to_owned(), stringify!($hash_name).to_owned(),
+                                         stringify!($hash_tpe).to_owned(),
+                                         x.clone(), h.hash().iter().map(|(k, v)| ((*k).to_owned(), v.value().clone())).collect(),
                                      )))
                                 )
                                 .unwrap_or_else(|| Ok($dft_val))?;
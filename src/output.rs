@@ -1,6 +1,13 @@
+#[cfg(feature = "async")]
+use std::future::Future;
 use std::io::{Error as IOError, Write};
+#[cfg(feature = "async")]
+use std::pin::Pin;
 use std::string::FromUtf8Error;
 
+#[cfg(feature = "async")]
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
 /// The Output API.
 ///
 /// Handlebars uses this trait to define rendered output.
@@ -24,6 +31,42 @@ impl<W: Write> WriteOutput<W> {
     }
 }
 
+/// The future of an `AsyncOutput` write
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub type AsyncOutputFuture<'a> = Pin<Box<dyn Future<Output = Result<(), IOError>> + Send + 'a>>;
+
+/// The async Output API.
+///
+/// Async renders write their output to it without blocking, see
+/// `Registry::render_with_context_to_async_output`.
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub trait AsyncOutput {
+    fn write<'a>(&'a mut self, seg: &'a str) -> AsyncOutputFuture<'a>;
+}
+
+/// An `AsyncOutput` writing to a tokio `AsyncWrite`
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub struct AsyncWriteOutput<W: AsyncWrite + Unpin + Send> {
+    write: W,
+}
+
+#[cfg(feature = "async")]
+impl<W: AsyncWrite + Unpin + Send> AsyncOutput for AsyncWriteOutput<W> {
+    fn write<'a>(&'a mut self, seg: &'a str) -> AsyncOutputFuture<'a> {
+        Box::pin(self.write.write_all(seg.as_bytes()))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<W: AsyncWrite + Unpin + Send> AsyncWriteOutput<W> {
+    pub fn new(write: W) -> AsyncWriteOutput<W> {
+        AsyncWriteOutput { write }
+    }
+}
+
 pub struct StringOutput {
     buf: Vec<u8>,
 }
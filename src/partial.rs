@@ -1,38 +1,47 @@
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use serde_json::value::Value as Json;
 
 use crate::block::BlockContext;
 use crate::context::{merge_json, Context};
-use crate::error::RenderError;
-use crate::json::path::Path;
+use crate::error::{RenderError, RenderErrorReason};
 use crate::output::Output;
 use crate::registry::Registry;
 use crate::render::{Decorator, Evaluable, RenderContext, Renderable};
 use crate::template::Template;
 
 pub(crate) const PARTIAL_BLOCK: &str = "@partial-block";
+/// hash key of the partial rendered when the named one is missing
+pub(crate) const PARTIAL_FALLBACK: &str = "fallback";
 
 fn find_partial<'reg: 'rc, 'rc: 'a, 'a>(
     rc: &'a RenderContext<'reg, 'rc>,
     r: &'reg Registry<'reg>,
-    d: &Decorator<'reg, 'rc>,
     name: &str,
 ) -> Result<Option<Cow<'a, Template>>, RenderError> {
-    if let Some(partial) = rc.get_partial(name) {
-        return Ok(Some(Cow::Borrowed(partial)));
+    if rc.is_current_template(name) {
+        return Err(RenderErrorReason::CannotIncludeSelf.into());
     }
 
-    if let Some(tpl) = r.get_or_load_template_optional(name) {
-        return tpl.map(Option::Some);
+    if let Some(partial) = rc.get_partial(name) {
+        return Ok(Some(Cow::Borrowed(partial)));
     }
 
-    if let Some(tpl) = d.template() {
-        return Ok(Some(Cow::Borrowed(tpl)));
+    let scope = rc.get_scope_template_name().map(String::as_str);
+    match r.get_or_load_partial(name, scope) {
+        Some((found, _)) if rc.is_current_template(&found) => {
+            Err(RenderErrorReason::CannotIncludeSelf.into())
+        }
+        Some((_, tpl)) => tpl.map(Option::Some),
+        None => Ok(None),
     }
+}
 
-    Ok(None)
+fn overlay(hash: &HashMap<&str, &Json>) -> BTreeMap<String, Json> {
+    hash.iter()
+        .map(|(k, v)| ((*k).to_owned(), (*v).clone()))
+        .collect()
 }
 
 pub fn expand_partial<'reg: 'rc, 'rc>(
@@ -48,12 +57,21 @@ pub fn expand_partial<'reg: 'rc, 'rc>(
     }
 
     let tname = d.name();
-    if rc.is_current_template(tname) {
-        return Err(RenderError::new("Cannot include self in >"));
-    }
+    let fallback = d
+        .hash_get(PARTIAL_FALLBACK)
+        .and_then(|p| p.value().as_str());
 
+    // the named partial, then the fallback one, then the partial block
     // if tname == PARTIAL_BLOCK
-    let partial = find_partial(rc, r, d, tname)?;
+    let mut partial = find_partial(rc, r, tname)?;
+    if let (None, Some(fallback)) = (&partial, fallback) {
+        partial = find_partial(rc, r, fallback)?;
+    }
+    let partial = partial.or_else(|| d.template().map(Cow::Borrowed));
+
+    if partial.is_none() && r.strict_partials() {
+        return Err(RenderErrorReason::PartialNotFound(tname.to_owned()).into());
+    }
 
     if let Some(t) = partial {
         // clone to avoid lifetime issue
@@ -67,26 +85,35 @@ pub fn expand_partial<'reg: 'rc, 'rc>(
 
         let mut block_created = false;
 
+        let hash = d
+            .hash()
+            .iter()
+            .filter(|(k, _)| **k != PARTIAL_FALLBACK)
+            .map(|(k, v)| (*k, v.value()))
+            .collect::<HashMap<&str, &Json>>();
+
         if let Some(base_path) = d.param(0).and_then(|p| p.context_path()) {
             // path given, update base_path
             let mut block = BlockContext::new();
             *block.base_path_mut() = base_path.to_vec();
+            block.set_overlay(overlay(&hash));
             block_created = true;
             local_rc.push_block(block);
-        } else if !d.hash().is_empty() {
+        } else if !hash.is_empty() {
             let mut block = BlockContext::new();
-            // hash given, update base_value
-            let hash_ctx = d
-                .hash()
-                .iter()
-                .map(|(k, v)| (*k, v.value()))
-                .collect::<HashMap<&str, &Json>>();
-
-            let merged_context = merge_json(
-                local_rc.evaluate2(ctx, &Path::current())?.as_json(),
-                &hash_ctx,
-            );
-            block.set_base_value(merged_context);
+            match local_rc.block() {
+                // hash given on a constant base, update base_value
+                Some(current) if current.base_value().is_some() => {
+                    block.set_base_value(merge_json(current.base_value().unwrap(), &hash));
+                }
+                // hash given, overlay it on the current base_path, so the
+                // other values are still resolved from the context
+                Some(current) => {
+                    *block.base_path_mut() = current.base_path().clone();
+                    block.set_overlay(overlay(&hash));
+                }
+                None => block.set_overlay(overlay(&hash)),
+            }
             block_created = true;
             local_rc.push_block(block);
         }
@@ -96,8 +123,17 @@ pub fn expand_partial<'reg: 'rc, 'rc>(
             local_rc.push_partial_block(pb);
         }
 
+        // entered last, so that the early returns above don't leak depth
+        if let Some(limits) = local_rc.limits() {
+            limits.enter_partial()?;
+        }
+
         let result = t.render(r, ctx, &mut local_rc, out);
 
+        if let Some(limits) = local_rc.limits() {
+            limits.leave_partial();
+        }
+
         // cleanup
         if block_created {
             local_rc.pop_block();
@@ -119,8 +155,10 @@ pub fn expand_partial<'reg: 'rc, 'rc>(
 
 #[cfg(test)]
 mod test {
-    use crate::context::Context;
-    use crate::error::RenderError;
+    use serde_json::value::Value as Json;
+
+    use crate::context::{Context, ContextValue};
+    use crate::error::{RenderError, RenderErrorReason};
     use crate::output::Output;
     use crate::registry::Registry;
     use crate::render::{Helper, RenderContext};
@@ -187,6 +225,53 @@ mod test {
         assert_eq!(handlebars.render("t9", &1).ok().unwrap(), "2".to_string());
     }
 
+    /// A lazy root which can't be serialized as a whole
+    struct Root;
+
+    impl ContextValue for Root {
+        fn get(&self, key: &str) -> Option<Box<dyn ContextValue + '_>> {
+            match key {
+                "user" => Some(Box::new(json!({"name": "Ann", "t": 0}))),
+                _ => None,
+            }
+        }
+
+        fn to_json(&self) -> Json {
+            panic!("the whole root is serialized");
+        }
+    }
+
+    #[test]
+    fn test_partial_hash_lazy_context() {
+        let mut hbs = Registry::new();
+        hbs.register_partial("card", "[{{user.greeting}}|{{t}}]")
+            .unwrap();
+        hbs.register_partial("user", "[{{greeting}}|{{t}}|{{../t}}|{{name}}]")
+            .unwrap();
+
+        let mut ctx = Context::lazy(Root);
+        ctx.register_computed("user.greeting", |user: &Json| {
+            Ok(json!(format!("Hi {}", user["name"].as_str().unwrap())))
+        })
+        .unwrap();
+
+        assert_eq!(
+            hbs.render_template_with_context("{{> card t=1}}", &ctx)
+                .unwrap(),
+            "[Hi Ann|1]"
+        );
+        assert_eq!(
+            hbs.render_template_with_context("{{> user user t=2}}", &ctx)
+                .unwrap(),
+            "[Hi Ann|2||Ann]"
+        );
+        assert_eq!(
+            hbs.render_template_with_context("{{#with user}}{{> card t=3}}{{/with}}", &ctx)
+                .unwrap(),
+            "[|3]"
+        );
+    }
+
     #[test]
     fn test_include_partial_block() {
         let t0 = "hello {{> @partial-block}}";
@@ -285,6 +370,56 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_dynamic_partial() {
+        let mut hbs = Registry::new();
+        hbs.register_template_string("article", "[{{title}}]")
+            .unwrap();
+        hbs.register_template_string("video card", "<{{title}} {{fallback}}>")
+            .unwrap();
+        hbs.register_template_string("card", "({{title}}{{size}})")
+            .unwrap();
+        let data = json!({"feed": [
+            {"kind": "article", "title": "a"},
+            {"kind": "video card", "title": "v"},
+            {"kind": "poll", "title": "p"},
+        ]});
+
+        assert_eq!(
+            hbs.render_template("{{#each feed}}{{> (lookup . 'kind')}}{{/each}}", &data)
+                .unwrap(),
+            "[a]<v >"
+        );
+        assert_eq!(
+            hbs.render_template(
+                "{{#each feed}}{{> (lookup this \"kind\") fallback=\"card\" size=1}}{{/each}}",
+                &data
+            )
+            .unwrap(),
+            "[a]<v >(p1)"
+        );
+        assert_eq!(
+            hbs.render_template(
+                "{{> 'video card' title=1}}{{> [video card] title=2}}",
+                &data
+            )
+            .unwrap(),
+            "<1 ><2 >"
+        );
+
+        hbs.set_strict_partials(true);
+        let e = hbs
+            .render_template("{{#each feed}}{{> (lookup . 'kind')}}{{/each}}", &data)
+            .unwrap_err();
+        assert!(matches!(e.reason(), RenderErrorReason::PartialNotFound(name) if name == "poll"));
+        assert!(hbs
+            .render_template("{{#> missing}}block{{/missing}}", &data)
+            .is_ok());
+        assert!(hbs
+            .render_template("{{> missing fallback=\"gone\"}}", &data)
+            .is_err());
+    }
+
     #[test]
     fn test_nested_partial_scope() {
         let t = "{{#*inline \"pp\"}}{{a}} {{b}}{{/inline}}{{#each c}}{{> pp a=2}}{{/each}}";
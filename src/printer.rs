@@ -0,0 +1,546 @@
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+use serde_json::value::Value as Json;
+
+use crate::grammar;
+use crate::template::{
+    BlockParam, DecoratorTemplate, HelperTemplate, Parameter, Template, TemplateElement,
+    WhitespaceControl,
+};
+
+/// A piece of printed template, either text or a tag
+enum Piece<'a> {
+    Text {
+        text: &'a str,
+        raw: bool,
+    },
+    Tag {
+        source: String,
+        // tags that are removed with their line when standing alone
+        standalone: bool,
+        omit_pre_ws: bool,
+        omit_pro_ws: bool,
+    },
+}
+
+fn tag(source: String, standalone: bool, ws: (bool, bool)) -> Piece<'static> {
+    Piece::Tag {
+        source,
+        standalone,
+        omit_pre_ws: ws.0,
+        omit_pro_ws: ws.1,
+    }
+}
+
+fn tilde(omit: bool) -> &'static str {
+    if omit {
+        "~"
+    } else {
+        ""
+    }
+}
+
+fn write_literal(out: &mut String, json: &Json) {
+    match json {
+        // the grammar only accepts an uppercase exponent
+        Json::Number(n) => out.push_str(&n.to_string().replace('e', "E")),
+        Json::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_literal(out, item);
+            }
+            out.push(']');
+        }
+        Json::Object(entries) => {
+            out.push('{');
+            for (i, (k, v)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Json::String(k.clone()).to_string());
+                out.push(':');
+                write_literal(out, v);
+            }
+            out.push('}');
+        }
+        _ => out.push_str(&json.to_string()),
+    }
+}
+
+fn write_param(out: &mut String, param: &Parameter) {
+    match param {
+        Parameter::Name(name) => out.push_str(name),
+        Parameter::Path(path) => out.push_str(path.raw()),
+        Parameter::Literal(json) => write_literal(out, json),
+        Parameter::Subexpression(subexpr) => {
+            out.push('(');
+            if let TemplateElement::Expression(ref ht) = *subexpr.as_element() {
+                write_expression(out, &ht.name, &ht.params, &ht.hash);
+            }
+            out.push(')');
+        }
+    }
+}
+
+fn write_expression(
+    out: &mut String,
+    name: &Parameter,
+    params: &[Parameter],
+    hash: &HashMap<String, Parameter>,
+) {
+    write_param(out, name);
+    write_expression_args(out, params, hash);
+}
+
+fn write_expression_args(
+    out: &mut String,
+    params: &[Parameter],
+    hash: &HashMap<String, Parameter>,
+) {
+    for p in params {
+        out.push(' ');
+        write_param(out, p);
+    }
+    let mut keys: Vec<&String> = hash.keys().collect();
+    keys.sort();
+    for k in keys {
+        let _ = write!(out, " {}=", k);
+        write_param(out, &hash[k]);
+    }
+}
+
+fn write_block_param(out: &mut String, block_param: &Option<BlockParam>) {
+    match block_param {
+        Some(BlockParam::Single(p)) => {
+            out.push_str(" as |");
+            write_param(out, p);
+            out.push('|');
+        }
+        Some(BlockParam::Pair((p1, p2))) => {
+            out.push_str(" as |");
+            write_param(out, p1);
+            out.push(' ');
+            write_param(out, p2);
+            out.push('|');
+        }
+        None => {}
+    }
+}
+
+fn helper_tag(h: &HelperTemplate, open: &str, prefix: &str, close: &str) -> String {
+    let ws = h.whitespace.open;
+    let mut s = format!("{}{}{}", open, tilde(ws.0), prefix);
+    write_expression(&mut s, &h.name, &h.params, &h.hash);
+    write_block_param(&mut s, &h.block_param);
+    s.push_str(tilde(ws.1));
+    s.push_str(close);
+    s
+}
+
+/// Write a partial name, quoting it when it's not a plain identifier
+fn write_partial_name(out: &mut String, name: &Parameter) {
+    match name {
+        Parameter::Name(name) if !name.chars().all(is_partial_symbol_char) => {
+            if name.contains(']') {
+                let _ = write!(out, "'{}'", name.replace('\'', "\\'"));
+            } else {
+                let _ = write!(out, "[{}]", name);
+            }
+        }
+        _ => write_param(out, name),
+    }
+}
+
+fn is_partial_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || ['-', '_', '/', '.', ':'].contains(&c) || !c.is_ascii()
+}
+
+fn decorator_tag(d: &DecoratorTemplate, prefix: &str) -> String {
+    let ws = d.whitespace.open;
+    let mut s = format!("{{{{{}{}", tilde(ws.0), prefix);
+    if prefix.contains('>') {
+        write_partial_name(&mut s, &d.name);
+        write_expression_args(&mut s, &d.params, &d.hash);
+    } else {
+        write_expression(&mut s, &d.name, &d.params, &d.hash);
+    }
+    s.push_str(tilde(ws.1));
+    s.push_str("}}");
+    s
+}
+
+fn close_tag(name: &Parameter, ws: (bool, bool), raw: bool, partial: bool) -> String {
+    let (open, close) = if raw { ("{{{{", "}}}}") } else { ("{{", "}}") };
+    let mut s = format!("{}{}/", open, tilde(ws.0));
+    if partial {
+        write_partial_name(&mut s, name);
+    } else {
+        write_param(&mut s, name);
+    }
+    s.push_str(tilde(ws.1));
+    s.push_str(close);
+    s
+}
+
+fn comment_tag(text: &str) -> String {
+    if text.contains("}}") || text.ends_with('}') || text.starts_with("--") {
+        format!("{{{{!--{}--}}}}", text)
+    } else {
+        format!("{{{{!{}}}}}", text)
+    }
+}
+
+fn push_template<'a>(t: &'a Template, raw: bool, pieces: &mut Vec<Piece<'a>>) {
+    for e in &t.elements {
+        push_element(e, raw, pieces);
+    }
+}
+
+fn push_decorator_block<'a>(d: &'a DecoratorTemplate, prefix: &str, pieces: &mut Vec<Piece<'a>>) {
+    let WhitespaceControl { open, close, .. } = d.whitespace;
+    pieces.push(tag(decorator_tag(d, prefix), true, open));
+    if let Some(ref t) = d.template {
+        push_template(t, false, pieces);
+    }
+    pieces.push(tag(
+        close_tag(&d.name, close, false, prefix.contains('>')),
+        true,
+        close,
+    ));
+}
+
+/// The block an inverse section is made of, if it can be written as
+/// `{{else if ...}}`
+///
+/// The parser reads a chained section as the only block of the inverse,
+/// sharing the `~` markers of the `else` and of the closing tag, like
+/// `{{else}}{{#if ...}}...{{/if}}` which renders the same.
+fn chained_block(
+    t: &Template,
+    inverse: (bool, bool),
+    close: (bool, bool),
+) -> Option<&HelperTemplate> {
+    match t.elements.as_slice() {
+        [TemplateElement::HelperBlock(h)]
+            if !h.raw && h.whitespace.open == inverse && h.whitespace.close == close =>
+        {
+            Some(h)
+        }
+        _ => None,
+    }
+}
+
+fn push_element<'a>(e: &'a TemplateElement, raw: bool, pieces: &mut Vec<Piece<'a>>) {
+    match e {
+        TemplateElement::RawString(text) => pieces.push(Piece::Text { text, raw }),
+        TemplateElement::Comment(text) => pieces.push(tag(comment_tag(text), true, (false, false))),
+        TemplateElement::Expression(h) => {
+            pieces.push(tag(helper_tag(h, "{{", "", "}}"), false, h.whitespace.open))
+        }
+        TemplateElement::HtmlExpression(h) => pieces.push(tag(
+            helper_tag(h, "{{{", "", "}}}"),
+            false,
+            h.whitespace.open,
+        )),
+        TemplateElement::HelperBlock(h) => {
+            let WhitespaceControl {
+                open,
+                inverse,
+                close,
+            } = h.whitespace;
+            if h.raw {
+                pieces.push(tag(helper_tag(h, "{{{{", "", "}}}}"), true, open));
+            } else {
+                pieces.push(tag(helper_tag(h, "{{", "#", "}}"), true, open));
+            }
+            if let Some(ref t) = h.template {
+                push_template(t, h.raw, pieces);
+            }
+            let mut branch: &HelperTemplate = h;
+            let mut inverse = inverse;
+            while let Some(ref t) = branch.inverse {
+                match chained_block(t, inverse, close) {
+                    Some(next) => {
+                        pieces.push(tag(
+                            helper_tag(next, "{{", "else ", "}}"),
+                            true,
+                            next.whitespace.open,
+                        ));
+                        if let Some(ref t) = next.template {
+                            push_template(t, false, pieces);
+                        }
+                        branch = next;
+                        inverse = next.whitespace.inverse;
+                    }
+                    None => {
+                        let s = format!("{{{{{}else{}}}}}", tilde(inverse.0), tilde(inverse.1));
+                        pieces.push(tag(s, true, inverse));
+                        push_template(t, false, pieces);
+                        break;
+                    }
+                }
+            }
+            pieces.push(tag(close_tag(&h.name, close, h.raw, false), true, close));
+        }
+        TemplateElement::DecoratorExpression(d) => {
+            pieces.push(tag(decorator_tag(d, "*"), true, d.whitespace.open))
+        }
+        TemplateElement::PartialExpression(d) => {
+            pieces.push(tag(decorator_tag(d, "> "), true, d.whitespace.open))
+        }
+        TemplateElement::DecoratorBlock(d) => push_decorator_block(d, "#*", pieces),
+        TemplateElement::PartialBlock(d) => push_decorator_block(d, "#> ", pieces),
+    }
+}
+
+/// Escape text so the parser reads it back unchanged
+///
+/// Tag openings are escaped with a backslash, and a sequence of backslashes
+/// before a tag gets one more, as the parser drops one of them.
+fn escape_text(out: &mut String, text: &str, raw: bool, before_tag: bool) {
+    let mut rest = text;
+    loop {
+        let unescaped = rest.trim_start_matches('\\');
+        let backslashes = &rest[..rest.len() - unescaped.len()];
+        out.push_str(backslashes);
+        if backslashes.is_empty() {
+            // `\{{{{` is escaped at once, it must be when followed by a tag
+            // as `\{{` would take the braces of the tag, and it's the only
+            // escape in raw blocks
+            let tag_open = if raw || (before_tag && unescaped == "{{{{") {
+                "{{{{"
+            } else {
+                "{{"
+            };
+            let tag_open = Some(tag_open).filter(|t| unescaped.starts_with(t));
+            if let Some(tag_open) = tag_open {
+                out.push('\\');
+                out.push_str(tag_open);
+                rest = &unescaped[tag_open.len()..];
+                continue;
+            }
+        } else if (unescaped.starts_with("{{") && (raw || !unescaped.starts_with("{{{")))
+            || (unescaped.is_empty() && before_tag)
+        {
+            out.push('\\');
+        }
+
+        let mut chars = unescaped.chars();
+        match chars.next() {
+            Some(c) => {
+                out.push(c);
+                rest = chars.as_str();
+            }
+            None => break,
+        }
+    }
+}
+
+/// Whether the piece is a text stripped empty by the parser
+///
+/// Raw block text is kept even when empty, so it isn't considered.
+fn is_stripped_text(piece: Option<&Piece<'_>>) -> bool {
+    matches!(piece, Some(Piece::Text { text, raw: false }) if text.is_empty())
+}
+
+fn omits_pre_ws(piece: Option<&Piece<'_>>) -> bool {
+    matches!(
+        piece,
+        Some(Piece::Tag {
+            omit_pre_ws: true,
+            ..
+        })
+    )
+}
+
+/// Whether the tag at `i` has to be printed alone on its line
+///
+/// The parser strips the line break after such a standalone tag, along with
+/// whitespaces before it. It's required when the text before or after the
+/// tag was stripped this way.
+fn strips_line(pieces: &[Piece<'_>], i: usize) -> bool {
+    match (&pieces[i], pieces.get(i + 1)) {
+        (
+            Piece::Tag {
+                standalone: true, ..
+            },
+            Some(Piece::Text { text, .. }),
+        ) => {
+            grammar::starts_with_empty_line(text)
+                || (is_stripped_text(pieces.get(i + 1)) && !omits_pre_ws(pieces.get(i + 2)))
+                || (i > 0 && is_stripped_text(pieces.get(i - 1)))
+        }
+        _ => false,
+    }
+}
+
+/// Print the template as handlebars source
+///
+/// The parser strips whitespace around standalone tags and `~`, what's left
+/// in the template is printed as is. Where printing that would be stripped
+/// again, a newline or space is added back for the parser to remove.
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pieces = Vec::new();
+        push_template(self, false, &mut pieces);
+
+        let mut out = String::new();
+        let mut line_stripped = false;
+        for (i, piece) in pieces.iter().enumerate() {
+            let next = pieces.get(i + 1);
+            match piece {
+                Piece::Tag { source, .. } => {
+                    line_stripped = strips_line(&pieces, i);
+                    if line_stripped && !grammar::ends_with_empty_line(&out) {
+                        // whitespaces after a `~` are dropped up to the next tag
+                        if let Some(Piece::Tag {
+                            omit_pro_ws: true, ..
+                        }) = i.checked_sub(1).map(|p| &pieces[p])
+                        {
+                            out.push('\n');
+                        } else {
+                            line_stripped = false;
+                        }
+                    }
+                    out.push_str(source);
+                }
+                Piece::Text { text, raw } => {
+                    if line_stripped {
+                        out.push('\n');
+                    } else if text.is_empty()
+                        && (omits_pre_ws(next)
+                            || (grammar::ends_with_empty_line(&out) && strips_line(&pieces, i + 1)))
+                    {
+                        out.push(' ');
+                    }
+                    escape_text(&mut out, text, *raw, next.is_some());
+                    // keep a brace apart from the next tag, `~` removes the space
+                    if omits_pre_ws(next) && out.ends_with('{') {
+                        out.push(' ');
+                    }
+                }
+            }
+        }
+        f.write_str(&out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::template::{Template, TemplateMapping};
+    use crate::visitor::{self, VisitorMut};
+
+    struct StripMapping;
+
+    impl VisitorMut for StripMapping {
+        fn visit_template_mut(&mut self, t: &mut Template) {
+            t.mapping.clear();
+            visitor::walk_template_mut(self, t);
+        }
+    }
+
+    fn without_mapping(mut t: Template) -> Template {
+        StripMapping.visit_template_mut(&mut t);
+        t
+    }
+
+    fn assert_round_trip(source: &str) {
+        let t = Template::compile(source).unwrap();
+        let printed = t.to_string();
+        let reparsed = Template::compile(&printed)
+            .unwrap_or_else(|e| panic!("{:?} printed as invalid {:?}: {}", source, printed, e));
+        assert_eq!(
+            without_mapping(t),
+            without_mapping(reparsed),
+            "{:?} printed as {:?}",
+            source,
+            printed
+        );
+    }
+
+    #[test]
+    fn test_print_template() {
+        let t = Template::compile(
+            "{{#each people as |p i| ~}}\n  {{{p.name}}}, {{! note }}{{> card size=(add i 1) }}\n{{else}}none{{/each}}",
+        )
+        .unwrap();
+        assert_eq!(
+            t.to_string(),
+            "{{#each people as |p i|~}}{{{p.name}}}, {{! note }}{{> card size=(add i 1)}}\n{{else}}none{{/each}}"
+        );
+        assert_eq!(t.mapping[0], TemplateMapping(1, 1));
+    }
+
+    #[test]
+    fn test_print_chained_else() {
+        let t = Template::compile("{{#if a}}1{{else}}{{#unless b}}2{{/unless}}{{/if}}").unwrap();
+        assert_eq!(t.to_string(), "{{#if a}}1{{else unless b}}2{{/if}}");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let sources = [
+            "hello {{name}}!",
+            "hello~     {{~world~}} \n  !{{~#if true}}else{{/if~}}",
+            "{{#if true}}1  {{~ else ~}} 2 {{~/if}}",
+            "{{#if a}}\n  yes\n{{else}}\n  no\n{{/if}}\n",
+            "<ul>\n  {{#each items}}\n\n  <li>{{this}}</li>\n  {{/each}}\n</ul>",
+            "{{#each a}}\n{{/each}}",
+            " {{~x}}",
+            "a\n  {{! comment }}  \nb",
+            "{{!-- {{a}} --}}{{!--x}}{{!a}}}",
+            "{{!---- ----}}",
+            "\\{{escaped}} \\{{{{raw}}}} \\\\{{x}} a\\\\",
+            "x\\{{{{y}}}}",
+            "{{{{raw}}}} {{x}} \\{{{{y}}}} \\{{z}} {{{{/raw}}}}",
+            "{{{{raw~}}}}\n  a\\\n{{{{~/raw}}}}",
+            "{{#> layout title=\"a \\\"b\\\"\\n\" }}\n  {{#*inline \"body\"}}x{{/inline}}\n{{/layout}}",
+            "{{*decorator 1 2.5 -3 1.5E-7 true null}}",
+            "{{helper [1,2.5,{\"a\":[null]}] {\"k\":\"v\"} 'single'}}",
+            "{{> (partial_name) ctx}}{{> partials/nav.hbs}}{{> [with space]}}",
+            "{{> (lookup . 'kind') fallback=\"card\"}}{{#> 'a]b'}}{{/'a]b'}}{{> ui::button}}",
+            "{{#each this as |v|}}{{@index}}{{../a}}{{./b}}{{this.[c d]}}{{@root.x}}{{/each}}",
+            "{{#if (and (not a) b c=(eq d \"e\"))}}x{{^}}y{{/if}}",
+            "{{#if a}}A{{else if b}}B{{~else unless c~}}C{{else}}D{{/if}}",
+            "{{#each a}}\n  x\n{{else with b as |y|}}\n  {{y}}\n{{/each}}\n",
+            "{{#if a}}{{else}}{{#each b}}{{else}}x{{/each}}{{/if}}",
+            "{{#if a}}{{~else}}{{#if b}}x{{/if~}}{{/if}}",
+            "{{#if a}}{{else}}{{{{raw}}}}x{{{{/raw}}}}{{/if}}",
+            "{{&amp}} {{{~triple~}}}",
+            "trailing {{x~}}  \n ",
+            "{{#if a}}\r\n  b\r\n{{/if}}\r\n",
+            "{{#if a}}  \n\n{{/if}}",
+            "a\n{{> p}}\n\n{{> q}}\n",
+            "  {{#if true}}\n  foo\n  {{/if}}\n  baz",
+            " {{! c }}  {{~x}}",
+            "{{~> p~}}\n{{*d}}\r\n{{x}}",
+            " {{{{raw}}}}{{{{/raw}}}}}",
+            "\\{\\{{ \\{{{{{{raw}}}} {{{y}}}{\t{{~x}}",
+        ];
+        for s in sources.iter() {
+            assert_round_trip(s);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_templates() {
+        let sources = [
+            include_str!("../examples/render/template.hbs"),
+            include_str!("../examples/partials/template2.hbs"),
+            include_str!("../examples/partials/base0.hbs"),
+            include_str!("../examples/decorator/template.hbs"),
+            include_str!("../examples/error/template.hbs"),
+            include_str!("../examples/partials/base1.hbs"),
+            include_str!("../examples/render_file/template.hbs"),
+            include_str!("../examples/dev_mode/template.hbs"),
+            include_str!("../examples/script/template.hbs"),
+        ];
+        for s in sources.iter() {
+            assert_round_trip(s);
+        }
+    }
+}
@@ -1,35 +1,46 @@
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Debug, Formatter};
-use std::io::{Error as IoError, Write};
+use std::io::Write;
+use std::iter;
 use std::path::Path;
-use std::sync::Arc;
+use std::rc::Rc;
+use std::sync::{Arc, PoisonError, RwLock};
 
 use serde::Serialize;
 
+use crate::analysis::{self, DependencyGraph, ValidationError};
 use crate::context::Context;
 use crate::decorators::{self, DecoratorDef};
+use crate::diagnostic::{self, Diagnostic};
 #[cfg(feature = "script_helper")]
 use crate::error::ScriptError;
-use crate::error::{RenderError, TemplateError};
+use crate::error::{RenderError, RenderErrorReason, TemplateError};
 use crate::helpers::{self, HelperDef};
+use crate::limits::{LimitState, LimitedOutput, RenderLimits};
 use crate::output::{Output, StringOutput, WriteOutput};
 use crate::render::{RenderContext, Renderable};
-use crate::sources::{FileSource, Source};
+use crate::sources::{self, FileLoader, NamespaceLoader, TemplateLoader};
 use crate::support::str::{self, StringWriter};
 use crate::template::Template;
 
-#[cfg(feature = "dir_source")]
-use std::path;
-#[cfg(feature = "dir_source")]
-use walkdir::{DirEntry, WalkDir};
-
 #[cfg(feature = "script_helper")]
 use rhai::Engine;
 
 #[cfg(feature = "script_helper")]
 use crate::helpers::scripting::ScriptHelper;
 
+#[cfg(feature = "async")]
+use crate::helpers::helper_async::{self, AsyncCalls, AsyncHelper};
+#[cfg(feature = "async")]
+use crate::helpers::AsyncHelperDef;
+#[cfg(feature = "async")]
+use crate::output::{AsyncOutput, AsyncWriteOutput};
+#[cfg(feature = "async")]
+use std::cell::RefCell;
+#[cfg(feature = "async")]
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
 /// This type represents an *escape fn*, that is a function whose purpose it is
 /// to escape potentially problematic characters in a string.
 ///
@@ -49,6 +60,9 @@ pub fn no_escape(data: &str) -> String {
     data.to_owned()
 }
 
+/// Separator of an explicit namespace in partial names, like `ui::button`
+const NAMESPACE_SEPARATOR: &str = "::";
+
 /// The single entry point of your Handlebars templates
 ///
 /// It maintains compiled templates and registered helpers.
@@ -57,19 +71,28 @@ pub struct Registry<'reg> {
     templates: HashMap<String, Template>,
 
     helpers: HashMap<String, Arc<dyn HelperDef + Send + Sync + 'reg>>,
+    /// builtin block helpers reading lazy values with `ContextValue`, until
+    /// they are replaced
+    lazy_block_helpers: HashSet<String>,
     decorators: HashMap<String, Arc<dyn DecoratorDef + Send + Sync + 'reg>>,
 
     escape_fn: EscapeFn,
     strict_mode: bool,
+    strict_partials: bool,
     dev_mode: bool,
+    render_limits: RenderLimits,
+    contextual_escape: bool,
+    partial_search_path: Vec<String>,
+    /// namespaces mounted with `register_namespace`
+    namespaces: HashSet<String>,
     #[cfg(feature = "script_helper")]
     pub(crate) engine: Arc<Engine>,
 
-    template_sources:
-        HashMap<String, Arc<dyn Source<Item = String, Error = IoError> + Send + Sync + 'reg>>,
+    template_sources: HashMap<String, Arc<dyn TemplateLoader + Send + Sync + 'reg>>,
+    template_loaders: Vec<Arc<dyn TemplateLoader + Send + Sync + 'reg>>,
+    loaded_templates: LoadedTemplates,
     #[cfg(feature = "script_helper")]
-    script_sources:
-        HashMap<String, Arc<dyn Source<Item = String, Error = IoError> + Send + Sync + 'reg>>,
+    script_sources: HashMap<String, Arc<dyn TemplateLoader + Send + Sync + 'reg>>,
 }
 
 impl<'reg> Debug for Registry<'reg> {
@@ -79,7 +102,11 @@ impl<'reg> Debug for Registry<'reg> {
             .field("helpers", &self.helpers.keys())
             .field("decorators", &self.decorators.keys())
             .field("strict_mode", &self.strict_mode)
+            .field("strict_partials", &self.strict_partials)
             .field("dev_mode", &self.dev_mode)
+            .field("render_limits", &self.render_limits)
+            .field("contextual_escape", &self.contextual_escape)
+            .field("partial_search_path", &self.partial_search_path)
             .finish()
     }
 }
@@ -90,19 +117,49 @@ impl<'reg> Default for Registry<'reg> {
     }
 }
 
-#[cfg(feature = "dir_source")]
-fn filter_file(entry: &DirEntry, suffix: &str) -> bool {
-    let path = entry.path();
-
-    // ignore hidden files, emacs buffers and files with wrong suffix
-    !path.is_file()
-        || path
-            .file_name()
-            .map(|s| {
-                let ds = s.to_string_lossy();
-                ds.starts_with('.') || ds.starts_with('#') || !ds.ends_with(suffix)
-            })
-            .unwrap_or(true)
+/// Templates compiled from the fallback loaders, `None` when no loader has
+/// the template
+#[derive(Default)]
+struct LoadedTemplates(RwLock<HashMap<String, Option<Arc<Template>>>>);
+
+impl LoadedTemplates {
+    fn get(&self, name: &str) -> Option<Option<Arc<Template>>> {
+        let templates = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        templates.get(name).cloned()
+    }
+
+    fn insert(&self, name: &str, template: Option<Arc<Template>>) {
+        let mut templates = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        templates.insert(name.to_owned(), template);
+    }
+
+    fn clear(&mut self) {
+        self.0
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+impl Clone for LoadedTemplates {
+    fn clone(&self) -> Self {
+        let templates = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        LoadedTemplates(RwLock::new(templates.clone()))
+    }
+}
+
+fn load_template<'reg>(
+    loader: &(dyn TemplateLoader + Send + Sync + 'reg),
+    name: &str,
+) -> Result<Option<Cow<'reg, Template>>, RenderError> {
+    let tpl_str = match loader.load(name) {
+        Ok(Some(s)) => s,
+        Ok(None) => return Ok(None),
+        Err(e) => return Err(TemplateError::from((e, name.to_owned())).into()),
+    };
+    Template::compile_with_name(tpl_str, name.to_owned())
+        .map(|t| Some(Cow::Owned(t)))
+        .map_err(RenderError::from)
 }
 
 #[cfg(feature = "script_helper")]
@@ -115,11 +172,19 @@ impl<'reg> Registry<'reg> {
         let r = Registry {
             templates: HashMap::new(),
             template_sources: HashMap::new(),
+            template_loaders: Vec::new(),
+            loaded_templates: LoadedTemplates::default(),
             helpers: HashMap::new(),
+            lazy_block_helpers: HashSet::new(),
             decorators: HashMap::new(),
             escape_fn: Arc::new(html_escape),
             strict_mode: false,
+            strict_partials: false,
             dev_mode: false,
+            render_limits: RenderLimits::default(),
+            contextual_escape: false,
+            partial_search_path: Vec::new(),
+            namespaces: HashSet::new(),
             #[cfg(feature = "script_helper")]
             engine: Arc::new(rhai_engine()),
             #[cfg(feature = "script_helper")]
@@ -134,6 +199,9 @@ impl<'reg> Registry<'reg> {
         self.register_helper("unless", Box::new(helpers::UNLESS_HELPER));
         self.register_helper("each", Box::new(helpers::EACH_HELPER));
         self.register_helper("with", Box::new(helpers::WITH_HELPER));
+        for name in ["if", "unless", "each", "with"].iter() {
+            self.lazy_block_helpers.insert((*name).to_owned());
+        }
         self.register_helper("lookup", Box::new(helpers::LOOKUP_HELPER));
         self.register_helper("raw", Box::new(helpers::RAW_HELPER));
         self.register_helper("log", Box::new(helpers::LOG_HELPER));
@@ -148,6 +216,12 @@ impl<'reg> Registry<'reg> {
         self.register_helper("or", Box::new(helpers::helper_extras::or));
         self.register_helper("not", Box::new(helpers::helper_extras::not));
         self.register_helper("len", Box::new(helpers::helper_extras::len));
+        #[cfg(feature = "string_helpers")]
+        helpers::helper_string::register(&mut self);
+        #[cfg(feature = "math_helpers")]
+        helpers::helper_math::register(&mut self);
+        #[cfg(feature = "datetime")]
+        helpers::helper_datetime::register(&mut self);
 
         self.register_decorator("inline", Box::new(decorators::INLINE_DECORATOR));
         self
@@ -175,6 +249,21 @@ impl<'reg> Registry<'reg> {
         self.strict_mode
     }
 
+    /// Enable or disable strict partials
+    ///
+    /// By default, a partial that can't be found, and has neither a
+    /// `fallback` partial nor a partial block, renders nothing. With strict
+    /// partials, a `RenderError` with `RenderErrorReason::PartialNotFound` is
+    /// raised instead.
+    pub fn set_strict_partials(&mut self, enabled: bool) {
+        self.strict_partials = enabled;
+    }
+
+    /// Return strict partials state, default is false
+    pub fn strict_partials(&self) -> bool {
+        self.strict_partials
+    }
+
     /// Return dev mode state, default is false
     ///
     /// With dev mode turned on, handlebars enables a set of development
@@ -197,6 +286,20 @@ impl<'reg> Registry<'reg> {
         if !enabled {
             self.template_sources.clear();
         }
+        self.loaded_templates.clear();
+    }
+
+    /// Set the limits on the resources used by each render
+    ///
+    /// No limit is set by default. Consider setting them when rendering
+    /// templates or data from untrusted sources.
+    pub fn set_render_limits(&mut self, limits: RenderLimits) {
+        self.render_limits = limits;
+    }
+
+    /// Return the limits on the resources used by each render
+    pub fn render_limits(&self) -> &RenderLimits {
+        &self.render_limits
     }
 
     /// Register a `Template`
@@ -250,20 +353,58 @@ impl<'reg> Registry<'reg> {
     where
         P: AsRef<Path>,
     {
-        let source = FileSource::new(tpl_path.as_ref().into());
+        self.register_template_source(name, Box::new(FileLoader::new(tpl_path.as_ref())))
+    }
+
+    /// Register a template loaded from a `TemplateLoader`
+    ///
+    /// The loader is asked for template `name` immediately. Returns
+    /// `TemplateError` if the loader fails, doesn't have the template, or the
+    /// template has syntax error.
+    ///
+    /// If dev mode is enabled, the registry will keep loading the template
+    /// from this loader everytime it's visited.
+    pub fn register_template_source(
+        &mut self,
+        name: &str,
+        source: Box<dyn TemplateLoader + Send + Sync + 'reg>,
+    ) -> Result<(), TemplateError> {
         let template_string = source
-            .load()
+            .load(name)
+            .and_then(|s| s.ok_or_else(|| sources::not_found(name)))
             .map_err(|err| TemplateError::from((err, name.to_owned())))?;
 
         self.register_template_string(name, template_string)?;
         if self.dev_mode {
-            self.template_sources
-                .insert(name.to_owned(), Arc::new(source));
+            self.template_sources.insert(name.to_owned(), source.into());
         }
 
         Ok(())
     }
 
+    /// Register a fallback `TemplateLoader`
+    ///
+    /// When a template is not registered, the registry asks its loaders for
+    /// it in the order they were registered, and uses the first template
+    /// found. Templates are compiled once and cached, along with the names
+    /// no loader has, until the loaders change. In dev mode, they are loaded
+    /// again on each visit.
+    ///
+    /// `has_template` and `get_templates` only report registered templates.
+    pub fn register_template_loader(
+        &mut self,
+        loader: Box<dyn TemplateLoader + Send + Sync + 'reg>,
+    ) {
+        self.template_loaders.push(loader.into());
+        self.loaded_templates.clear();
+    }
+
+    /// Remove all fallback `TemplateLoader`s
+    pub fn clear_template_loaders(&mut self) {
+        self.template_loaders.clear();
+        self.loaded_templates.clear();
+    }
+
     /// Register templates from a directory
     ///
     /// * `tpl_extension`: the template file extension
@@ -289,37 +430,115 @@ impl<'reg> Registry<'reg> {
     where
         P: AsRef<Path>,
     {
-        let dir_path = dir_path.as_ref();
+        // entries that can't be read, like a missing directory, are skipped
+        let files = sources::walk_template_files(tpl_extension, dir_path.as_ref());
+        for (tpl_name, tpl_path) in files.filter_map(Result::ok) {
+            self.register_template_file(&tpl_name, &tpl_path)?;
+        }
 
-        let prefix_len = if dir_path
-            .to_string_lossy()
-            .ends_with(|c| c == '\\' || c == '/')
-        // `/` will work on windows too so we still need to check
-        {
-            dir_path.to_string_lossy().len()
-        } else {
-            dir_path.to_string_lossy().len() + 1
-        };
+        Ok(())
+    }
 
-        let walker = WalkDir::new(dir_path);
-        let dir_iter = walker
-            .min_depth(1)
-            .into_iter()
-            .filter(|e| e.is_ok() && !filter_file(e.as_ref().unwrap(), tpl_extension));
+    /// Register templates embedded with `embed_templates_directory`
+    ///
+    /// `templates` is a list of template names and sources, like the one
+    /// generated by `embed_templates_directory` at build time. Returns
+    /// `TemplateError` if any of them has syntax error.
+    pub fn register_embed_templates(
+        &mut self,
+        templates: &[(&str, &str)],
+    ) -> Result<(), TemplateError> {
+        for (name, tpl_str) in templates {
+            self.register_template_string(name, tpl_str)?;
+        }
 
-        for entry in dir_iter {
-            let entry = entry?;
+        Ok(())
+    }
 
-            let tpl_path = entry.path();
-            let tpl_file_path = entry.path().to_string_lossy();
+    /// Register the templates of another registry under a namespace
+    ///
+    /// Template `name` of `templates` is registered as `<namespace>/name`,
+    /// along with its loaders, so a component library can be mounted in any
+    /// registry without its names colliding with others. Helpers and
+    /// decorators are not copied.
+    ///
+    /// Partials included by a template of the namespace are searched in it
+    /// first, see `set_partial_search_path`. `{{> ui::button}}`
+    /// includes `ui/button` from any template.
+    ///
+    /// ```
+    /// use handlebars::Handlebars;
+    ///
+    /// let mut ui = Handlebars::new();
+    /// ui.register_template_string("button", "<button>{{label}}</button>").unwrap();
+    /// ui.register_template_string("card", "<div>{{> button}}</div>").unwrap();
+    ///
+    /// let mut hbs = Handlebars::new();
+    /// hbs.register_template_string("button", "app button").unwrap();
+    /// hbs.register_template_string("page", "{{> ui::card}} {{> button}}").unwrap();
+    /// hbs.register_namespace("ui", &ui);
+    ///
+    /// assert_eq!(
+    ///     hbs.render("page", &serde_json::json!({"label": "ok"})).unwrap(),
+    ///     "<div><button>ok</button></div> app button"
+    /// );
+    /// ```
+    pub fn register_namespace(&mut self, namespace: &str, templates: &Registry<'reg>) {
+        let prefix = format!("{}/", namespace);
+        for (name, template) in &templates.templates {
+            let name = format!("{}{}", prefix, name);
+            let mut template = template.clone();
+            template.name = Some(name.clone());
+            self.templates.insert(name, template);
+        }
 
-            let tpl_name = &tpl_file_path[prefix_len..tpl_file_path.len() - tpl_extension.len()];
-            // replace platform path separator with our internal one
-            let tpl_canonical_name = tpl_name.replace(path::MAIN_SEPARATOR, "/");
-            self.register_template_file(&tpl_canonical_name, &tpl_path)?;
+        let namespaced = |loader: &Arc<dyn TemplateLoader + Send + Sync + 'reg>| {
+            Arc::new(NamespaceLoader {
+                prefix: prefix.clone(),
+                loader: loader.clone(),
+            }) as Arc<dyn TemplateLoader + Send + Sync + 'reg>
+        };
+        if self.dev_mode {
+            for (name, source) in &templates.template_sources {
+                self.template_sources
+                    .insert(format!("{}{}", prefix, name), namespaced(source));
+            }
+        }
+        for loader in &templates.template_loaders {
+            self.template_loaders.push(namespaced(loader));
         }
+        self.loaded_templates.clear();
 
-        Ok(())
+        self.namespaces.insert(namespace.to_owned());
+        for nested in &templates.namespaces {
+            self.namespaces.insert(format!("{}{}", prefix, nested));
+        }
+    }
+
+    /// Set the namespaces searched for partials
+    ///
+    /// A partial `name` is searched in the namespaces mounted with
+    /// `register_namespace` the template including it belongs to first, the
+    /// innermost first, then as `<namespace>/name` in each of these
+    /// namespaces in order, and at last as `name`. With `["ui"]`,
+    /// `{{> button}}` includes `ui/button` when it's registered.
+    ///
+    /// Other templates with a `/` in their name, like the ones of
+    /// `register_templates_directory`, don't make a namespace.
+    ///
+    /// Partials with an explicit namespace like `{{> ui::button}}` are not
+    /// searched.
+    pub fn set_partial_search_path<I, S>(&mut self, namespaces: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.partial_search_path = namespaces.into_iter().map(Into::into).collect();
+    }
+
+    /// Return the namespaces searched for partials, empty by default
+    pub fn partial_search_path(&self) -> &[String] {
+        &self.partial_search_path
     }
 
     /// Remove a template from the registry
@@ -330,9 +549,61 @@ impl<'reg> Registry<'reg> {
 
     /// Register a helper
     pub fn register_helper(&mut self, name: &str, def: Box<dyn HelperDef + Send + Sync + 'reg>) {
+        self.lazy_block_helpers.remove(name);
         self.helpers.insert(name.to_string(), def.into());
     }
 
+    /// Whether the helper `name` is a builtin block helper, which reads lazy
+    /// values without turning them into JSON
+    pub(crate) fn reads_lazy_values(&self, name: &str) -> bool {
+        self.lazy_block_helpers.contains(name)
+    }
+
+    /// Register the `extend`, `block` and `super` helpers for layouts
+    ///
+    /// They are not registered by default, as their names would shadow data
+    /// fields like `{{block}}` in existing templates.
+    pub fn register_layout_helpers(&mut self) {
+        self.register_helper("extend", Box::new(helpers::EXTEND_HELPER));
+        self.register_helper("block", Box::new(helpers::BLOCK_HELPER));
+        self.register_helper("super", Box::new(helpers::SUPER_HELPER));
+    }
+
+    /// Register the `json` and `parse-json` helpers, and `to-yaml` and
+    /// `to-toml` with the `yaml_helper` and `toml_helper` features
+    ///
+    /// They are not registered by default, as their names would shadow data
+    /// fields like `{{json}}` in existing templates.
+    pub fn register_json_helpers(&mut self) {
+        helpers::helper_json::register(self);
+    }
+
+    /// Register the collection helpers like `sort-by`, `first` and `keys`
+    ///
+    /// They are not registered by default, as names like `first` or `keys`
+    /// would shadow data fields in existing templates, even in crates that
+    /// didn't turn the feature on themselves.
+    #[cfg(feature = "collection_helpers")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "collection_helpers")))]
+    pub fn register_collection_helpers(&mut self) {
+        helpers::helper_collection::register(self);
+    }
+
+    /// Register an async helper
+    ///
+    /// Templates using async helpers have to be rendered with
+    /// `render_async` and its friends.
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn register_async_helper(
+        &mut self,
+        name: &str,
+        def: Box<dyn AsyncHelperDef + Send + Sync + 'reg>,
+    ) {
+        let helper = AsyncHelper { def: def.into() };
+        self.helpers.insert(name.to_string(), Arc::new(helper));
+    }
+
     /// Register a [rhai](https://docs.rs/rhai/) script as handlebars helper
     ///
     /// Currently only simple helpers are supported. You can do computation or
@@ -377,8 +648,8 @@ impl<'reg> Registry<'reg> {
     where
         P: AsRef<Path>,
     {
-        let source = FileSource::new(script_path.as_ref().into());
-        let script = source.load()?;
+        let source = FileLoader::new(script_path.as_ref());
+        let script = source.load(name)?.ok_or_else(|| sources::not_found(name))?;
 
         self.script_sources
             .insert(name.to_owned(), Arc::new(source));
@@ -428,6 +699,42 @@ impl<'reg> Registry<'reg> {
         &*self.escape_fn
     }
 
+    /// Enable or disable context-aware escaping for HTML templates
+    ///
+    /// When enabled, the HTML context of each expression is tracked from the
+    /// template text around it, and values are escaped for it instead of
+    /// using the *escape fn*: entities in text and quoted attributes,
+    /// percent encoding in URLs, js strings in scripts and event handlers,
+    /// and CSS escapes in styles. Values in scripts are always written as
+    /// js strings.
+    ///
+    /// Rendering fails for values that can't be escaped safely, in tags,
+    /// unquoted attribute values and `srcdoc`, and for URLs with a scheme
+    /// other than `http`, `https`, `mailto`, `tel` and `ftp`, like
+    /// `javascript:`. The scheme is checked on everything written at the
+    /// start of the URL, leading spaces included, so it can't be split
+    /// between values.
+    ///
+    /// Strings, comments and regexps are tracked in scripts. Whether a `/`
+    /// starts a regexp is guessed from the token before it, and rendering
+    /// fails for values after a `/` that depends on the branch a block took.
+    ///
+    /// The context after a block is computed from the text of its branches,
+    /// and after a partial from the text of the partial, whatever was
+    /// rendered. Rendering fails when the branches of a block, or a block
+    /// without `else` and its body, end in different contexts, as in
+    /// `{{#if a}}<script>{{/if}}`.
+    ///
+    /// Triple-stash expressions are still written as is.
+    pub fn set_contextual_escape(&mut self, enabled: bool) {
+        self.contextual_escape = enabled;
+    }
+
+    /// Return contextual escape state, default is false
+    pub fn contextual_escape(&self) -> bool {
+        self.contextual_escape
+    }
+
     /// Return `true` if a template is registered for the given name
     pub fn has_template(&self, name: &str) -> bool {
         self.get_template(name).is_some()
@@ -444,18 +751,41 @@ impl<'reg> Registry<'reg> {
         name: &str,
     ) -> Option<Result<Cow<'reg, Template>, RenderError>> {
         if let (true, Some(source)) = (self.dev_mode, self.template_sources.get(name)) {
-            let r = source
-                .load()
-                .map_err(|e| TemplateError::from((e, name.to_owned())))
-                .and_then(|tpl_str| Template::compile_with_name(tpl_str, name.to_owned()))
-                .map(Cow::Owned)
-                .map_err(RenderError::from);
-            Some(r)
+            if let Some(r) = load_template(source.as_ref(), name).transpose() {
+                return Some(r);
+            }
+        }
+
+        if let Some(t) = self.templates.get(name) {
+            Some(Ok(Cow::Borrowed(t)))
+        } else if self.dev_mode {
+            self.load_from_loaders(name)
+        } else if let Some(loaded) = self.loaded_templates.get(name) {
+            loaded.map(|t| Ok(Cow::Owned(t.as_ref().clone())))
         } else {
-            self.templates.get(name).map(|t| Ok(Cow::Borrowed(t)))
+            let loaded = self.load_from_loaders(name);
+            match loaded {
+                Some(Ok(ref t)) => self
+                    .loaded_templates
+                    .insert(name, Some(Arc::new(t.as_ref().clone()))),
+                None => self.loaded_templates.insert(name, None),
+                // errors are reported again on the next visit
+                Some(Err(_)) => {}
+            }
+            loaded
         }
     }
 
+    fn load_from_loaders(
+        &'reg self,
+        name: &str,
+    ) -> Option<Result<Cow<'reg, Template>, RenderError>> {
+        self.template_loaders
+            .iter()
+            .map(|loader| load_template(loader.as_ref(), name))
+            .find_map(Result::transpose)
+    }
+
     #[inline]
     pub(crate) fn get_or_load_template(
         &'reg self,
@@ -464,8 +794,56 @@ impl<'reg> Registry<'reg> {
         if let Some(result) = self.get_or_load_template_optional(name) {
             result
         } else {
-            Err(RenderError::new(format!("Template not found: {}", name)))
+            Err(RenderErrorReason::TemplateNotFound(name.to_owned()).into())
+        }
+    }
+
+    /// Call `f` with the template names partial `name` included from
+    /// template `scope` may refer to, in search order, until it returns a
+    /// value
+    pub(crate) fn find_partial_candidate<T, F>(
+        &self,
+        name: &str,
+        scope: Option<&str>,
+        mut f: F,
+    ) -> Option<T>
+    where
+        F: FnMut(&str) -> Option<T>,
+    {
+        if name.contains(NAMESPACE_SEPARATOR) {
+            return f(&name.replace(NAMESPACE_SEPARATOR, "/"));
         }
+
+        let scope_namespaces = iter::successors(scope, |s| s.rfind('/').map(|idx| &s[..idx]))
+            .skip(1)
+            .filter(|namespace| self.namespaces.contains(*namespace));
+        let namespaces =
+            scope_namespaces.chain(self.partial_search_path.iter().map(String::as_str));
+
+        let mut candidate = String::new();
+        for namespace in namespaces {
+            candidate.clear();
+            candidate.push_str(namespace);
+            candidate.push('/');
+            candidate.push_str(name);
+            if let Some(found) = f(&candidate) {
+                return Some(found);
+            }
+        }
+        f(name)
+    }
+
+    /// Find partial `name` included from template `scope`, along with the
+    /// name it's found with
+    pub(crate) fn get_or_load_partial(
+        &'reg self,
+        name: &str,
+        scope: Option<&str>,
+    ) -> Option<(String, Result<Cow<'reg, Template>, RenderError>)> {
+        self.find_partial_candidate(name, scope, |candidate| {
+            self.get_or_load_template_optional(candidate)
+                .map(|tpl| (candidate.to_owned(), tpl))
+        })
     }
 
     /// Return a registered helper
@@ -477,7 +855,8 @@ impl<'reg> Registry<'reg> {
         #[cfg(feature = "script_helper")]
         if let (true, Some(source)) = (self.dev_mode, self.script_sources.get(name)) {
             return source
-                .load()
+                .load(name)
+                .and_then(|s| s.ok_or_else(|| sources::not_found(name)))
                 .map_err(ScriptError::from)
                 .and_then(|s| {
                     let helper = Box::new(ScriptHelper {
@@ -514,12 +893,115 @@ impl<'reg> Registry<'reg> {
         &self.templates
     }
 
+    /// Return the partials, helpers, decorators and data paths used by each
+    /// registered template, along with those that are not registered and
+    /// partial cycles
+    ///
+    /// Templates are analyzed without rendering them, see `Template::dependencies`.
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        analysis::dependency_graph(self)
+    }
+
+    /// Check that partials, helpers and decorators used by registered
+    /// templates are all registered
+    ///
+    /// It reports up front what would otherwise fail at render time. Partials
+    /// including each other are valid when the recursion is guarded, they
+    /// are listed in `DependencyGraph::cycles` instead.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let errors = self.dependency_graph().errors();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Prepare a render error for display, with a hint when a helper,
+    /// decorator or template name looks like a typo of a registered one
+    ///
+    /// Template lines are included when the template is reloaded in dev
+    /// mode, otherwise use `Diagnostic::with_source` to add them.
+    pub fn diagnostic(&self, error: &RenderError) -> Diagnostic {
+        let d = diagnostic::diagnostic(self, error);
+        let source = error
+            .template_name
+            .as_ref()
+            .filter(|_| self.dev_mode)
+            .and_then(|name| self.template_sources.get(name).map(|s| s.load(name)));
+        match source {
+            Some(Ok(Some(source))) => d.with_source(&source),
+            _ => d,
+        }
+    }
+
+    pub(crate) fn helper_names(&self) -> Vec<&str> {
+        self.helpers.keys().map(String::as_str).collect()
+    }
+
+    pub(crate) fn decorator_names(&self) -> Vec<&str> {
+        self.decorators.keys().map(String::as_str).collect()
+    }
+
     /// Unregister all templates
     pub fn clear_templates(&mut self) {
         self.templates.clear();
         self.template_sources.clear();
     }
 
+    /// Render a template within the render limits
+    fn render_limited<'a>(
+        &'a self,
+        tpl: &'a Template,
+        ctx: &'a Context,
+        rc: &mut RenderContext<'a, 'a>,
+        output: &mut dyn Output,
+    ) -> Result<(), RenderError> {
+        let state = LimitState::new(&self.render_limits).map(Rc::new);
+        self.render_with_limits(tpl, ctx, rc, output, state)
+    }
+
+    /// Render a pass of an async render, within the limits of the whole
+    /// render
+    #[cfg(feature = "async")]
+    fn render_async_pass<'a>(
+        &'a self,
+        tpl: &'a Template,
+        ctx: &'a Context,
+        rc: &mut RenderContext<'a, 'a>,
+        output: &mut dyn Output,
+        calls: Rc<RefCell<AsyncCalls>>,
+    ) -> Result<(), RenderError> {
+        let state = helper_async::take_limits(&calls).map(Rc::new);
+        rc.set_async_calls(calls.clone());
+        let result = self.render_with_limits(tpl, ctx, rc, output, state.clone());
+        if let Some(state) = state {
+            helper_async::put_limits(&calls, state.after_pass());
+        }
+        result
+    }
+
+    fn render_with_limits<'a>(
+        &'a self,
+        tpl: &'a Template,
+        ctx: &'a Context,
+        rc: &mut RenderContext<'a, 'a>,
+        output: &mut dyn Output,
+        state: Option<Rc<LimitState>>,
+    ) -> Result<(), RenderError> {
+        match state {
+            Some(state) => {
+                rc.set_limits(state.clone());
+                let mut output = LimitedOutput {
+                    output,
+                    state: &state,
+                };
+                tpl.render(self, ctx, rc, &mut output)
+            }
+            None => tpl.render(self, ctx, rc, output),
+        }
+    }
+
     #[inline]
     fn render_to_output<O>(
         &self,
@@ -532,7 +1014,7 @@ impl<'reg> Registry<'reg> {
     {
         self.get_or_load_template(name).and_then(|t| {
             let mut render_context = RenderContext::new(t.name.as_ref());
-            t.render(self, ctx, &mut render_context, output)
+            self.render_limited(&t, ctx, &mut render_context, output)
         })
     }
 
@@ -570,6 +1052,21 @@ impl<'reg> Registry<'reg> {
         self.render_to_output(name, &ctx, &mut output)
     }
 
+    /// Render a registered template with reused context and write it to the
+    /// `std::io::Write`
+    pub fn render_with_context_to_write<W>(
+        &self,
+        name: &str,
+        ctx: &Context,
+        writer: W,
+    ) -> Result<(), RenderError>
+    where
+        W: Write,
+    {
+        let mut output = WriteOutput::new(writer);
+        self.render_to_output(name, ctx, &mut output)
+    }
+
     /// Render a template string using current registry without registering it
     pub fn render_template<T>(&self, template_string: &str, data: &T) -> Result<String, RenderError>
     where
@@ -591,7 +1088,7 @@ impl<'reg> Registry<'reg> {
         let mut out = StringOutput::new();
         {
             let mut render_context = RenderContext::new(None);
-            tpl.render(self, ctx, &mut render_context, &mut out)?;
+            self.render_limited(&tpl, ctx, &mut render_context, &mut out)?;
         }
 
         out.into_string().map_err(RenderError::from)
@@ -612,7 +1109,136 @@ impl<'reg> Registry<'reg> {
         let ctx = Context::wraps(data)?;
         let mut render_context = RenderContext::new(None);
         let mut out = WriteOutput::new(writer);
-        tpl.render(self, &ctx, &mut render_context, &mut out)
+        self.render_limited(&tpl, &ctx, &mut render_context, &mut out)
+    }
+
+    /// Render a template string using reused context data and write it to the
+    /// `std::io::Write`
+    pub fn render_template_with_context_to_write<W>(
+        &self,
+        template_string: &str,
+        ctx: &Context,
+        writer: W,
+    ) -> Result<(), RenderError>
+    where
+        W: Write,
+    {
+        let tpl = Template::compile(template_string)?;
+        let mut render_context = RenderContext::new(None);
+        let mut out = WriteOutput::new(writer);
+        self.render_limited(&tpl, ctx, &mut render_context, &mut out)
+    }
+
+    /// Render a registered template with some data, resolving async helpers
+    ///
+    /// The template is rendered synchronously, and rendered again each time
+    /// new async helper calls are found, after awaiting them. Templates
+    /// without async helpers are rendered only once.
+    ///
+    /// Async helpers return `null` until they are resolved. Once a pass gave
+    /// that placeholder to another helper, like `if` in
+    /// `{{#if (cached)}}..{{else}}{{fetch}}{{/if}}`, the branches it renders
+    /// may not be the final ones, so the async helpers it meets next are
+    /// only called by the next passes, if they meet them too.
+    ///
+    /// Only the output of the final pass is kept, but sync helpers and
+    /// decorators run on every pass, so their side effects may happen more
+    /// than once for a single render. Records of the `log` helper are kept
+    /// until the final pass and only logged once. Render limits apply to all
+    /// the passes together.
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub async fn render_async<T>(&self, name: &str, data: &T) -> Result<String, RenderError>
+    where
+        T: Serialize,
+    {
+        let ctx = Context::wraps(data)?;
+        self.render_with_context_async(name, &ctx).await
+    }
+
+    /// Render a registered template with reused context, resolving async helpers
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub async fn render_with_context_async(
+        &self,
+        name: &str,
+        ctx: &Context,
+    ) -> Result<String, RenderError> {
+        helper_async::render_resolved(&self.render_limits, |calls| {
+            let mut output = StringOutput::new();
+            self.get_or_load_template(name).and_then(|t| {
+                let mut render_context = RenderContext::new(t.name.as_ref());
+                self.render_async_pass(&t, ctx, &mut render_context, &mut output, calls)
+            })?;
+            output.into_string().map_err(RenderError::from)
+        })
+        .await
+    }
+
+    /// Render a template string without registering it, resolving async helpers
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub async fn render_template_async<T>(
+        &self,
+        template_string: &str,
+        data: &T,
+    ) -> Result<String, RenderError>
+    where
+        T: Serialize,
+    {
+        let tpl = Template::compile(template_string)?;
+        let ctx = Context::wraps(data)?;
+        helper_async::render_resolved(&self.render_limits, |calls| {
+            let mut output = StringOutput::new();
+            let mut render_context = RenderContext::new(None);
+            self.render_async_pass(&tpl, &ctx, &mut render_context, &mut output, calls)?;
+            output.into_string().map_err(RenderError::from)
+        })
+        .await
+    }
+
+    /// Render a registered template with reused context and write it to an
+    /// `AsyncOutput`
+    ///
+    /// Templates are rendered synchronously, in passes discarded until async
+    /// helpers are all resolved, see `render_async`. So the output isn't
+    /// written as it renders: the final pass is rendered into a buffer,
+    /// which is then written to `output` without blocking.
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub async fn render_with_context_to_async_output(
+        &self,
+        name: &str,
+        ctx: &Context,
+        output: &mut (dyn AsyncOutput + Send),
+    ) -> Result<(), RenderError> {
+        let rendered = self.render_with_context_async(name, ctx).await?;
+        output.write(&rendered).await?;
+        Ok(())
+    }
+
+    /// Render a registered template and write it to a tokio `AsyncWrite`
+    ///
+    /// The output is written once rendered, see
+    /// `render_with_context_to_async_output`.
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub async fn render_to_async_write<T, W>(
+        &self,
+        name: &str,
+        data: &T,
+        mut writer: W,
+    ) -> Result<(), RenderError>
+    where
+        T: Serialize,
+        W: AsyncWrite + Unpin + Send,
+    {
+        let ctx = Context::wraps(data)?;
+        let mut output = AsyncWriteOutput::new(&mut writer);
+        self.render_with_context_to_async_output(name, &ctx, &mut output)
+            .await?;
+        writer.flush().await?;
+        Ok(())
     }
 }
 
@@ -628,6 +1254,7 @@ mod test {
     use crate::template::Template;
     use std::fs::File;
     use std::io::Write;
+    use std::iter::FromIterator;
     use tempfile::tempdir;
 
     #[derive(Clone, Copy)]
@@ -671,9 +1298,26 @@ mod test {
         let num_helpers = 7;
         let num_boolean_helpers = 10; // stuff like gt and lte
         let num_custom_helpers = 1; // dummy from above
+        #[cfg(feature = "string_helpers")]
+        let num_string_helpers = 11;
+        #[cfg(not(feature = "string_helpers"))]
+        let num_string_helpers = 0;
+        #[cfg(feature = "math_helpers")]
+        let num_math_helpers = 12;
+        #[cfg(not(feature = "math_helpers"))]
+        let num_math_helpers = 0;
+        #[cfg(feature = "datetime")]
+        let num_datetime_helpers = 2;
+        #[cfg(not(feature = "datetime"))]
+        let num_datetime_helpers = 0;
         assert_eq!(
             r.helpers.len(),
-            num_helpers + num_boolean_helpers + num_custom_helpers
+            num_helpers
+                + num_boolean_helpers
+                + num_custom_helpers
+                + num_string_helpers
+                + num_math_helpers
+                + num_datetime_helpers
         );
     }
 
@@ -807,6 +1451,32 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_register_embed_templates() {
+        static TEMPLATES: &[(&str, &str)] = &[("t1", "{{> p/t2}}"), ("p/t2", "<h1></h1>")];
+
+        let mut r = Registry::new();
+        r.register_embed_templates(TEMPLATES).unwrap();
+        assert_eq!(r.templates.len(), 2);
+        assert_eq!(r.render("t1", &()).unwrap(), "<h1></h1>");
+
+        assert!(r.register_embed_templates(&[("t3", "{{#if}}")]).is_err());
+    }
+
+    #[test]
+    fn test_layout_helpers_opt_in() {
+        let mut r = Registry::new();
+        let data = json!({"block": "B", "super": "S"});
+        assert_eq!(
+            r.render_template("[{{block}}|{{super}}]", &data).unwrap(),
+            "[B|S]"
+        );
+
+        r.register_layout_helpers();
+        assert!(r.has_helper("extend"));
+        assert!(r.render_template("[{{block}}]", &data).is_err());
+    }
+
     #[test]
     fn test_render_to_write() {
         let mut r = Registry::new();
@@ -1086,6 +1756,167 @@ mod test {
         dir.close().unwrap();
     }
 
+    #[test]
+    fn test_template_loader() {
+        use crate::sources::{DirectoryLoader, MapLoader};
+
+        let mut reg = Registry::new();
+
+        let mut map = MapLoader::new();
+        map.insert("index", "<ul>{{> item}}</ul>");
+        map.insert("bad", "{{#if}}");
+        reg.register_template_loader(Box::new(map));
+
+        let dir = tempdir().unwrap();
+        {
+            let mut file1: File = File::create(dir.path().join("item.hbs")).unwrap();
+            write!(file1, "<li>{{{{this}}}}</li>").unwrap();
+            let mut file2: File = File::create(dir.path().join("index.hbs")).unwrap();
+            write!(file2, "shadowed").unwrap();
+        }
+        reg.register_template_loader(Box::new(DirectoryLoader::new(dir.path(), ".hbs")));
+
+        assert!(!reg.has_template("index"));
+        assert_eq!(reg.render("index", &1).unwrap(), "<ul><li>1</li></ul>");
+        assert!(reg.render("bad", &()).is_err());
+        assert_eq!(
+            reg.render("missing", &()).unwrap_err().desc,
+            "Template not found: missing"
+        );
+
+        // registered templates take precedence over loaders
+        reg.register_template_string("item", "<li>{{this}}!</li>")
+            .unwrap();
+        assert_eq!(reg.render("index", &1).unwrap(), "<ul><li>1!</li></ul>");
+
+        reg.register_template_source("loaded", Box::new(DirectoryLoader::new(dir.path(), ".hbs")))
+            .unwrap_err();
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_template_loader_cache() {
+        use crate::sources::TemplateLoader;
+        use std::io::Error as IOError;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        struct CountingLoader(Arc<AtomicUsize>);
+
+        impl TemplateLoader for CountingLoader {
+            fn load(&self, name: &str) -> Result<Option<String>, IOError> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(if name == "index" {
+                    Some("{{> missing}}{{this}}".to_owned())
+                } else {
+                    None
+                })
+            }
+        }
+
+        let loads = Arc::new(AtomicUsize::new(0));
+        let mut reg = Registry::new();
+        reg.register_template_loader(Box::new(CountingLoader(loads.clone())));
+
+        for _ in 0..3 {
+            assert_eq!(reg.render("index", &1).unwrap(), "1");
+        }
+        // `index` and `missing` are loaded once
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+
+        reg.clear_template_loaders();
+        reg.register_template_loader(Box::new(CountingLoader(loads.clone())));
+        assert_eq!(reg.render("index", &1).unwrap(), "1");
+        assert_eq!(loads.load(Ordering::SeqCst), 4);
+
+        reg.set_dev_mode(true);
+        assert_eq!(reg.render("index", &1).unwrap(), "1");
+        assert_eq!(reg.render("index", &1).unwrap(), "1");
+        assert_eq!(loads.load(Ordering::SeqCst), 8);
+    }
+
+    #[test]
+    fn test_namespaces() {
+        use crate::sources::MapLoader;
+
+        let mut ui = Registry::new();
+        ui.register_template_string(
+            "card",
+            "<card>{{> icon}}{{#if true}}{{> button}}{{/if}}</card>",
+        )
+        .unwrap();
+        ui.register_template_string("forms/field", "<field>{{> button}}</field>")
+            .unwrap();
+        ui.register_template_loader(Box::new(MapLoader::from_iter(vec![(
+            "button",
+            "<button/>",
+        )])));
+
+        let mut reg = Registry::new();
+        reg.register_template_string("icon", "<icon/>").unwrap();
+        reg.register_template_string("button", "app button")
+            .unwrap();
+        reg.register_template_string("page", "{{> ui::card}}{{> card}}|{{> button}}")
+            .unwrap();
+        reg.register_namespace("ui", &ui);
+
+        assert!(reg.has_template("ui/card"));
+        assert_eq!(
+            reg.render("ui/forms/field", &()).unwrap(),
+            "<field><button/></field>"
+        );
+        assert_eq!(
+            reg.render("page", &()).unwrap(),
+            "<card><icon/><button/></card>|app button"
+        );
+
+        reg.set_partial_search_path(vec!["ui"]);
+        assert_eq!(reg.partial_search_path(), ["ui".to_owned()]);
+        assert_eq!(
+            reg.render("page", &()).unwrap(),
+            "<card><icon/><button/></card><card><icon/><button/></card>|<button/>"
+        );
+        assert!(reg.validate().is_ok());
+
+        // a partial resolved to the template including it
+        reg.register_template_string("ui/icon", "{{> icon}}")
+            .unwrap();
+        assert!(reg.render("ui/icon", &()).is_err());
+        assert_eq!(
+            reg.dependency_graph().cycles,
+            vec![vec!["ui/icon".to_owned(), "ui/icon".to_owned()]]
+        );
+    }
+
+    #[test]
+    fn test_partials_outside_namespaces() {
+        use crate::sources::MapLoader;
+
+        // names with a `/` from a directory are not namespaces
+        let mut reg = Registry::new();
+        reg.register_template_string("pages/index", "{{> header}}")
+            .unwrap();
+        reg.register_template_string("pages/header", "page header")
+            .unwrap();
+        reg.register_template_string("header", "header").unwrap();
+        assert_eq!(reg.render("pages/index", &()).unwrap(), "header");
+
+        // nested namespaces are mounted along with their parent
+        let mut icons = Registry::new();
+        icons
+            .register_template_string("star", "*{{> size}}")
+            .unwrap();
+        icons.register_template_string("size", "16").unwrap();
+        let mut ui = Registry::new();
+        ui.register_namespace("icons", &icons);
+        ui.register_template_loader(Box::new(MapLoader::from_iter(vec![("size", "32")])));
+        reg.register_namespace("ui", &ui);
+        assert_eq!(reg.render("ui/icons/star", &()).unwrap(), "*16");
+        reg.unregister_template("ui/icons/size");
+        assert_eq!(reg.render("ui/icons/star", &()).unwrap(), "*32");
+    }
+
     #[test]
     #[cfg(feature = "script_helper")]
     fn test_script_helper() {
@@ -1,16 +1,22 @@
 use std::borrow::{Borrow, Cow};
-use std::collections::{BTreeMap, VecDeque};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fmt;
 use std::rc::Rc;
 
 use serde_json::value::Value as Json;
 
 use crate::block::BlockContext;
-use crate::context::Context;
-use crate::error::RenderError;
+use crate::context::{ComputedCache, Context};
+use crate::error::{RenderError, RenderErrorReason};
+use crate::escape::{self, HtmlContext};
+#[cfg(feature = "async")]
+use crate::helpers::helper_async::AsyncCalls;
+use crate::helpers::helper_layout::BlockFrame;
 use crate::helpers::HelperDef;
 use crate::json::path::Path;
 use crate::json::value::{JsonRender, PathAndJson, ScopedJson};
+use crate::limits::LimitState;
 use crate::output::{Output, StringOutput};
 use crate::partial;
 use crate::registry::Registry;
@@ -46,7 +52,26 @@ pub struct RenderContextInner<'reg: 'rc, 'rc> {
     current_template: Option<&'reg String>,
     /// root template name
     root_template: Option<&'reg String>,
+    /// the last named template entered, partials are searched from its
+    /// namespace
+    scope_template: Option<&'reg String>,
     disable_escape: bool,
+    /// async helper calls when rendering with `render_async`
+    #[cfg(feature = "async")]
+    async_calls: Option<Rc<RefCell<AsyncCalls>>>,
+    /// resources used against the registry render limits
+    limits: Option<Rc<LimitState>>,
+    /// html context of the element being rendered, for contextual escaping
+    html_context: Option<HtmlContext>,
+    /// what was written of the URL value being rendered, while its scheme
+    /// is unknown
+    url_prefix: Rc<RefCell<String>>,
+    /// blocks overridden by templates extending a layout, outermost first
+    layout_blocks: BTreeMap<String, Vec<&'reg Template>>,
+    /// layout blocks being rendered
+    block_frames: Vec<BlockFrame<'reg>>,
+    /// computed context values evaluated in this render
+    computed_cache: Rc<ComputedCache>,
 }
 
 impl<'reg: 'rc, 'rc> RenderContext<'reg, 'rc> {
@@ -59,7 +84,16 @@ impl<'reg: 'rc, 'rc> RenderContext<'reg, 'rc> {
             local_helpers: BTreeMap::new(),
             current_template: None,
             root_template,
+            scope_template: None,
             disable_escape: false,
+            #[cfg(feature = "async")]
+            async_calls: None,
+            limits: None,
+            html_context: None,
+            url_prefix: Rc::new(RefCell::new(String::new())),
+            layout_blocks: BTreeMap::new(),
+            block_frames: Vec::new(),
+            computed_cache: Rc::new(RefCell::new(HashMap::new())),
         });
 
         let mut blocks = VecDeque::with_capacity(5);
@@ -156,7 +190,19 @@ impl<'reg: 'rc, 'rc> RenderContext<'reg, 'rc> {
                 .get_local_var(*level, name)
                 .map(|v| ScopedJson::Derived(v.clone()))
                 .unwrap_or_else(|| ScopedJson::Missing)),
-            Path::Relative((segs, _)) => context.navigate(segs, &self.blocks),
+            Path::Relative((segs, _)) => {
+                context.navigate(segs, &self.blocks, &self.inner().computed_cache)
+            }
+        }
+    }
+
+    /// The full path of `path`, when it's resolved by a lazy context
+    pub(crate) fn lazy_path(&self, context: &Context, path: &Path) -> Option<Vec<String>> {
+        match path {
+            Path::Relative((segs, _)) if self.context().is_none() => {
+                context.lazy_path(segs, &self.blocks)
+            }
+            _ => None,
         }
     }
 
@@ -252,6 +298,12 @@ impl<'reg: 'rc, 'rc> RenderContext<'reg, 'rc> {
         self.inner_mut().current_template = name;
     }
 
+    /// Name of the template that partials are resolved from, unlike the
+    /// current template it's kept in blocks
+    pub(crate) fn get_scope_template_name(&self) -> Option<&'reg String> {
+        self.inner().scope_template
+    }
+
     /// Get root template name if any.
     /// This is the template name that you call `render` from `Handlebars`.
     pub fn get_root_template_name(&self) -> Option<&'reg String> {
@@ -268,6 +320,64 @@ impl<'reg: 'rc, 'rc> RenderContext<'reg, 'rc> {
     pub fn set_disable_escape(&mut self, disable: bool) {
         self.inner_mut().disable_escape = disable
     }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn async_calls(&self) -> Option<Rc<RefCell<AsyncCalls>>> {
+        self.inner().async_calls.clone()
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn set_async_calls(&mut self, calls: Rc<RefCell<AsyncCalls>>) {
+        self.inner_mut().async_calls = Some(calls);
+    }
+
+    pub(crate) fn limits(&self) -> Option<&LimitState> {
+        self.inner().limits.as_deref()
+    }
+
+    pub(crate) fn set_limits(&mut self, limits: Rc<LimitState>) {
+        self.inner_mut().limits = Some(limits);
+    }
+
+    pub(crate) fn html_context(&self) -> Option<&HtmlContext> {
+        self.inner().html_context.as_ref()
+    }
+
+    pub(crate) fn set_html_context(&mut self, html_context: HtmlContext) {
+        self.inner_mut().html_context = Some(html_context);
+    }
+
+    pub(crate) fn url_prefix(&self) -> &RefCell<String> {
+        &self.inner().url_prefix
+    }
+
+    pub(crate) fn add_layout_block(&mut self, name: String, content: &'reg Template) {
+        self.inner_mut()
+            .layout_blocks
+            .entry(name)
+            .or_default()
+            .push(content);
+    }
+
+    pub(crate) fn layout_blocks(&self, name: &str) -> Vec<&'reg Template> {
+        self.inner()
+            .layout_blocks
+            .get(name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn block_frame(&self) -> Option<&BlockFrame<'reg>> {
+        self.inner().block_frames.last()
+    }
+
+    pub(crate) fn push_block_frame(&mut self, frame: BlockFrame<'reg>) {
+        self.inner_mut().block_frames.push(frame);
+    }
+
+    pub(crate) fn pop_block_frame(&mut self) {
+        self.inner_mut().block_frames.pop();
+    }
 }
 
 impl<'reg, 'rc> fmt::Debug for RenderContextInner<'reg, 'rc> {
@@ -277,6 +387,7 @@ impl<'reg, 'rc> fmt::Debug for RenderContextInner<'reg, 'rc> {
             .field("partial_block_stack", &self.partial_block_stack)
             .field("root_template", &self.root_template)
             .field("current_template", &self.current_template)
+            .field("scope_template", &self.scope_template)
             .field("disable_eacape", &self.disable_escape)
             .finish()
     }
@@ -302,9 +413,24 @@ impl<'reg: 'rc, 'rc> Helper<'reg, 'rc> {
         render_context: &mut RenderContext<'reg, 'rc>,
     ) -> Result<Helper<'reg, 'rc>, RenderError> {
         let name = ht.name.expand_as_name(registry, context, render_context)?;
+        // builtin block helpers read lazy values from the context, so they
+        // are not turned into JSON here
+        let reads_lazy = ht.block
+            && registry.reads_lazy_values(&name)
+            && render_context.get_local_helper(&name).is_none();
         let mut pv = Vec::with_capacity(ht.params.len());
         for p in &ht.params {
-            let r = p.expand(registry, context, render_context)?;
+            let lazy_path = match *p {
+                Parameter::Path(ref path) if reads_lazy => render_context.lazy_path(context, path),
+                _ => None,
+            };
+            let r = match lazy_path {
+                Some(paths) => PathAndJson::new(
+                    p.as_name().map(str::to_owned),
+                    ScopedJson::Lazy(Json::Null, paths),
+                ),
+                None => p.expand(registry, context, render_context)?,
+            };
             pv.push(r);
         }
 
@@ -612,6 +738,9 @@ impl Parameter {
                 Expression(ref ht) => {
                     let name = ht.name.expand_as_name(registry, ctx, rc)?;
 
+                    if let Some(limits) = rc.limits() {
+                        limits.count_helper_call()?;
+                    }
                     let h = Helper::try_from_template(ht, registry, ctx, rc)?;
                     if let Some(ref d) = rc.get_local_helper(&name) {
                         call_helper_for_value(d.as_ref(), &h, registry, ctx, rc)
@@ -628,12 +757,14 @@ impl Parameter {
 
                         helper
                             .ok_or_else(|| {
-                                RenderError::new(format!("Helper not defined: {:?}", ht.name))
+                                RenderErrorReason::HelperNotFound(name.to_string()).into()
                             })
                             .and_then(|d| call_helper_for_value(d.as_ref(), &h, registry, ctx, rc))
                     }
                 }
-                _ => unreachable!(),
+                // the parser only creates expressions, other elements can be
+                // set when rewriting the template
+                _ => Err(RenderErrorReason::InvalidSubexpression.into()),
             },
         }
     }
@@ -648,24 +779,51 @@ impl Renderable for Template {
         out: &mut dyn Output,
     ) -> Result<(), RenderError> {
         rc.set_current_template_name(self.name.as_ref());
+        if self.name.is_some() {
+            rc.inner_mut().scope_template = self.name.as_ref();
+        }
         let iter = self.elements.iter();
 
+        // html context of the next element, when escaping by context
+        let start = if registry.contextual_escape() {
+            Some(rc.html_context().cloned().unwrap_or(HtmlContext::Text))
+        } else {
+            None
+        };
+        let mut html = start.clone();
+
         for (idx, t) in iter.enumerate() {
-            t.render(registry, ctx, rc, out).map_err(|mut e| {
-                // add line/col number if the template has mapping data
-                if e.line_no.is_none() {
-                    if let Some(&TemplateMapping(line, col)) = self.mapping.get(idx) {
-                        e.line_no = Some(line);
-                        e.column_no = Some(col);
-                    }
+            if let Some(limits) = rc.limits() {
+                limits.check_deadline()?;
+            }
+            let result = match html.take() {
+                Some(html_ctx) => {
+                    rc.set_html_context(html_ctx.clone());
+                    escape::element_end(registry, rc, html_ctx, t).map(|end| html = Some(end))
                 }
+                None => Ok(()),
+            };
+            result
+                .and_then(|_| t.render(registry, ctx, rc, out))
+                .map_err(|mut e| {
+                    // add line/col number if the template has mapping data
+                    if e.line_no.is_none() {
+                        if let Some(&TemplateMapping(line, col)) = self.mapping.get(idx) {
+                            e.line_no = Some(line);
+                            e.column_no = Some(col);
+                        }
+                    }
 
-                if e.template_name.is_none() {
-                    e.template_name = self.name.clone();
-                }
+                    if e.template_name.is_none() {
+                        e.template_name = self.name.clone();
+                    }
 
-                e
-            })?;
+                    e
+                })?;
+        }
+        // blocks render their body again from where they started
+        if let Some(start) = start {
+            rc.set_html_context(start);
         }
         Ok(())
     }
@@ -713,6 +871,9 @@ fn render_helper<'reg: 'rc, 'rc>(
     rc: &mut RenderContext<'reg, 'rc>,
     out: &mut dyn Output,
 ) -> Result<(), RenderError> {
+    if let Some(limits) = rc.limits() {
+        limits.count_helper_call()?;
+    }
     let h = Helper::try_from_template(ht, registry, ctx, rc)?;
     debug!(
         "Rendering helper: {:?}, params: {:?}, hash: {:?}",
@@ -734,16 +895,24 @@ fn render_helper<'reg: 'rc, 'rc>(
         }
 
         helper
-            .ok_or_else(|| RenderError::new(format!("Helper not defined: {:?}", h.name())))
+            .ok_or_else(|| RenderErrorReason::HelperNotFound(h.name().to_owned()).into())
             .and_then(|d| d.call(&h, registry, ctx, rc, out))
     }
 }
 
-pub(crate) fn do_escape(r: &Registry<'_>, rc: &RenderContext<'_, '_>, content: String) -> String {
-    if !rc.is_disable_escape() {
-        r.get_escape_fn()(&content)
-    } else {
-        content
+pub(crate) fn do_escape(
+    r: &Registry<'_>,
+    rc: &RenderContext<'_, '_>,
+    content: String,
+) -> Result<String, RenderError> {
+    if rc.is_disable_escape() {
+        return Ok(content);
+    }
+    match rc.html_context() {
+        Some(html) if r.contextual_escape() => {
+            html.escape(&content, &mut rc.url_prefix().borrow_mut())
+        }
+        _ => Ok(r.get_escape_fn()(&content)),
     }
 }
 
@@ -757,6 +926,9 @@ impl Renderable for TemplateElement {
     ) -> Result<(), RenderError> {
         match *self {
             RawString(ref v) => {
+                if let Some(html) = rc.html_context().filter(|_| registry.contextual_escape()) {
+                    html.check_raw(v, &mut rc.url_prefix().borrow_mut())?;
+                }
                 out.write(v.as_ref())?;
                 Ok(())
             }
@@ -788,7 +960,7 @@ impl Renderable for TemplateElement {
                             }
                         } else {
                             let rendered = context_json.value().render();
-                            let output = do_escape(registry, rc, rendered);
+                            let output = do_escape(registry, rc, rendered)?;
                             out.write(output.as_ref())?;
                             Ok(())
                         }
@@ -828,10 +1000,7 @@ impl Evaluable for TemplateElement {
                 let di = Decorator::try_from_template(dt, registry, ctx, rc)?;
                 match registry.get_decorator(di.name()) {
                     Some(d) => d.call(&di, registry, ctx, rc),
-                    None => Err(RenderError::new(format!(
-                        "Decorator not defined: {:?}",
-                        dt.name
-                    ))),
+                    None => Err(RenderErrorReason::DecoratorNotFound(di.name().to_owned()).into()),
                 }
             }
             _ => Ok(()),
@@ -988,6 +1157,26 @@ fn test_render_subexpression_issue_115() {
     assert_eq!(sw.into_string(), "123".to_string());
 }
 
+#[test]
+fn test_render_invalid_subexpression() {
+    let r = Registry::new();
+    let mut t = Template::compile("{{lookup (lookup a \"b\") \"c\"}}").unwrap();
+    if let Expression(ref mut ht) = t.elements[0] {
+        if let Parameter::Subexpression(ref mut subexpr) = ht.params[0] {
+            *subexpr.element = RawString("x".to_owned());
+        }
+    }
+
+    let ctx = Context::null();
+    let mut rc = RenderContext::new(None);
+    let mut out = StringOutput::new();
+    let e = t.render(&r, &ctx, &mut rc, &mut out).unwrap_err();
+    assert!(matches!(
+        e.reason(),
+        RenderErrorReason::InvalidSubexpression
+    ));
+}
+
 #[test]
 fn test_render_error_line_no() {
     let mut r = Registry::new();
@@ -1,34 +1,284 @@
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, Error as IOError, Read};
-use std::path::PathBuf;
+use std::io::{BufReader, Error as IOError, ErrorKind, Read};
+use std::iter::FromIterator;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
 
-pub(crate) trait Source {
-    type Item;
-    type Error;
+#[cfg(feature = "dir_source")]
+use crate::error::TemplateError;
+#[cfg(feature = "dir_source")]
+use std::path;
+#[cfg(feature = "dir_source")]
+use walkdir::{DirEntry, Error as WalkdirError, WalkDir};
 
-    fn load(&self) -> Result<Self::Item, Self::Error>;
+/// A source of template text
+///
+/// The registry asks its loaders for the source of a template by name. This
+/// happens when a template is registered with
+/// `Registry::register_template_source`, when a template is not found in the
+/// registry (see `Registry::register_template_loader`), and every time a
+/// template is reloaded in dev mode.
+///
+/// Implement this trait to serve templates from a database, an embedded
+/// archive or anything else that can produce a string for a name.
+///
+/// ```
+/// use std::io::Error;
+/// use handlebars::{Handlebars, TemplateLoader};
+///
+/// struct Upper;
+///
+/// impl TemplateLoader for Upper {
+///     fn load(&self, name: &str) -> Result<Option<String>, Error> {
+///         Ok(Some(name.to_uppercase()))
+///     }
+/// }
+///
+/// let mut hbs = Handlebars::new();
+/// hbs.register_template_loader(Box::new(Upper));
+/// assert_eq!(hbs.render("hello", &()).unwrap(), "HELLO");
+/// ```
+pub trait TemplateLoader {
+    /// Load the source of template `name`
+    ///
+    /// Returns `Ok(None)` when this loader doesn't know about the template.
+    fn load(&self, name: &str) -> Result<Option<String>, IOError>;
 }
 
-pub(crate) struct FileSource {
+pub(crate) fn not_found(name: &str) -> IOError {
+    IOError::new(
+        ErrorKind::NotFound,
+        format!("Template source not found: {}", name),
+    )
+}
+
+fn read_file(path: &Path) -> Result<String, IOError> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut buf = String::new();
+    reader.read_to_string(&mut buf)?;
+
+    Ok(buf)
+}
+
+#[cfg(feature = "dir_source")]
+fn filter_file(entry: &DirEntry, suffix: &str) -> bool {
+    let path = entry.path();
+
+    // ignore hidden files, emacs buffers and files with wrong suffix
+    !path.is_file()
+        || path
+            .file_name()
+            .map(|s| {
+                let ds = s.to_string_lossy();
+                ds.starts_with('.') || ds.starts_with('#') || !ds.ends_with(suffix)
+            })
+            .unwrap_or(true)
+}
+
+/// Find template files in `dir_path`, paired with their template names
+///
+/// A file `some/path/file<tpl_extension>` is named `some/path/file`. Entries
+/// that can't be read, like a missing `dir_path`, are returned as errors.
+#[cfg(feature = "dir_source")]
+pub(crate) fn walk_template_files<'a>(
+    tpl_extension: &'a str,
+    dir_path: &Path,
+) -> impl Iterator<Item = Result<(String, PathBuf), WalkdirError>> + 'a {
+    let prefix_len = if dir_path
+        .to_string_lossy()
+        .ends_with(|c| c == '\\' || c == '/')
+    // `/` will work on windows too so we still need to check
+    {
+        dir_path.to_string_lossy().len()
+    } else {
+        dir_path.to_string_lossy().len() + 1
+    };
+
+    let walker = WalkDir::new(dir_path);
+    walker
+        .min_depth(1)
+        .into_iter()
+        .filter(move |e| match e {
+            Ok(entry) => !filter_file(entry, tpl_extension),
+            Err(_) => true,
+        })
+        .map(move |entry| {
+            let entry = entry?;
+            let tpl_path = entry.path();
+            let tpl_file_path = entry.path().to_string_lossy();
+
+            let tpl_name = &tpl_file_path[prefix_len..tpl_file_path.len() - tpl_extension.len()];
+            // replace platform path separator with our internal one
+            let tpl_canonical_name = tpl_name.replace(path::MAIN_SEPARATOR, "/");
+            Ok((tpl_canonical_name, tpl_path.to_owned()))
+        })
+}
+
+/// Find template files in `dir_path`, failing on the first entry that can't
+/// be read, see `walk_template_files`
+#[cfg(feature = "dir_source")]
+pub(crate) fn template_files(
+    tpl_extension: &str,
+    dir_path: &Path,
+) -> Result<Vec<(String, PathBuf)>, TemplateError> {
+    walk_template_files(tpl_extension, dir_path)
+        .collect::<Result<_, _>>()
+        .map_err(TemplateError::from)
+}
+
+/// A loader that reads a single file, whatever name it is asked for
+#[derive(Debug, Clone)]
+pub struct FileLoader {
     path: PathBuf,
 }
 
-impl FileSource {
-    pub(crate) fn new(path: PathBuf) -> FileSource {
-        FileSource { path }
+impl FileLoader {
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileLoader {
+        FileLoader { path: path.into() }
+    }
+}
+
+impl TemplateLoader for FileLoader {
+    fn load(&self, _: &str) -> Result<Option<String>, IOError> {
+        read_file(&self.path).map(Some)
+    }
+}
+
+/// A loader that maps template names to files in a directory
+///
+/// Template `some/path/file` is read from `<dir>/some/path/file<extension>`,
+/// which is the naming rule of `Registry::register_templates_directory`.
+/// Names that would escape the directory, like `../secret`, are never loaded.
+#[derive(Debug, Clone)]
+pub struct DirectoryLoader {
+    dir: PathBuf,
+    extension: String,
+}
+
+impl DirectoryLoader {
+    pub fn new<P: Into<PathBuf>>(dir: P, extension: &str) -> DirectoryLoader {
+        DirectoryLoader {
+            dir: dir.into(),
+            extension: extension.to_owned(),
+        }
+    }
+}
+
+impl TemplateLoader for DirectoryLoader {
+    fn load(&self, name: &str) -> Result<Option<String>, IOError> {
+        let relative = Path::new(name);
+        if name.is_empty()
+            || !relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Ok(None);
+        }
+
+        let path = self.dir.join(format!("{}{}", name, self.extension));
+        match read_file(&path) {
+            Ok(s) => Ok(Some(s)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// A loader backed by an in-memory map of template names and sources
+///
+/// ```
+/// use handlebars::{Handlebars, MapLoader};
+///
+/// static TEMPLATES: &[(&str, &str)] = &[("hello", "Hello {{this}}")];
+///
+/// let mut hbs = Handlebars::new();
+/// hbs.register_template_loader(Box::new(TEMPLATES.iter().copied().collect::<MapLoader>()));
+/// assert_eq!(hbs.render("hello", &"world").unwrap(), "Hello world");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MapLoader {
+    templates: HashMap<String, String>,
+}
+
+impl MapLoader {
+    pub fn new() -> MapLoader {
+        MapLoader::default()
+    }
+
+    /// Add or replace a template source
+    pub fn insert<K: Into<String>, V: Into<String>>(&mut self, name: K, source: V) {
+        self.templates.insert(name.into(), source.into());
+    }
+
+    /// Remove a template source
+    pub fn remove(&mut self, name: &str) {
+        self.templates.remove(name);
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for MapLoader {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> MapLoader {
+        let mut loader = MapLoader::new();
+        for (name, source) in iter {
+            loader.insert(name, source);
+        }
+        loader
+    }
+}
+
+impl From<HashMap<String, String>> for MapLoader {
+    fn from(templates: HashMap<String, String>) -> MapLoader {
+        MapLoader { templates }
+    }
+}
+
+impl TemplateLoader for MapLoader {
+    fn load(&self, name: &str) -> Result<Option<String>, IOError> {
+        Ok(self.templates.get(name).cloned())
+    }
+}
+
+/// A loader serving the templates of another loader under a namespace
+///
+/// Template `<namespace>/name` is loaded as `name` from the inner loader.
+pub(crate) struct NamespaceLoader<'a> {
+    pub(crate) prefix: String,
+    pub(crate) loader: Arc<dyn TemplateLoader + Send + Sync + 'a>,
+}
+
+impl<'a> TemplateLoader for NamespaceLoader<'a> {
+    fn load(&self, name: &str) -> Result<Option<String>, IOError> {
+        match name.strip_prefix(self.prefix.as_str()) {
+            Some(name) => self.loader.load(name),
+            None => Ok(None),
+        }
     }
 }
 
-impl Source for FileSource {
-    type Item = String;
-    type Error = IOError;
+#[cfg(test)]
+mod test {
+    use super::{DirectoryLoader, TemplateLoader};
+    use std::fs::{self, File};
+    use std::io::Write;
+    use tempfile::tempdir;
 
-    fn load(&self) -> Result<Self::Item, Self::Error> {
-        let mut reader = BufReader::new(File::open(&self.path)?);
+    #[test]
+    fn test_directory_loader() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("partials")).unwrap();
+        {
+            let mut f = File::create(dir.path().join("partials/nav.hbs")).unwrap();
+            write!(f, "<nav></nav>").unwrap();
+        }
 
-        let mut buf = String::new();
-        reader.read_to_string(&mut buf)?;
+        let loader = DirectoryLoader::new(dir.path().join("partials"), ".hbs");
+        assert_eq!(loader.load("nav").unwrap().unwrap(), "<nav></nav>");
+        assert!(loader.load("footer").unwrap().is_none());
+        assert!(loader.load("../partials/nav").unwrap().is_none());
+        assert!(loader.load("").unwrap().is_none());
 
-        Ok(buf)
+        dir.close().unwrap();
     }
 }
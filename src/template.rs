@@ -18,6 +18,10 @@ use self::TemplateElement::*;
 pub struct TemplateMapping(pub usize, pub usize);
 
 /// A handlebars template
+///
+/// A template prints as handlebars source with `Display`. Compiling the
+/// printed source gives back an equal template, apart from the source
+/// mapping.
 #[derive(PartialEq, Clone, Debug, Default)]
 pub struct Template {
     pub name: Option<String>,
@@ -46,6 +50,8 @@ impl Subexpression {
                 inverse: None,
                 block_param: None,
                 block: false,
+                raw: false,
+                whitespace: WhitespaceControl::default(),
             }))),
         }
     }
@@ -110,6 +116,20 @@ pub enum Parameter {
     Subexpression(Subexpression),
 }
 
+/// Whitespace control `~` markers of the tags of an element
+///
+/// Each pair tells if whitespaces before (`{{~`) and after (`~}}`) the tag
+/// are omitted.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct WhitespaceControl {
+    /// the opening tag of a block, or the only tag of an expression
+    pub open: (bool, bool),
+    /// the `{{else}}` tag of a helper block
+    pub inverse: (bool, bool),
+    /// the closing tag of a block
+    pub close: (bool, bool),
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub struct HelperTemplate {
     pub name: Parameter,
@@ -119,6 +139,9 @@ pub struct HelperTemplate {
     pub template: Option<Template>,
     pub inverse: Option<Template>,
     pub block: bool,
+    /// raw block `{{{{raw}}}}...{{{{/raw}}}}`
+    pub raw: bool,
+    pub whitespace: WhitespaceControl,
 }
 
 impl HelperTemplate {
@@ -132,6 +155,8 @@ impl HelperTemplate {
             template: None,
             inverse: None,
             block: false,
+            raw: false,
+            whitespace: WhitespaceControl::default(),
         }
     }
 
@@ -146,6 +171,7 @@ pub struct DecoratorTemplate {
     pub params: Vec<Parameter>,
     pub hash: HashMap<String, Parameter>,
     pub template: Option<Template>,
+    pub whitespace: WhitespaceControl,
 }
 
 impl Parameter {
@@ -174,6 +200,43 @@ impl Parameter {
     }
 }
 
+/// The json string of a `'single quoted'` literal
+fn single_quoted_string(s: &str) -> Option<Json> {
+    if s.len() < 2 || !s.starts_with('\'') || !s.ends_with('\'') {
+        return None;
+    }
+    let mut double_quoted = String::with_capacity(s.len());
+    double_quoted.push('"');
+    let mut chars = s[1..s.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('\'') => double_quoted.push('\''),
+                Some(escaped) => {
+                    double_quoted.push('\\');
+                    double_quoted.push(escaped);
+                }
+                None => return None,
+            },
+            '"' => double_quoted.push_str("\\\""),
+            _ => double_quoted.push(c),
+        }
+    }
+    double_quoted.push('"');
+    Json::from_str(&double_quoted).ok()
+}
+
+/// The partial name of `[my partial]` or `'my partial'`
+fn unquote_partial_name(raw: &str) -> String {
+    if raw.len() > 1 && raw.starts_with('[') && raw.ends_with(']') {
+        raw[1..raw.len() - 1].to_owned()
+    } else if raw.len() > 1 && raw.starts_with('\'') && raw.ends_with('\'') {
+        raw[1..raw.len() - 1].replace("\\'", "'")
+    } else {
+        raw.to_owned()
+    }
+}
+
 impl Template {
     pub fn new() -> Template {
         Template::default()
@@ -212,9 +275,12 @@ impl Template {
         let rule = name_node.as_rule();
         let name_span = name_node.as_span();
         match rule {
-            Rule::identifier | Rule::partial_identifier | Rule::invert_tag_item => {
+            Rule::identifier | Rule::invert_tag_item => {
                 Ok(Parameter::Name(name_span.as_str().to_owned()))
             }
+            Rule::partial_identifier => {
+                Ok(Parameter::Name(unquote_partial_name(name_span.as_str())))
+            }
             Rule::reference => {
                 let paths = parse_json_path_from_iter(it, name_span.end());
                 Ok(Parameter::Path(Path::new(name_span.as_str(), paths)))
@@ -249,6 +315,8 @@ impl Template {
                 let s = param_span.as_str();
                 if let Ok(json) = Json::from_str(s) {
                     Parameter::Literal(json)
+                } else if let Some(json) = single_quoted_string(s) {
+                    Parameter::Literal(json)
                 } else {
                     Parameter::Name(s.to_owned())
                 }
@@ -332,6 +400,11 @@ impl Template {
             it.next();
         }
 
+        // the `else` of a chained inverse tag, followed by the helper
+        if it.peek().unwrap().as_rule() == Rule::invert_chain_item {
+            it.next();
+        }
+
         let name = Template::parse_name(source, it.by_ref(), limit)?;
 
         loop {
@@ -457,7 +530,9 @@ impl Template {
     }
 
     pub fn compile<'a>(source: &'a str) -> Result<Template, TemplateError> {
-        let mut helper_stack: VecDeque<HelperTemplate> = VecDeque::new();
+        // open blocks, with whether they are a chained inverse section
+        // `{{else if ...}}` closed with the block they follow
+        let mut helper_stack: VecDeque<(HelperTemplate, bool)> = VecDeque::new();
         let mut decorator_stack: VecDeque<DecoratorTemplate> = VecDeque::new();
         let mut template_stack: VecDeque<Template> = VecDeque::new();
 
@@ -576,8 +651,13 @@ impl Template {
                                     block: true,
                                     template: None,
                                     inverse: None,
+                                    raw: rule == Rule::raw_block_start,
+                                    whitespace: WhitespaceControl {
+                                        open: (exp.omit_pre_ws, exp.omit_pro_ws),
+                                        ..Default::default()
+                                    },
                                 };
-                                helper_stack.push_front(helper_template);
+                                helper_stack.push_front((helper_template, false));
                             }
                             Rule::decorator_block_start | Rule::partial_block_start => {
                                 let decorator = DecoratorTemplate {
@@ -585,6 +665,10 @@ impl Template {
                                     params: exp.params,
                                     hash: exp.hash,
                                     template: None,
+                                    whitespace: WhitespaceControl {
+                                        open: (exp.omit_pre_ws, exp.omit_pro_ws),
+                                        ..Default::default()
+                                    },
                                 };
                                 decorator_stack.push_front(decorator);
                             }
@@ -626,8 +710,51 @@ impl Template {
                         );
 
                         let t = template_stack.pop_front().unwrap();
-                        let h = helper_stack.front_mut().unwrap();
+                        let (h, _) = helper_stack.front_mut().unwrap();
+                        h.template = Some(t);
+                        h.whitespace.inverse = (exp.omit_pre_ws, exp.omit_pro_ws);
+                    }
+                    Rule::invert_chain_tag => {
+                        let exp = Template::parse_expression(source, it.by_ref(), span.end())?;
+
+                        if exp.omit_pre_ws {
+                            Template::remove_previous_whitespace(&mut template_stack);
+                        }
+                        omit_pro_ws = exp.omit_pro_ws;
+
+                        // standalone statement check, it also removes leading whitespaces of
+                        // previous rawstring when standalone statement detected
+                        trim_line_required = Template::process_standalone_statement(
+                            &mut template_stack,
+                            source,
+                            &span,
+                        );
+
+                        let t = template_stack.pop_front().unwrap();
+                        let (h, _) = helper_stack.front_mut().unwrap();
                         h.template = Some(t);
+                        h.whitespace.inverse = (exp.omit_pre_ws, exp.omit_pro_ws);
+
+                        // the chained block is the inverse of the previous one, it's
+                        // closed with it
+                        let mut inverse = Template::new();
+                        inverse.mapping.push(TemplateMapping(line_no, col_no));
+                        template_stack.push_front(inverse);
+                        let chained = HelperTemplate {
+                            name: exp.name,
+                            params: exp.params,
+                            hash: exp.hash,
+                            block_param: exp.block_param,
+                            block: true,
+                            template: None,
+                            inverse: None,
+                            raw: false,
+                            whitespace: WhitespaceControl {
+                                open: (exp.omit_pre_ws, exp.omit_pro_ws),
+                                ..Default::default()
+                            },
+                        };
+                        helper_stack.push_front((chained, true));
                     }
                     Rule::raw_block_text => {
                         let mut t = Template::new();
@@ -668,6 +795,11 @@ impl Template {
                                     block: false,
                                     template: None,
                                     inverse: None,
+                                    raw: false,
+                                    whitespace: WhitespaceControl {
+                                        open: (exp.omit_pre_ws, exp.omit_pro_ws),
+                                        ..Default::default()
+                                    },
                                 };
                                 let el = if rule == Rule::expression {
                                     Expression(Box::new(helper_template))
@@ -691,6 +823,10 @@ impl Template {
                                     params: exp.params,
                                     hash: exp.hash,
                                     template: None,
+                                    whitespace: WhitespaceControl {
+                                        open: (exp.omit_pre_ws, exp.omit_pro_ws),
+                                        ..Default::default()
+                                    },
                                 };
                                 let el = if rule == Rule::decorator_expression {
                                     DecoratorExpression(Box::new(decorator))
@@ -709,25 +845,32 @@ impl Template {
                                     &span,
                                 );
 
-                                let mut h = helper_stack.pop_front().unwrap();
                                 let close_tag_name = exp.name.as_name();
-                                if h.name.as_name() == close_tag_name {
+                                // close the chained blocks, then the block they follow
+                                loop {
+                                    let (mut h, chained) = helper_stack.pop_front().unwrap();
+                                    if !chained && h.name.as_name() != close_tag_name {
+                                        return Err(TemplateError::of(
+                                            TemplateErrorReason::MismatchingClosedHelper(
+                                                h.name.debug_name(),
+                                                exp.name.debug_name(),
+                                            ),
+                                        )
+                                        .at(source, line_no, col_no));
+                                    }
+
                                     let prev_t = template_stack.pop_front().unwrap();
                                     if h.template.is_some() {
                                         h.inverse = Some(prev_t);
                                     } else {
                                         h.template = Some(prev_t);
                                     }
+                                    h.whitespace.close = (exp.omit_pre_ws, exp.omit_pro_ws);
                                     let t = template_stack.front_mut().unwrap();
                                     t.elements.push(HelperBlock(Box::new(h)));
-                                } else {
-                                    return Err(TemplateError::of(
-                                        TemplateErrorReason::MismatchingClosedHelper(
-                                            h.name.debug_name(),
-                                            exp.name.debug_name(),
-                                        ),
-                                    )
-                                    .at(source, line_no, col_no));
+                                    if !chained {
+                                        break;
+                                    }
                                 }
                             }
                             Rule::decorator_block_end | Rule::partial_block_end => {
@@ -744,6 +887,7 @@ impl Template {
                                 if d.name.as_name() == close_tag_name {
                                     let prev_t = template_stack.pop_front().unwrap();
                                     d.template = Some(prev_t);
+                                    d.whitespace.close = (exp.omit_pre_ws, exp.omit_pro_ws);
                                     let t = template_stack.front_mut().unwrap();
                                     if rule == Rule::decorator_block_end {
                                         t.elements.push(DecoratorBlock(Box::new(d)));
@@ -941,6 +1085,55 @@ mod test {
         assert!(Template::compile(source).is_ok());
     }
 
+    #[test]
+    fn test_parse_quoted_partial_name() {
+        let t = Template::compile("{{> [my card]}}{{#> 'it\\'s'}}{{/[it's]}}").unwrap();
+        let names: Vec<_> = t
+            .elements
+            .iter()
+            .filter_map(|e| match e {
+                PartialExpression(d) | PartialBlock(d) => d.name.as_name(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, vec!["my card", "it's"]);
+    }
+
+    #[test]
+    fn test_parse_single_quoted_literal() {
+        let t = Template::compile("{{lookup . 'it\\'s \"x\"\\n'}}").unwrap();
+        match t.elements[0] {
+            Expression(ref h) => assert_eq!(h.params[1], Parameter::Literal(json!("it's \"x\"\n"))),
+            _ => panic!("Expression expected"),
+        }
+    }
+
+    #[test]
+    fn test_parse_chained_else() {
+        let t = Template::compile("{{#if a}}1{{else if b}}2{{else}}3{{/if}}").unwrap();
+        assert_eq!(t.elements.len(), 1);
+        match t.elements[0] {
+            HelperBlock(ref h) => {
+                let inverse = h.inverse.as_ref().unwrap();
+                assert_eq!(inverse.elements.len(), 1);
+                match inverse.elements[0] {
+                    HelperBlock(ref chained) => {
+                        assert_eq!(chained.name.as_name(), Some("if"));
+                        assert_eq!(
+                            chained.inverse.as_ref().unwrap().elements[0],
+                            RawString("3".to_owned())
+                        );
+                    }
+                    _ => panic!("Helper block expected"),
+                }
+            }
+            _ => panic!("Helper block expected"),
+        }
+
+        // `else` is still a keyword, not a helper
+        assert!(Template::compile("{{#if a}}{{elseif b}}{{/if}}").is_ok());
+    }
+
     #[test]
     fn test_parse_error() {
         let source = "{{#ifequals name compare=\"hello\"}}\nhello\n\t{{else}}\ngood";
@@ -1031,13 +1224,17 @@ mod test {
         assert_eq!(t.elements.len(), 4);
 
         assert_eq!(t.elements[0], RawString("hello~".to_string()));
-        assert_eq!(
-            t.elements[1],
-            Expression(Box::new(HelperTemplate::with_path(Path::with_named_paths(
-                &["world"]
-            ))))
-        );
+        let mut world = HelperTemplate::with_path(Path::with_named_paths(&["world"]));
+        world.whitespace.open = (true, true);
+        assert_eq!(t.elements[1], Expression(Box::new(world)));
         assert_eq!(t.elements[2], RawString("!".to_string()));
+        match t.elements[3] {
+            HelperBlock(ref h) => {
+                assert_eq!(h.whitespace.open, (true, false));
+                assert_eq!(h.whitespace.close, (false, true));
+            }
+            _ => unreachable!(),
+        }
 
         let t2 = Template::compile("{{#if true}}1  {{~ else ~}} 2 {{~/if}}")
             .ok()
@@ -1053,6 +1250,7 @@ mod test {
                     h.inverse.as_ref().unwrap().elements[0],
                     RawString("2".to_string())
                 );
+                assert_eq!(h.whitespace.inverse, (true, true));
             }
             _ => unreachable!(),
         }
@@ -1253,6 +1451,6 @@ mod test {
         let s = "{{#>(X)}}{{/X}}";
         let result = Template::compile(s);
         assert!(result.is_err());
-        assert_eq!("decorator \"Subexpression(Subexpression { element: Expression(HelperTemplate { name: Path(Relative(([Named(\\\"X\\\")], \\\"X\\\"))), params: [], hash: {}, block_param: None, template: None, inverse: None, block: false }) })\" was opened, but \"X\" is closing", format!("{}", result.unwrap_err().reason));
+        assert_eq!("decorator \"Subexpression(Subexpression { element: Expression(HelperTemplate { name: Path(Relative(([Named(\\\"X\\\")], \\\"X\\\"))), params: [], hash: {}, block_param: None, template: None, inverse: None, block: false, raw: false, whitespace: WhitespaceControl { open: (false, false), inverse: (false, false), close: (false, false) } }) })\" was opened, but \"X\" is closing", format!("{}", result.unwrap_err().reason));
     }
 }
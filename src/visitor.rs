@@ -0,0 +1,436 @@
+//! Traversal of the template AST
+//!
+//! `Visitor` walks a `Template` by reference and `VisitorMut` by mutable
+//! reference. Every method has a default implementation that visits the
+//! children of the node, through the `walk_*` functions of this module. To
+//! hook into a node kind, override its method and call the matching
+//! `walk_*` function from it if you still want its children visited.
+//!
+//! Block bodies, inverse branches, params, hash values, block params and
+//! subexpressions are all visited. Hash entries are visited in the order of
+//! their keys.
+//!
+//! ```
+//! use handlebars::template::{HelperTemplate, Template};
+//! use handlebars::visitor::{walk_helper_template, Visitor};
+//!
+//! #[derive(Default)]
+//! struct HelperNames(Vec<String>);
+//!
+//! impl<'ast> Visitor<'ast> for HelperNames {
+//!     fn visit_helper_block(&mut self, h: &'ast HelperTemplate) {
+//!         self.0.extend(h.name.as_name().map(|s| s.to_owned()));
+//!         walk_helper_template(self, h);
+//!     }
+//! }
+//!
+//! let t = Template::compile("{{#if a}}{{#each b}}{{this}}{{/each}}{{/if}}").unwrap();
+//! let mut names = HelperNames::default();
+//! names.visit_template(&t);
+//! assert_eq!(names.0, vec!["if", "each"]);
+//! ```
+
+use std::collections::HashMap;
+
+use serde_json::value::Value as Json;
+
+use crate::json::path::Path;
+use crate::template::{
+    BlockParam, DecoratorTemplate, HelperTemplate, Parameter, Subexpression, Template,
+    TemplateElement, TemplateMapping,
+};
+
+/// Read-only traversal of a template
+pub trait Visitor<'ast> {
+    fn visit_template(&mut self, t: &'ast Template) {
+        walk_template(self, t)
+    }
+
+    /// Visit an element, with the line and column it starts at, if known
+    fn visit_element(&mut self, e: &'ast TemplateElement, _mapping: Option<&'ast TemplateMapping>) {
+        walk_element(self, e)
+    }
+
+    fn visit_raw_string(&mut self, _s: &'ast str) {}
+
+    fn visit_comment(&mut self, _s: &'ast str) {}
+
+    /// Visit an escaped expression `{{...}}`
+    fn visit_expression(&mut self, h: &'ast HelperTemplate) {
+        walk_helper_template(self, h)
+    }
+
+    /// Visit an unescaped expression `{{{...}}}`
+    fn visit_html_expression(&mut self, h: &'ast HelperTemplate) {
+        walk_helper_template(self, h)
+    }
+
+    fn visit_helper_block(&mut self, h: &'ast HelperTemplate) {
+        walk_helper_template(self, h)
+    }
+
+    fn visit_decorator(&mut self, d: &'ast DecoratorTemplate) {
+        walk_decorator_template(self, d)
+    }
+
+    fn visit_decorator_block(&mut self, d: &'ast DecoratorTemplate) {
+        walk_decorator_template(self, d)
+    }
+
+    fn visit_partial(&mut self, d: &'ast DecoratorTemplate) {
+        walk_decorator_template(self, d)
+    }
+
+    fn visit_partial_block(&mut self, d: &'ast DecoratorTemplate) {
+        walk_decorator_template(self, d)
+    }
+
+    fn visit_parameter(&mut self, p: &'ast Parameter) {
+        walk_parameter(self, p)
+    }
+
+    fn visit_name(&mut self, _name: &'ast str) {}
+
+    fn visit_path(&mut self, _path: &'ast Path) {}
+
+    fn visit_literal(&mut self, _value: &'ast Json) {}
+
+    fn visit_subexpression(&mut self, s: &'ast Subexpression) {
+        walk_subexpression(self, s)
+    }
+
+    fn visit_hash_entry(&mut self, _key: &'ast str, value: &'ast Parameter) {
+        self.visit_parameter(value)
+    }
+
+    fn visit_block_param(&mut self, bp: &'ast BlockParam) {
+        walk_block_param(self, bp)
+    }
+}
+
+fn sorted_keys<V>(hash: &HashMap<String, V>) -> Vec<&String> {
+    let mut keys: Vec<&String> = hash.keys().collect();
+    keys.sort();
+    keys
+}
+
+pub fn walk_template<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, t: &'ast Template) {
+    for (idx, e) in t.elements.iter().enumerate() {
+        v.visit_element(e, t.mapping.get(idx));
+    }
+}
+
+pub fn walk_element<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, e: &'ast TemplateElement) {
+    match e {
+        TemplateElement::RawString(s) => v.visit_raw_string(s),
+        TemplateElement::Comment(s) => v.visit_comment(s),
+        TemplateElement::Expression(h) => v.visit_expression(h),
+        TemplateElement::HtmlExpression(h) => v.visit_html_expression(h),
+        TemplateElement::HelperBlock(h) => v.visit_helper_block(h),
+        TemplateElement::DecoratorExpression(d) => v.visit_decorator(d),
+        TemplateElement::DecoratorBlock(d) => v.visit_decorator_block(d),
+        TemplateElement::PartialExpression(d) => v.visit_partial(d),
+        TemplateElement::PartialBlock(d) => v.visit_partial_block(d),
+    }
+}
+
+/// Visit name, params, hash, block params, body and inverse of a helper
+pub fn walk_helper_template<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, h: &'ast HelperTemplate) {
+    v.visit_parameter(&h.name);
+    for p in &h.params {
+        v.visit_parameter(p);
+    }
+    for k in sorted_keys(&h.hash) {
+        v.visit_hash_entry(k, &h.hash[k]);
+    }
+    if let Some(ref bp) = h.block_param {
+        v.visit_block_param(bp);
+    }
+    if let Some(ref t) = h.template {
+        v.visit_template(t);
+    }
+    if let Some(ref t) = h.inverse {
+        v.visit_template(t);
+    }
+}
+
+/// Visit name, params, hash and body of a decorator or partial
+pub fn walk_decorator_template<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    d: &'ast DecoratorTemplate,
+) {
+    v.visit_parameter(&d.name);
+    for p in &d.params {
+        v.visit_parameter(p);
+    }
+    for k in sorted_keys(&d.hash) {
+        v.visit_hash_entry(k, &d.hash[k]);
+    }
+    if let Some(ref t) = d.template {
+        v.visit_template(t);
+    }
+}
+
+pub fn walk_parameter<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, p: &'ast Parameter) {
+    match p {
+        Parameter::Name(n) => v.visit_name(n),
+        Parameter::Path(path) => v.visit_path(path),
+        Parameter::Literal(value) => v.visit_literal(value),
+        Parameter::Subexpression(s) => v.visit_subexpression(s),
+    }
+}
+
+/// Visit the helper call of a subexpression, like an expression
+pub fn walk_subexpression<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, s: &'ast Subexpression) {
+    match s.as_element() {
+        TemplateElement::Expression(h) => walk_helper_template(v, h),
+        e => v.visit_element(e, None),
+    }
+}
+
+pub fn walk_block_param<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, bp: &'ast BlockParam) {
+    match bp {
+        BlockParam::Single(p) => v.visit_parameter(p),
+        BlockParam::Pair((p1, p2)) => {
+            v.visit_parameter(p1);
+            v.visit_parameter(p2);
+        }
+    }
+}
+
+/// Mutable traversal of a template, for rewriting it in place
+pub trait VisitorMut {
+    fn visit_template_mut(&mut self, t: &mut Template) {
+        walk_template_mut(self, t)
+    }
+
+    /// Visit an element, with the line and column it starts at, if known
+    fn visit_element_mut(&mut self, e: &mut TemplateElement, _mapping: Option<&TemplateMapping>) {
+        walk_element_mut(self, e)
+    }
+
+    fn visit_raw_string_mut(&mut self, _s: &mut String) {}
+
+    fn visit_comment_mut(&mut self, _s: &mut String) {}
+
+    /// Visit an escaped expression `{{...}}`
+    fn visit_expression_mut(&mut self, h: &mut HelperTemplate) {
+        walk_helper_template_mut(self, h)
+    }
+
+    /// Visit an unescaped expression `{{{...}}}`
+    fn visit_html_expression_mut(&mut self, h: &mut HelperTemplate) {
+        walk_helper_template_mut(self, h)
+    }
+
+    fn visit_helper_block_mut(&mut self, h: &mut HelperTemplate) {
+        walk_helper_template_mut(self, h)
+    }
+
+    fn visit_decorator_mut(&mut self, d: &mut DecoratorTemplate) {
+        walk_decorator_template_mut(self, d)
+    }
+
+    fn visit_decorator_block_mut(&mut self, d: &mut DecoratorTemplate) {
+        walk_decorator_template_mut(self, d)
+    }
+
+    fn visit_partial_mut(&mut self, d: &mut DecoratorTemplate) {
+        walk_decorator_template_mut(self, d)
+    }
+
+    fn visit_partial_block_mut(&mut self, d: &mut DecoratorTemplate) {
+        walk_decorator_template_mut(self, d)
+    }
+
+    fn visit_parameter_mut(&mut self, p: &mut Parameter) {
+        walk_parameter_mut(self, p)
+    }
+
+    fn visit_name_mut(&mut self, _name: &mut String) {}
+
+    fn visit_path_mut(&mut self, _path: &mut Path) {}
+
+    fn visit_literal_mut(&mut self, _value: &mut Json) {}
+
+    fn visit_subexpression_mut(&mut self, s: &mut Subexpression) {
+        walk_subexpression_mut(self, s)
+    }
+
+    fn visit_hash_entry_mut(&mut self, _key: &str, value: &mut Parameter) {
+        self.visit_parameter_mut(value)
+    }
+
+    fn visit_block_param_mut(&mut self, bp: &mut BlockParam) {
+        walk_block_param_mut(self, bp)
+    }
+}
+
+fn walk_hash_mut<V: VisitorMut + ?Sized>(v: &mut V, hash: &mut HashMap<String, Parameter>) {
+    let keys: Vec<String> = sorted_keys(hash).into_iter().cloned().collect();
+    for k in keys {
+        if let Some(value) = hash.get_mut(&k) {
+            v.visit_hash_entry_mut(&k, value);
+        }
+    }
+}
+
+pub fn walk_template_mut<V: VisitorMut + ?Sized>(v: &mut V, t: &mut Template) {
+    let mapping = &t.mapping;
+    for (idx, e) in t.elements.iter_mut().enumerate() {
+        v.visit_element_mut(e, mapping.get(idx));
+    }
+}
+
+pub fn walk_element_mut<V: VisitorMut + ?Sized>(v: &mut V, e: &mut TemplateElement) {
+    match e {
+        TemplateElement::RawString(s) => v.visit_raw_string_mut(s),
+        TemplateElement::Comment(s) => v.visit_comment_mut(s),
+        TemplateElement::Expression(h) => v.visit_expression_mut(h),
+        TemplateElement::HtmlExpression(h) => v.visit_html_expression_mut(h),
+        TemplateElement::HelperBlock(h) => v.visit_helper_block_mut(h),
+        TemplateElement::DecoratorExpression(d) => v.visit_decorator_mut(d),
+        TemplateElement::DecoratorBlock(d) => v.visit_decorator_block_mut(d),
+        TemplateElement::PartialExpression(d) => v.visit_partial_mut(d),
+        TemplateElement::PartialBlock(d) => v.visit_partial_block_mut(d),
+    }
+}
+
+pub fn walk_helper_template_mut<V: VisitorMut + ?Sized>(v: &mut V, h: &mut HelperTemplate) {
+    v.visit_parameter_mut(&mut h.name);
+    for p in h.params.iter_mut() {
+        v.visit_parameter_mut(p);
+    }
+    walk_hash_mut(v, &mut h.hash);
+    if let Some(ref mut bp) = h.block_param {
+        v.visit_block_param_mut(bp);
+    }
+    if let Some(ref mut t) = h.template {
+        v.visit_template_mut(t);
+    }
+    if let Some(ref mut t) = h.inverse {
+        v.visit_template_mut(t);
+    }
+}
+
+pub fn walk_decorator_template_mut<V: VisitorMut + ?Sized>(v: &mut V, d: &mut DecoratorTemplate) {
+    v.visit_parameter_mut(&mut d.name);
+    for p in d.params.iter_mut() {
+        v.visit_parameter_mut(p);
+    }
+    walk_hash_mut(v, &mut d.hash);
+    if let Some(ref mut t) = d.template {
+        v.visit_template_mut(t);
+    }
+}
+
+pub fn walk_parameter_mut<V: VisitorMut + ?Sized>(v: &mut V, p: &mut Parameter) {
+    match p {
+        Parameter::Name(n) => v.visit_name_mut(n),
+        Parameter::Path(path) => v.visit_path_mut(path),
+        Parameter::Literal(value) => v.visit_literal_mut(value),
+        Parameter::Subexpression(s) => v.visit_subexpression_mut(s),
+    }
+}
+
+pub fn walk_subexpression_mut<V: VisitorMut + ?Sized>(v: &mut V, s: &mut Subexpression) {
+    match s.element.as_mut() {
+        TemplateElement::Expression(h) => walk_helper_template_mut(v, h),
+        e => v.visit_element_mut(e, None),
+    }
+}
+
+pub fn walk_block_param_mut<V: VisitorMut + ?Sized>(v: &mut V, bp: &mut BlockParam) {
+    match bp {
+        BlockParam::Single(p) => v.visit_parameter_mut(p),
+        BlockParam::Pair((p1, p2)) => {
+            v.visit_parameter_mut(p1);
+            v.visit_parameter_mut(p2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registry::Registry;
+
+    #[derive(Default)]
+    struct Collector {
+        names: Vec<String>,
+        paths: Vec<String>,
+        literals: Vec<Json>,
+        positions: Vec<(usize, usize)>,
+    }
+
+    impl<'ast> Visitor<'ast> for Collector {
+        fn visit_element(
+            &mut self,
+            e: &'ast TemplateElement,
+            mapping: Option<&'ast TemplateMapping>,
+        ) {
+            if let Some(TemplateMapping(line, col)) = mapping {
+                if !matches!(e, TemplateElement::RawString(_)) {
+                    self.positions.push((*line, *col));
+                }
+            }
+            walk_element(self, e);
+        }
+
+        fn visit_name(&mut self, name: &'ast str) {
+            self.names.push(name.to_owned());
+        }
+
+        fn visit_path(&mut self, path: &'ast Path) {
+            self.paths.push(path.raw().to_owned());
+        }
+
+        fn visit_literal(&mut self, value: &'ast Json) {
+            self.literals.push(value.clone());
+        }
+    }
+
+    #[test]
+    fn test_visit_all_nodes() {
+        let t = Template::compile(
+            "{{#each (filter items kind=\"a\") as |item i|}}\n\
+             {{lookup item (concat \"na\" \"me\")}}{{else}}{{{empty}}}{{/each}}\n\
+             {{#> layout title=(upper name)}}{{*inline \"x\"}}{{/layout}}",
+        )
+        .unwrap();
+
+        let mut c = Collector::default();
+        c.visit_template(&t);
+
+        assert_eq!(c.paths, vec!["items", "item", "empty", "name"]);
+        assert_eq!(
+            c.names,
+            vec!["each", "filter", "item", "i", "lookup", "concat", "layout", "upper", "inline"]
+        );
+        assert_eq!(
+            c.literals,
+            vec![json!("a"), json!("na"), json!("me"), json!("x")]
+        );
+        assert_eq!(c.positions, vec![(1, 1), (2, 1), (2, 43), (3, 1), (3, 33)]);
+    }
+
+    struct Rename;
+
+    impl VisitorMut for Rename {
+        fn visit_path_mut(&mut self, path: &mut Path) {
+            if path.raw() == "name" {
+                *path = Path::parse("title").unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_visit_mut() {
+        let mut t = Template::compile("{{name}} {{#if (eq name \"x\")}}{{name}}{{/if}}").unwrap();
+        Rename.visit_template_mut(&mut t);
+
+        let mut r = Registry::new();
+        r.register_template("t", t);
+        assert_eq!(r.render("t", &json!({"title": "x"})).unwrap(), "x x");
+    }
+}
@@ -0,0 +1,536 @@
+use std::collections::hash_map::DefaultHasher;
+#[cfg(feature = "dir_source")]
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Error as IOError;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, RwLockWriteGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[cfg(feature = "script_helper")]
+use crate::error::ScriptError;
+use crate::error::TemplateError;
+use crate::registry::Registry;
+#[cfg(feature = "dir_source")]
+use crate::sources::template_files;
+use crate::template::Template;
+
+quick_error! {
+/// Error reported by `TemplateWatcher` when reloading a file fails
+    #[derive(Debug)]
+    pub enum WatchError {
+        Template(err: TemplateError) {
+            from()
+            source(err)
+            display("{}", err)
+        }
+        #[cfg(feature = "script_helper")]
+        Script(name: String, err: ScriptError) {
+            source(err)
+            display("Script helper \"{}\": {}", name, err)
+        }
+    }
+}
+
+type ErrorCallback = Box<dyn Fn(&WatchError) + Send>;
+
+/// hash of the content of a file, `None` when it can't be read
+///
+/// Files are read on each poll, so edits keeping the size and modification
+/// time of a file are found too.
+type Stamp = Option<u64>;
+
+fn hash_source(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn stamp(source: &Result<String, IOError>) -> Stamp {
+    source.as_ref().ok().map(|s| hash_source(s))
+}
+
+#[derive(Debug)]
+enum FileKind {
+    Template,
+    #[cfg(feature = "script_helper")]
+    Script,
+}
+
+#[derive(Debug)]
+struct WatchedFile {
+    name: String,
+    path: PathBuf,
+    kind: FileKind,
+    stamp: Stamp,
+}
+
+#[cfg(feature = "dir_source")]
+#[derive(Debug)]
+struct WatchedDir {
+    tpl_extension: String,
+    path: PathBuf,
+    files: HashMap<String, Stamp>,
+}
+
+enum Update {
+    Template(String, Template),
+    Remove(String),
+    #[cfg(feature = "script_helper")]
+    Script(String, String),
+}
+
+fn compile_template(
+    name: &str,
+    source: Result<String, IOError>,
+) -> Result<Template, TemplateError> {
+    let tpl_str = source.map_err(|e| TemplateError::from((e, name.to_owned())))?;
+    Template::compile_with_name(tpl_str, name.to_owned())
+}
+
+fn write_lock<'a>(
+    registry: &'a RwLock<Registry<'static>>,
+) -> RwLockWriteGuard<'a, Registry<'static>> {
+    registry.write().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Hot reload of file based templates and script helpers
+///
+/// Unlike dev mode, which reads and compiles a template on every render, the
+/// watcher polls the files it watches for changes and only recompiles the
+/// ones that changed. Directories are rescanned as well, so templates added
+/// or removed there are registered or unregistered. All changes found in
+/// one poll are swapped into the shared registry under a single write lock.
+///
+/// A template or script that fails to load or compile doesn't replace the
+/// registered one, the error is reported to the `on_error` callback instead.
+/// So is a watched directory that can't be read, its templates are kept.
+///
+/// ```no_run
+/// use std::sync::{Arc, RwLock};
+/// use std::time::Duration;
+/// use handlebars::{Handlebars, TemplateWatcher};
+///
+/// let registry = Arc::new(RwLock::new(Handlebars::new()));
+///
+/// let mut watcher = TemplateWatcher::new(registry.clone());
+/// watcher.watch_template_file("index", "templates/index.hbs").unwrap();
+/// watcher.on_error(|e| eprintln!("{}", e));
+/// let _handle = watcher.start(Duration::from_secs(1));
+///
+/// let output = registry.read().unwrap().render("index", &()).unwrap();
+/// ```
+pub struct TemplateWatcher {
+    registry: Arc<RwLock<Registry<'static>>>,
+    files: Vec<WatchedFile>,
+    #[cfg(feature = "dir_source")]
+    dirs: Vec<WatchedDir>,
+    on_error: Option<ErrorCallback>,
+}
+
+impl TemplateWatcher {
+    pub fn new(registry: Arc<RwLock<Registry<'static>>>) -> TemplateWatcher {
+        TemplateWatcher {
+            registry,
+            files: Vec::new(),
+            #[cfg(feature = "dir_source")]
+            dirs: Vec::new(),
+            on_error: None,
+        }
+    }
+
+    /// Set the callback for errors found when reloading
+    ///
+    /// Errors are ignored when no callback is set.
+    pub fn on_error<F>(&mut self, f: F)
+    where
+        F: Fn(&WatchError) + Send + 'static,
+    {
+        self.on_error = Some(Box::new(f));
+    }
+
+    /// Register a template file and watch it for changes
+    ///
+    /// Like `Registry::register_template_file`, this fails if the template
+    /// can't be loaded at first place.
+    pub fn watch_template_file<P>(&mut self, name: &str, tpl_path: P) -> Result<(), TemplateError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = tpl_path.as_ref().to_owned();
+        let source = fs::read_to_string(&path);
+        let stamp = stamp(&source);
+        let tpl = compile_template(name, source)?;
+        write_lock(&self.registry).register_template(name, tpl);
+
+        self.files.push(WatchedFile {
+            name: name.to_owned(),
+            path,
+            kind: FileKind::Template,
+            stamp,
+        });
+        Ok(())
+    }
+
+    /// Register templates from a directory and watch it for changes
+    ///
+    /// Templates are named like `Registry::register_templates_directory`
+    /// does. Files added to the directory later are registered, and
+    /// templates whose file is removed are unregistered. Unlike
+    /// `Registry::register_templates_directory`, this fails if the directory
+    /// can't be read.
+    #[cfg(feature = "dir_source")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "dir_source")))]
+    pub fn watch_templates_directory<P>(
+        &mut self,
+        tpl_extension: &str,
+        dir_path: P,
+    ) -> Result<(), TemplateError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = dir_path.as_ref().to_owned();
+
+        let mut templates = Vec::new();
+        let mut files = HashMap::new();
+        for (name, tpl_path) in template_files(tpl_extension, &path)? {
+            let source = fs::read_to_string(&tpl_path);
+            files.insert(name.clone(), stamp(&source));
+            templates.push((name.clone(), compile_template(&name, source)?));
+        }
+
+        let mut registry = write_lock(&self.registry);
+        for (name, tpl) in templates {
+            registry.register_template(&name, tpl);
+        }
+        drop(registry);
+
+        self.dirs.push(WatchedDir {
+            tpl_extension: tpl_extension.to_owned(),
+            path,
+            files,
+        });
+        Ok(())
+    }
+
+    /// Register a rhai script helper file and watch it for changes
+    #[cfg(feature = "script_helper")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "script_helper")))]
+    pub fn watch_script_helper_file<P>(
+        &mut self,
+        name: &str,
+        script_path: P,
+    ) -> Result<(), ScriptError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = script_path.as_ref().to_owned();
+        let script = fs::read_to_string(&path)?;
+        let stamp = Some(hash_source(&script));
+        write_lock(&self.registry).register_script_helper(name, &script)?;
+
+        self.files.push(WatchedFile {
+            name: name.to_owned(),
+            path,
+            kind: FileKind::Script,
+            stamp,
+        });
+        Ok(())
+    }
+
+    fn report(&self, e: WatchError) {
+        if let Some(ref f) = self.on_error {
+            f(&e);
+        }
+    }
+
+    fn load_file(
+        file: &WatchedFile,
+        source: Result<String, IOError>,
+    ) -> Result<Update, WatchError> {
+        match file.kind {
+            FileKind::Template => compile_template(&file.name, source)
+                .map(|tpl| Update::Template(file.name.clone(), tpl))
+                .map_err(WatchError::from),
+            #[cfg(feature = "script_helper")]
+            FileKind::Script => source
+                .map(|s| Update::Script(file.name.clone(), s))
+                .map_err(|e| WatchError::Script(file.name.clone(), e.into())),
+        }
+    }
+
+    /// Check watched files once and reload the changed ones
+    ///
+    /// Returns the number of templates and scripts updated or removed in the
+    /// registry. `start` calls this periodically, call it directly if you
+    /// drive reloading yourself.
+    pub fn poll(&mut self) -> usize {
+        let mut updates = Vec::new();
+        let mut errors = Vec::new();
+
+        for file in self.files.iter_mut() {
+            let source = fs::read_to_string(&file.path);
+            let new_stamp = stamp(&source);
+            if new_stamp == file.stamp {
+                continue;
+            }
+            file.stamp = new_stamp;
+
+            match TemplateWatcher::load_file(file, source) {
+                Ok(u) => updates.push(u),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        #[cfg(feature = "dir_source")]
+        for dir in self.dirs.iter_mut() {
+            // the templates of a directory that can't be read are kept
+            let found = match template_files(&dir.tpl_extension, &dir.path) {
+                Ok(found) => found,
+                Err(e) => {
+                    errors.push(e.into());
+                    continue;
+                }
+            };
+
+            let mut files = HashMap::with_capacity(found.len());
+            for (name, tpl_path) in found {
+                let source = fs::read_to_string(&tpl_path);
+                let new_stamp = stamp(&source);
+                if dir.files.get(&name) != Some(&new_stamp) {
+                    match compile_template(&name, source) {
+                        Ok(tpl) => updates.push(Update::Template(name.clone(), tpl)),
+                        Err(e) => errors.push(e.into()),
+                    }
+                }
+                files.insert(name, new_stamp);
+            }
+            for name in dir.files.keys() {
+                if !files.contains_key(name) {
+                    updates.push(Update::Remove(name.clone()));
+                }
+            }
+            dir.files = files;
+        }
+
+        let mut applied = 0;
+        if !updates.is_empty() {
+            let mut registry = write_lock(&self.registry);
+            for u in updates {
+                match u {
+                    Update::Template(name, tpl) => registry.register_template(&name, tpl),
+                    Update::Remove(name) => registry.unregister_template(&name),
+                    #[cfg(feature = "script_helper")]
+                    Update::Script(name, script) => {
+                        if let Err(e) = registry.register_script_helper(&name, &script) {
+                            errors.push(WatchError::Script(name, e));
+                            continue;
+                        }
+                    }
+                }
+                applied += 1;
+            }
+        }
+
+        for e in errors {
+            self.report(e);
+        }
+        applied
+    }
+
+    /// Poll for changes every `interval` on a background thread
+    ///
+    /// The thread stops when the returned handle is dropped.
+    pub fn start(mut self, interval: Duration) -> WatcherHandle {
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let thread = thread::spawn(move || loop {
+            thread::park_timeout(interval);
+            if !thread_running.load(Ordering::SeqCst) {
+                break;
+            }
+            self.poll();
+        });
+
+        WatcherHandle {
+            running,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Handle of a running `TemplateWatcher`, which stops it on drop
+#[derive(Debug)]
+pub struct WatcherHandle {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WatcherHandle {
+    /// Stop the watcher thread and wait for it to finish
+    ///
+    /// A poll in progress is completed first.
+    pub fn stop(mut self) {
+        self.join();
+    }
+
+    fn join(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for WatcherHandle {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TemplateWatcher, WatchError};
+    use crate::registry::Registry;
+    use std::fs::File;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex, RwLock};
+    use tempfile::tempdir;
+
+    fn write_file(path: &std::path::Path, content: &str) {
+        let mut f = File::create(path).unwrap();
+        write!(f, "{}", content).unwrap();
+    }
+
+    #[test]
+    fn test_watch_template_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("t1.hbs");
+        write_file(&path, "<h1>Hello {{name}}!</h1>");
+
+        let registry = Arc::new(RwLock::new(Registry::new()));
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let errors2 = errors.clone();
+
+        let mut watcher = TemplateWatcher::new(registry.clone());
+        watcher.on_error(move |e: &WatchError| errors2.lock().unwrap().push(e.to_string()));
+        watcher.watch_template_file("t1", &path).unwrap();
+
+        let data = json!({"name": "Alex"});
+        let render = || registry.read().unwrap().render("t1", &data).unwrap();
+        assert_eq!(render(), "<h1>Hello Alex!</h1>");
+        assert_eq!(watcher.poll(), 0);
+
+        write_file(&path, "<h1>Privet {{name}}!!</h1>");
+        assert_eq!(watcher.poll(), 1);
+        assert_eq!(render(), "<h1>Privet Alex!!</h1>");
+
+        // an edit keeping the size is found too
+        write_file(&path, "<h1>Zdravo {{name}}!!</h1>");
+        assert_eq!(watcher.poll(), 1);
+        assert_eq!(render(), "<h1>Zdravo Alex!!</h1>");
+
+        // a broken template is reported and the last good one kept
+        write_file(&path, "<h1>{{#if}}</h1>");
+        assert_eq!(watcher.poll(), 0);
+        assert_eq!(render(), "<h1>Zdravo Alex!!</h1>");
+        assert_eq!(errors.lock().unwrap().len(), 1);
+        assert_eq!(watcher.poll(), 0);
+        assert_eq!(errors.lock().unwrap().len(), 1);
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "dir_source")]
+    fn test_watch_templates_directory() {
+        let dir = tempdir().unwrap();
+        write_file(&dir.path().join("t1.hbs"), "one");
+
+        let registry = Arc::new(RwLock::new(Registry::new()));
+        let mut watcher = TemplateWatcher::new(registry.clone());
+        watcher
+            .watch_templates_directory(".hbs", dir.path())
+            .unwrap();
+        assert!(registry.read().unwrap().has_template("t1"));
+
+        write_file(&dir.path().join("t2.hbs"), "two");
+        std::fs::remove_file(dir.path().join("t1.hbs")).unwrap();
+        assert_eq!(watcher.poll(), 2);
+
+        {
+            let registry = registry.read().unwrap();
+            assert!(!registry.has_template("t1"));
+            assert_eq!(registry.render("t2", &()).unwrap(), "two");
+        }
+
+        // a directory that can't be read is reported, its templates kept
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let errors2 = errors.clone();
+        watcher.on_error(move |e: &WatchError| errors2.lock().unwrap().push(e.to_string()));
+        let path = dir.path().to_owned();
+        dir.close().unwrap();
+        assert_eq!(watcher.poll(), 0);
+        assert_eq!(errors.lock().unwrap().len(), 1);
+        assert!(registry.read().unwrap().has_template("t2"));
+
+        let mut watcher = TemplateWatcher::new(registry);
+        assert!(watcher.watch_templates_directory(".hbs", &path).is_err());
+    }
+
+    #[test]
+    fn test_watcher_stop() {
+        use std::time::Duration;
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("t1.hbs");
+        write_file(&path, "one");
+
+        let registry = Arc::new(RwLock::new(Registry::new()));
+        let mut watcher = TemplateWatcher::new(registry.clone());
+        watcher.watch_template_file("t1", &path).unwrap();
+        let handle = watcher.start(Duration::from_millis(10));
+        handle.stop();
+
+        // the thread is joined, nothing is reloaded after `stop`
+        write_file(&path, "two");
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(registry.read().unwrap().render("t1", &()).unwrap(), "one");
+        // the watcher owned by the thread is dropped with it
+        assert_eq!(Arc::strong_count(&registry), 1);
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "script_helper")]
+    fn test_watch_script_helper_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("acc.rhai");
+        write_file(&path, "params.reduce(|sum, x| x + sum, 0)");
+
+        let registry = Arc::new(RwLock::new(Registry::new()));
+        let mut watcher = TemplateWatcher::new(registry.clone());
+        watcher.watch_script_helper_file("acc", &path).unwrap();
+
+        let render = || {
+            registry
+                .read()
+                .unwrap()
+                .render_template("{{acc 1 2 3 4}}", &())
+                .unwrap()
+        };
+        assert_eq!(render(), "10");
+
+        // a changed script is reloaded
+        write_file(&path, "params.reduce(|product, x| x * product, 1)");
+        assert_eq!(watcher.poll(), 1);
+        assert_eq!(render(), "24");
+
+        dir.close().unwrap();
+    }
+}
@@ -0,0 +1,263 @@
+#![cfg(feature = "async")]
+
+#[macro_use]
+extern crate serde_json;
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use handlebars::{
+    AsyncWriteOutput, Context, Handlebars, JsonValue, LimitError, RenderError, RenderLimits,
+};
+
+fn registry() -> Handlebars<'static> {
+    let mut hbs = Handlebars::new();
+    hbs.register_async_helper(
+        "users",
+        Box::new(|_, _| async {
+            tokio::task::yield_now().await;
+            Ok(json!([{"id": 1}, {"id": 2}]))
+        }),
+    );
+    hbs.register_async_helper(
+        "name",
+        Box::new(
+            |params: Vec<JsonValue>, hash: BTreeMap<String, JsonValue>| async move {
+                let id = params[0].as_i64().unwrap();
+                let prefix = hash.get("prefix").and_then(|v| v.as_str()).unwrap_or("");
+                Ok(json!(format!("{}user<{}>", prefix, id)))
+            },
+        ),
+    );
+    hbs
+}
+
+#[tokio::test]
+async fn test_async_helper() {
+    let mut hbs = registry();
+    hbs.register_template_string("item", "[{{name id prefix=\"@\"}}]")
+        .unwrap();
+    hbs.register_template_string(
+        "list",
+        "{{#each (users)}}{{#if @first}}first {{/if}}{{> item}}{{/each}} {{{name 3}}}",
+    )
+    .unwrap();
+
+    assert_eq!(
+        hbs.render_async("list", &()).await.unwrap(),
+        "first [@user&lt;1&gt;][@user&lt;2&gt;] user<3>"
+    );
+    assert_eq!(
+        hbs.render_template_async("{{len (users)}} {{name (len (users))}}", &())
+            .await
+            .unwrap(),
+        "2 user&lt;2&gt;"
+    );
+
+    let mut buf = Vec::new();
+    hbs.render_to_async_write("item", &json!({"id": 4}), &mut buf)
+        .await
+        .unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), "[@user&lt;4&gt;]");
+}
+
+#[tokio::test]
+async fn test_async_helper_calls() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+
+    let mut hbs = Handlebars::new();
+    hbs.register_async_helper(
+        "count",
+        Box::new(move |_, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok(json!(1)) }
+        }),
+    );
+
+    // identical calls are awaited once per render
+    assert_eq!(
+        hbs.render_template_async("{{count}}{{count}}", &())
+            .await
+            .unwrap(),
+        "11"
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_async_helper_branches() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+
+    let mut hbs = registry();
+    hbs.register_async_helper("cached", Box::new(|_, _| async { Ok(json!(true)) }));
+    hbs.register_async_helper(
+        "fetch",
+        Box::new(move |_, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok(json!("fetched")) }
+        }),
+    );
+
+    // `fetch` is not called on the branch taken while `cached` is pending
+    assert_eq!(
+        hbs.render_template_async("{{#if (cached)}}hit{{else}}{{fetch}}{{/if}}", &())
+            .await
+            .unwrap(),
+        "hit"
+    );
+    assert_eq!(
+        hbs.render_template_async("{{#unless (cached)}}{{fetch}}{{/unless}}", &())
+            .await
+            .unwrap(),
+        ""
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    assert_eq!(
+        hbs.render_template_async("{{#if (cached)}}{{fetch}}{{/if}}", &())
+            .await
+            .unwrap(),
+        "fetched"
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_async_helper_limits() {
+    let mut hbs = registry();
+    hbs.set_render_limits(RenderLimits {
+        max_iterations: Some(3),
+        ..Default::default()
+    });
+
+    // the iterations of the passes waiting for `name` add up
+    let e = hbs
+        .render_template_async("{{#each (users)}}{{name id}}{{/each}}", &())
+        .await
+        .unwrap_err();
+    assert_eq!(e.limit_exceeded(), Some(&LimitError::Iterations(3)));
+    assert_eq!(
+        hbs.render_template_async("{{#each (users)}}{{id}}{{/each}}", &())
+            .await
+            .unwrap(),
+        "12"
+    );
+}
+
+#[tokio::test]
+async fn test_async_output() {
+    let mut hbs = registry();
+    hbs.register_template_string("t", "{{name 1}}").unwrap();
+
+    let mut buf = Vec::new();
+    let mut output = AsyncWriteOutput::new(&mut buf);
+    let ctx = Context::null();
+    hbs.render_with_context_to_async_output("t", &ctx, &mut output)
+        .await
+        .unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), "user&lt;1&gt;");
+}
+
+#[tokio::test]
+async fn test_async_helper_error() {
+    let mut hbs = registry();
+    hbs.register_async_helper(
+        "fail",
+        Box::new(|_, _| async { Err(RenderError::new("unavailable")) }),
+    );
+
+    let e = hbs
+        .render_template_async("{{fail}}", &())
+        .await
+        .unwrap_err();
+    assert_eq!(e.desc, "unavailable");
+
+    // async helpers are not available to sync rendering
+    assert!(hbs.render_template("{{name 1}}", &()).is_err());
+    // sync rendering without async helpers is unchanged
+    assert_eq!(hbs.render_template("{{len \"abc\"}}", &()).unwrap(), "3");
+}
+
+#[tokio::test]
+async fn test_render_async_spawn() {
+    let mut hbs = registry();
+    hbs.register_template_string("t", "{{name 1}}").unwrap();
+    let hbs = Arc::new(hbs);
+
+    let output = tokio::spawn(async move {
+        let mut buf = Vec::new();
+        hbs.render_to_async_write("t", &(), &mut buf).await?;
+        Ok::<_, RenderError>((hbs.render_async("t", &()).await?, buf))
+    })
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(output.0, "user&lt;1&gt;");
+    assert_eq!(output.1, b"user&lt;1&gt;");
+}
+
+#[tokio::test]
+async fn test_async_helper_with_iter() {
+    let mut hbs = registry();
+    hbs.register_template_string(
+        "t",
+        "{{#if rows}}{{#each rows}}{{name this}} {{/each}}{{/if}}{{#unless empty}}none{{/unless}}",
+    )
+    .unwrap();
+
+    let mut ctx = Context::wraps(json!({})).unwrap();
+    ctx.register_iter("rows", vec![1, 2]).unwrap();
+    ctx.register_iter("empty", Vec::<u8>::new()).unwrap();
+
+    // items consumed by the passes waiting for `name` are replayed
+    assert_eq!(
+        hbs.render_with_context_async("t", &ctx).await.unwrap(),
+        "user&lt;1&gt; user&lt;2&gt; none"
+    );
+    assert!(hbs.render_with_context_async("t", &ctx).await.is_err());
+}
+
+/// Counts the records of the `log` helper in `test_async_helper_log`
+#[cfg(not(feature = "no_logging"))]
+struct CountingLogger;
+
+#[cfg(not(feature = "no_logging"))]
+static LOGGED: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(not(feature = "no_logging"))]
+impl log::Log for CountingLogger {
+    fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if record.args().to_string().starts_with("async log") {
+            LOGGED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(not(feature = "no_logging"))]
+#[tokio::test]
+async fn test_async_helper_log() {
+    static LOGGER: CountingLogger = CountingLogger;
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+
+    let hbs = registry();
+    // three passes: `users`, then `name` for each user
+    assert_eq!(
+        hbs.render_template_async(
+            "{{log \"async log\"}}{{#each (users)}}{{name id}}{{/each}}",
+            &()
+        )
+        .await
+        .unwrap(),
+        "user&lt;1&gt;user&lt;2&gt;"
+    );
+    assert_eq!(LOGGED.load(Ordering::SeqCst), 1);
+}
@@ -4,7 +4,7 @@ extern crate handlebars;
 extern crate serde_json;
 
 use chrono::{DateTime, FixedOffset};
-use handlebars::Handlebars;
+use handlebars::{Handlebars, RenderErrorReason};
 
 handlebars_helper!(lower: |s: str| s.to_lowercase());
 handlebars_helper!(upper: |s: str| s.to_uppercase());
@@ -86,3 +86,28 @@ fn test_macro_helper() {
         "2015-02-18"
     );
 }
+
+#[test]
+fn test_macro_helper_type_mismatch() {
+    let mut hbs = Handlebars::new();
+    hbs.register_helper("hex", Box::new(hex));
+    hbs.register_helper("money", Box::new(money));
+
+    let e = hbs.render_template("{{hex \"a\" 1}}", &()).unwrap_err();
+    assert!(matches!(
+        e.reason(),
+        RenderErrorReason::ParamValueMismatchForName(helper, name, expected, value, params)
+            if helper == "hex" && name == "v" && expected == "i64"
+                && *value == json!("a") && *params == vec![json!("a"), json!(1)]
+    ));
+    assert!(e
+        .desc
+        .ends_with("It's String(\"a\") as JSON. Got these params: [String(\"a\"), Number(1)]"));
+
+    let e = hbs.render_template("{{money 1 cur=2}}", &()).unwrap_err();
+    assert!(matches!(
+        e.reason(),
+        RenderErrorReason::HashValueMismatchForName(helper, name, _, value, _)
+            if helper == "money" && name == "cur" && *value == json!(2)
+    ));
+}
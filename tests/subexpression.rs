@@ -5,7 +5,10 @@ extern crate serde_json;
 use std::sync::atomic::{AtomicU16, Ordering};
 use std::sync::Arc;
 
-use handlebars::{Context, Handlebars, Helper, HelperDef, RenderContext, RenderError, ScopedJson};
+use handlebars::{
+    handlebars_helper, Context, Decorator, Handlebars, Helper, HelperDef, RenderContext,
+    RenderError, ScopedJson,
+};
 
 #[test]
 fn test_subexpression() {
@@ -167,3 +170,180 @@ fn test_helper_call_count() {
 
     assert_eq!(2, counter.load(Ordering::SeqCst));
 }
+
+handlebars_helper!(add: |x: i64, y: i64| x + y);
+handlebars_helper!(mul: |x: i64, y: i64| x * y);
+handlebars_helper!(len: |x: array| x.len());
+handlebars_helper!(format: |x: i64, {precision: i64 = 0}| format!("{}.{}", x, "0".repeat(precision as usize)));
+
+fn set_decorator(
+    d: &Decorator<'_, '_>,
+    _: &Handlebars<'_>,
+    ctx: &Context,
+    rc: &mut RenderContext<'_, '_>,
+) -> Result<(), RenderError> {
+    let mut new_ctx = ctx.clone();
+    if let Some(m) = new_ctx.data_mut().as_object_mut() {
+        for (k, v) in d.hash() {
+            m.insert((*k).to_owned(), v.value().clone());
+        }
+    }
+    rc.set_context(new_ctx);
+    Ok(())
+}
+
+fn nested_registry() -> Handlebars<'static> {
+    let mut hbs = Handlebars::new();
+    hbs.register_helper("add", Box::new(add));
+    hbs.register_helper("mul", Box::new(mul));
+    hbs.register_helper("len", Box::new(len));
+    hbs.register_helper("format", Box::new(format));
+    hbs.register_decorator("set", Box::new(set_decorator));
+    hbs.register_template_string("card", "[{{title}}{{> @partial-block}}]")
+        .unwrap();
+    hbs
+}
+
+#[test]
+fn test_nested_subexpression_in_params() {
+    let hbs = nested_registry();
+    let data = json!({"a": 3, "items": [1, 2], "cfg": {"p": 2}});
+
+    assert_eq!(
+        hbs.render_template(
+            "{{format (add (mul a 2) (len items)) precision=(lookup cfg \"p\")}}",
+            &data
+        )
+        .unwrap(),
+        "8.00"
+    );
+    assert_eq!(
+        hbs.render_template(
+            "{{add (add (add (add 1 1) 1) 1) (mul (mul 2 2) (add 1 (add 1 1)))}}",
+            &data
+        )
+        .unwrap(),
+        "16"
+    );
+    assert_eq!(
+        hbs.render_template("{{{format (add a (len (lookup this \"items\")))}}}", &data)
+            .unwrap(),
+        "5."
+    );
+}
+
+#[test]
+fn test_nested_subexpression_in_hash() {
+    let hbs = nested_registry();
+    let data = json!({"a": 3, "items": [1, 2], "cfg": {"p": 2}});
+
+    assert_eq!(
+        hbs.render_template("{{format 1 precision=(add (mul 0 a) (len items))}}", &data)
+            .unwrap(),
+        "1.00"
+    );
+    assert_eq!(
+        hbs.render_template(
+            "{{#with (format a precision=(add 0 (lookup cfg \"p\"))) as |s|}}{{s}}{{/with}}",
+            &data
+        )
+        .unwrap(),
+        "3.00"
+    );
+}
+
+#[test]
+fn test_nested_subexpression_in_blocks() {
+    let hbs = nested_registry();
+    let data = json!({"a": 3, "rows": {"x": [1, 2]}});
+
+    assert_eq!(
+        hbs.render_template(
+            "{{#each (lookup (lookup this \"rows\") \"x\") as |v i|}}{{add (mul v 10) i}},{{/each}}",
+            &data
+        )
+        .unwrap(),
+        "10,21,"
+    );
+    assert_eq!(
+        hbs.render_template(
+            "{{#with (add (mul a (add 1 1)) 1) as |v|}}{{v}}{{/with}}",
+            &data
+        )
+        .unwrap(),
+        "7"
+    );
+    assert_eq!(
+        hbs.render_template(
+            "{{#if (mul (add a 1) 0)}}no{{else if (add (mul a 0) (len (lookup rows \"x\")))}}yes{{/if}}",
+            &data
+        )
+        .unwrap(),
+        "yes"
+    );
+    assert_eq!(
+        hbs.render_template(
+            "{{#if (add (mul a 0) 0) includeZero=(gt (len (lookup rows \"x\")) 1)}}zero{{/if}}",
+            &data
+        )
+        .unwrap(),
+        "zero"
+    );
+}
+
+#[test]
+fn test_nested_subexpression_in_partials() {
+    let hbs = nested_registry();
+    let data = json!({"a": 3, "names": {"c": "card"}});
+
+    assert_eq!(
+        hbs.render_template("{{> card title=(add (mul a 2) (add 0 1))}}", &data)
+            .unwrap(),
+        "[7]"
+    );
+    assert_eq!(
+        hbs.render_template(
+            "{{#> card title=(format (add a 1) precision=(add 0 1))}}!{{/card}}",
+            &data
+        )
+        .unwrap(),
+        "[4.0!]"
+    );
+    assert_eq!(
+        hbs.render_template(
+            "{{> (lookup names (lookup (lookup this \"keys\") 0)) title=1}}",
+            &json!({"names": {"c": "card"}, "keys": ["c"]})
+        )
+        .unwrap(),
+        "[1]"
+    );
+}
+
+#[test]
+fn test_nested_subexpression_in_decorators() {
+    let hbs = nested_registry();
+
+    assert_eq!(
+        hbs.render_template(
+            "{{*set v=(add (mul 2 3) (len (lookup this \"items\")))}}{{v}}",
+            &json!({"items": [1]})
+        )
+        .unwrap(),
+        "7"
+    );
+}
+
+#[test]
+fn test_deeply_nested_subexpression() {
+    let hbs = nested_registry();
+
+    let mut nested = "1".to_owned();
+    for _ in 0..64 {
+        nested = format!("(add {} (mul 1 1))", nested);
+    }
+    let template = format!(
+        "{{{{add {} 0}}}} {{{{#if {}}}}}y{{{{/if}}}} {{{{> card title={}}}}}",
+        nested, nested, nested
+    );
+    assert_eq!(hbs.render_template(&template, &()).unwrap(), "65 y [65]");
+}